@@ -0,0 +1,142 @@
+//! 限速器基准：本机回环 TCP 上以 10 Gbit/s 限速传输，确认限速器本身不成为瓶颈。
+//! 走的是上传、下载处理器实际使用的路径 `Limiter::pacer(peer).consume(n)`，
+//! 全局、对端和单个传输三层上限都打开。
+//!
+//! 运行：`cargo bench --bench throttle`，`BENCH_GIB` 可调整每轮传输量（默认 4）。
+//! 达不到目标时以非零状态退出。
+
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use transport_lib::transfer::limiter::{Limiter, Pacer};
+use transport_lib::transfer::manager::Peer;
+
+/// 与下载处理器相同的分块大小。
+const CHUNK_SIZE: usize = 512 * 1024;
+/// 10 Gbit/s
+const TARGET_BYTES_PER_SEC: u64 = 10_000_000_000 / 8;
+
+fn gbit_per_sec(bytes: u64, elapsed: Duration) -> f64 {
+    bytes as f64 * 8.0 / elapsed.as_secs_f64() / 1e9
+}
+
+fn peer() -> Peer {
+    Peer {
+        addr: "127.0.0.1".to_string(),
+        device_id: Some("bench".to_string()),
+    }
+}
+
+/// 三层上限都设为 `bytes_per_sec` 的限速器。
+async fn limiter(bytes_per_sec: u64) -> Limiter {
+    let limiter = Limiter::new(bytes_per_sec);
+    limiter.set_peer_rate("bench", bytes_per_sec).await;
+    limiter.set_transfer_rate(bytes_per_sec);
+    limiter
+}
+
+/// 用 `streams` 条回环连接共发送 `total` 字节，每条连接是一个传输，每块先经过限速器。
+async fn loopback(total: u64, streams: usize, limiter: Option<Limiter>) -> Duration {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let per_stream = total / streams as u64;
+
+    let receiver = tokio::spawn(async move {
+        let mut readers = Vec::new();
+        for _ in 0..streams {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            readers.push(tokio::spawn(async move {
+                let mut buf = vec![0u8; CHUNK_SIZE];
+                let mut received = 0u64;
+                while received < per_stream {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    assert!(n > 0, "connection closed early");
+                    received += n as u64;
+                }
+            }));
+        }
+        for reader in readers {
+            reader.await.unwrap();
+        }
+    });
+
+    let start = Instant::now();
+    let senders: Vec<_> = (0..streams)
+        .map(|_| {
+            let pacer: Option<Pacer> = limiter.as_ref().map(|l| l.pacer(peer()));
+            tokio::spawn(async move {
+                let mut socket = TcpStream::connect(addr).await.unwrap();
+                socket.set_nodelay(true).unwrap();
+                let chunk = vec![0xA5u8; CHUNK_SIZE];
+                let mut sent = 0u64;
+                while sent < per_stream {
+                    let n = (per_stream - sent).min(CHUNK_SIZE as u64) as usize;
+                    if let Some(pacer) = &pacer {
+                        pacer.consume(n).await;
+                    }
+                    socket.write_all(&chunk[..n]).await.unwrap();
+                    sent += n as u64;
+                }
+            })
+        })
+        .collect();
+    for sender in senders {
+        sender.await.unwrap();
+    }
+    receiver.await.unwrap();
+    start.elapsed()
+}
+
+/// 不需要等待时每块 `consume` 的开销。
+async fn consume_overhead(calls: u32) -> Duration {
+    let pacer = limiter(u64::MAX / 2).await.pacer(peer());
+    let start = Instant::now();
+    for _ in 0..calls {
+        pacer.consume(CHUNK_SIZE).await;
+    }
+    start.elapsed() / calls
+}
+
+#[tokio::main]
+async fn main() {
+    let gib: u64 = std::env::var("BENCH_GIB")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(4);
+    let total = gib * 1024 * 1024 * 1024;
+
+    let per_call = consume_overhead(1_000_000).await;
+    println!("consume overhead: {:?}/call", per_call);
+
+    let mut failed = false;
+    for streams in [1, 8] {
+        let baseline = loopback(total, streams, None).await;
+        // 先用掉全局和对端初始的一秒额度，只测稳定速率
+        let limiter = limiter(TARGET_BYTES_PER_SEC).await;
+        limiter
+            .pacer(peer())
+            .consume(TARGET_BYTES_PER_SEC as usize)
+            .await;
+        let throttled = loopback(total, streams, Some(limiter)).await;
+
+        let baseline_rate = gbit_per_sec(total, baseline);
+        let throttled_rate = gbit_per_sec(total, throttled);
+        // 回环本身达不到 10 Gbit/s 时，以裸传输速度为准
+        let limit = TARGET_BYTES_PER_SEC as f64 * 8.0 / 1e9;
+        let expected = baseline_rate.min(limit);
+        let ok = throttled_rate >= expected * 0.95 && throttled_rate <= limit * 1.02;
+        failed |= !ok;
+        println!(
+            "{} stream(s), {} GiB: unthrottled {:.2} Gbit/s, limit 10 Gbit/s -> {:.2} Gbit/s [{}]",
+            streams,
+            gib,
+            baseline_rate,
+            throttled_rate,
+            if ok { "ok" } else { "BELOW TARGET" }
+        );
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
@@ -1,10 +1,205 @@
-/// 独立 axum 服务器，用于浏览器模式开发调试。
-/// 不启动 Tauri 窗口，编译快、启动快。
-use transport_lib::server;
-use transport_lib::transfer::throttle::Throttle;
+//! 独立 axum 服务器，不启动 Tauri 窗口：用于浏览器模式开发调试，
+//! 也可以无界面部署在 NAS 等设备上。所有参数都可以用环境变量代替。
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Parser;
+use transport_lib::discovery::Discovery;
+use transport_lib::events::EventBus;
+use transport_lib::identity::Identity;
+use transport_lib::server::auth::Pairing;
+use transport_lib::server::sandbox::SharedRoot;
+use transport_lib::server::{self, PortFallback, ServerOptions};
+use transport_lib::settings::{Overrides, SettingsStore};
+use transport_lib::transfer::limiter::Throttles;
+
+/// 命令行参数优先于配置文件，且不会写回配置文件。
+#[derive(Parser, Debug)]
+#[command(name = "transport-server", version, about = "Transport 文件传输服务")]
+struct Args {
+    /// 监听地址，默认同时监听 IPv4 和 IPv6；`0.0.0.0` 只监听 IPv4
+    #[arg(long, env = "TRANSPORT_BIND", default_value = "::")]
+    bind: IpAddr,
+
+    /// 监听端口，默认取配置文件中的值（8090）；0 表示由系统分配
+    #[arg(short, long, env = "TRANSPORT_PORT")]
+    port: Option<u16>,
+
+    /// 端口被占用时：`strict` 直接退出，`any` 由系统分配，数字 n 表示依次尝试其后 n 个端口
+    #[arg(long, env = "TRANSPORT_PORT_FALLBACK", default_value = "10")]
+    port_fallback: PortFallback,
+
+    /// 退出时最多等进行中的传输多少秒
+    #[arg(long, env = "TRANSPORT_DRAIN_TIMEOUT", default_value_t = 30)]
+    drain_timeout: u64,
+
+    /// 共享目录，可重复指定；环境变量用逗号分隔。默认取配置文件中的值
+    #[arg(long = "root", env = "TRANSPORT_ROOTS", value_delimiter = ',')]
+    roots: Vec<PathBuf>,
+
+    /// 所有共享目录只读
+    #[arg(long, env = "TRANSPORT_READ_ONLY")]
+    read_only: bool,
+
+    /// 启用 HTTPS（用设备密钥自签的证书），明文 HTTP 只把网页重定向过去
+    #[arg(long, env = "TRANSPORT_HTTPS")]
+    https: bool,
+
+    /// 上传和下载的基础限速，如 `512K`、`10M`、`1.5G`（字节/秒），0 表示不限
+    #[arg(long, env = "TRANSPORT_THROTTLE", value_parser = parse_rate)]
+    throttle: Option<u64>,
+
+    /// 配置文件路径
+    #[arg(short, long, env = "TRANSPORT_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// 日志级别：off / error / warn / info / debug / trace
+    #[arg(long, env = "TRANSPORT_LOG_LEVEL", default_value = "info")]
+    log_level: log::LevelFilter,
+
+    /// 从该目录读取前端（开发用），默认使用编译时嵌入的版本
+    #[arg(long, env = "TRANSPORT_FRONTEND_DIST")]
+    frontend_dist: Option<PathBuf>,
+}
+
+/// 解析带 K / M / G 后缀（1024 进制）的速率。
+fn parse_rate(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, unit) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c.to_ascii_uppercase()),
+        _ => (s, 'B'),
+    };
+    let multiplier: u64 = match unit {
+        'B' => 1,
+        'K' => 1 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        _ => return Err(format!("unknown unit '{}', expected K, M or G", unit)),
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid rate '{}'", s))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("invalid rate '{}'", s));
+    }
+    Ok((value * multiplier as f64) as u64)
+}
 
 #[tokio::main]
 async fn main() {
-    let throttle = Throttle::new(0);
-    server::start_server(8090, throttle).await;
+    let args = Args::parse();
+    env_logger::Builder::new()
+        .filter_level(args.log_level)
+        .init();
+
+    let overrides = Overrides {
+        port: args.port,
+        shared_roots: (!args.roots.is_empty()).then(|| {
+            args.roots
+                .into_iter()
+                .map(|path| SharedRoot {
+                    path,
+                    read_only: false,
+                })
+                .collect()
+        }),
+        read_only: args.read_only,
+        https: args.https,
+        bytes_per_sec: args.throttle,
+    };
+    let settings = SettingsStore::load(args.config.unwrap_or_else(SettingsStore::default_path))
+        .with_overrides(overrides);
+    let options = ServerOptions {
+        bind: args.bind,
+        port_fallback: args.port_fallback,
+        drain_timeout: Duration::from_secs(args.drain_timeout),
+        frontend_dist: args.frontend_dist,
+    };
+
+    let identity = match Identity::load_or_create(&Identity::default_path()) {
+        Ok(identity) => identity,
+        Err(e) => {
+            log::error!("Failed to load device identity: {}", e);
+            std::process::exit(1);
+        }
+    };
+    log::info!("Device id {} ({})", identity.id(), identity.fingerprint());
+
+    let throttles = Throttles::unlimited();
+    // 配对 PIN 直接打印在控制台
+    let pairing = Pairing::load(Pairing::default_store_path(), &identity);
+    let result = server::start_server(
+        settings,
+        options,
+        throttles,
+        Discovery::new(),
+        pairing,
+        EventBus::new(),
+        identity,
+    )
+    .await;
+    let handle = match result {
+        Ok(handle) => handle,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Ctrl-C 时等进行中的传输结束再退出，再按一次立即退出
+    if tokio::signal::ctrl_c().await.is_ok() {
+        log::info!("Shutting down, press Ctrl-C again to exit immediately");
+        tokio::select! {
+            result = handle.shutdown() => {
+                if let Err(e) = result {
+                    log::error!("{}", e);
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("0"), Ok(0));
+        assert_eq!(parse_rate("1000"), Ok(1000));
+        assert_eq!(parse_rate("512k"), Ok(512 * 1024));
+        assert_eq!(parse_rate("1.5G"), Ok(3 << 29));
+        assert!(parse_rate("10X").is_err());
+        assert!(parse_rate("-1M").is_err());
+        assert!(parse_rate("M").is_err());
+    }
+
+    #[test]
+    fn test_args() {
+        let args = Args::try_parse_from([
+            "server",
+            "--root",
+            "/srv/a",
+            "--root",
+            "/srv/b",
+            "--read-only",
+            "--throttle",
+            "10M",
+            "--log-level",
+            "debug",
+        ])
+        .unwrap();
+        assert_eq!(args.roots.len(), 2);
+        assert!(args.read_only);
+        assert_eq!(args.throttle, Some(10 << 20));
+        assert_eq!(args.log_level, log::LevelFilter::Debug);
+        assert_eq!(args.port_fallback, PortFallback::Range(10));
+        assert!(Args::try_parse_from(["server", "--bind", "nas"]).is_err());
+
+        let args = Args::try_parse_from(["server", "--port-fallback", "strict"]).unwrap();
+        assert_eq!(args.port_fallback, PortFallback::Strict);
+    }
 }
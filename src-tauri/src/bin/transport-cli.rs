@@ -0,0 +1,527 @@
+//! 命令行客户端：在没有浏览器的机器上用脚本收发文件。
+//! 远端路径都是对端共享目录下的绝对路径；失败时以非零状态码退出。
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use futures_util::StreamExt;
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use reqwest::StatusCode;
+use tokio::io::AsyncWriteExt;
+use transport_lib::client::{ClientError, TransportClient, UploadOptions};
+use transport_lib::identity::Identity;
+use transport_lib::server::error::ErrorCode;
+use transport_lib::transfer::atomic::ConflictPolicy;
+use transport_lib::transfer::checksum::{self, Algorithm};
+
+type Result<T> = std::result::Result<T, String>;
+
+#[derive(Parser, Debug)]
+#[command(name = "transport-cli", version, about = "Transport 命令行客户端")]
+struct Cli {
+    /// 对端地址，如 `192.168.1.5:8090` 或 `http://nas.local:8090`
+    #[arg(long, env = "TRANSPORT_HOST", default_value = "127.0.0.1:8090")]
+    host: String,
+
+    /// 对端启用了 HTTPS 时，它的设备指纹（启动日志、设备信息或配对时显示）；
+    /// 指定后使用 HTTPS，证书不符时拒绝连接
+    #[arg(long, env = "TRANSPORT_FINGERPRINT")]
+    fingerprint: Option<String>,
+
+    /// 配对后得到的设备令牌，本机访问不需要
+    #[arg(long, env = "TRANSPORT_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    /// 不显示进度条
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 向对端申请配对，输入主机上显示的 PIN 后把令牌打印到标准输出
+    Pair {
+        /// 在对端显示的本机名称
+        #[arg(long, default_value = "transport-cli")]
+        name: String,
+    },
+    /// 列出目录
+    Ls { path: String },
+    /// 下载文件，中断后再次执行会从断点继续
+    Get {
+        remote: String,
+        /// 本地文件或目录，默认当前目录
+        local: Option<PathBuf>,
+        /// 下载完成后与对端比对 BLAKE3 摘要
+        #[arg(long)]
+        verify: bool,
+    },
+    /// 上传文件到远端目录
+    Put {
+        /// 本地文件；目录需要 `-r`
+        #[arg(required = true)]
+        local: Vec<PathBuf>,
+        /// 远端目录
+        remote: String,
+        /// 递归上传目录
+        #[arg(short, long)]
+        recursive: bool,
+        /// 同名文件的处理方式：overwrite / rename / skip / fail
+        #[arg(long, default_value = "overwrite", value_parser = parse_policy)]
+        on_conflict: ConflictPolicy,
+        /// 附带 BLAKE3 摘要，由对端校验
+        #[arg(long)]
+        verify: bool,
+    },
+    /// 删除文件或目录（目录连同内容）
+    Rm {
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// 移动或重命名
+    Mv { from: String, to: String },
+    /// 创建目录（含上级目录）
+    Mkdir {
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+}
+
+fn parse_policy(s: &str) -> Result<ConflictPolicy> {
+    ConflictPolicy::parse(s).ok_or_else(|| format!("invalid policy '{}'", s))
+}
+
+struct Remote {
+    client: TransportClient,
+    quiet: bool,
+}
+
+/// 未配对时提示设置令牌。
+fn describe(e: ClientError) -> String {
+    match e {
+        ClientError::Status {
+            status, message, ..
+        } if status == StatusCode::UNAUTHORIZED => {
+            format!("{} (pair first or set TRANSPORT_TOKEN)", message)
+        }
+        ClientError::Status {
+            code: Some(ErrorCode::HttpsRequired),
+            message,
+            ..
+        } => format!(
+            "{} (set TRANSPORT_FINGERPRINT to the host's fingerprint)",
+            message
+        ),
+        e => e.to_string(),
+    }
+}
+
+impl Remote {
+    fn new(host: &str, fingerprint: Option<&str>, token: Option<String>, quiet: bool) -> Self {
+        let client = TransportClient::new(host);
+        let client = match fingerprint {
+            Some(fingerprint) => client.with_pinned_fingerprint(fingerprint),
+            None => client,
+        };
+        Self {
+            client: match token {
+                Some(token) => client.with_token(token),
+                None => client,
+            },
+            quiet,
+        }
+    }
+
+    fn progress(&self, total: u64, message: String) -> ProgressBar {
+        if self.quiet {
+            return ProgressBar::hidden();
+        }
+        let bar = ProgressBar::new(total).with_message(message);
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{msg:30!} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} {eta}",
+            )
+            .unwrap()
+            .progress_chars("=> "),
+        );
+        bar
+    }
+
+    async fn pair(&mut self, name: &str) -> Result<()> {
+        // 带上本机身份，对端换了地址也认得这台机器
+        let identity = Identity::load_or_create(&Identity::default_path())
+            .map_err(|e| format!("Failed to load device identity: {}", e))?;
+        self.client = self.client.clone().with_identity(identity);
+        let requested = self.client.request_pairing(name).await.map_err(describe)?;
+        eprint!("PIN shown on the host: ");
+        let mut pin = String::new();
+        std::io::stdin()
+            .read_line(&mut pin)
+            .map_err(|e| e.to_string())?;
+        let confirmed = self
+            .client
+            .confirm_pairing(&requested, pin.trim())
+            .await
+            .map_err(describe)?;
+        if !confirmed.fingerprint.is_empty() {
+            eprintln!("Host fingerprint: {}", confirmed.fingerprint);
+        }
+        println!("{}", confirmed.token);
+        Ok(())
+    }
+
+    async fn ls(&self, path: &str) -> Result<()> {
+        for entry in self.client.list(path).await.map_err(describe)? {
+            let modified = chrono::DateTime::from_timestamp(entry.modified as i64, 0)
+                .map(|t| {
+                    t.with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                })
+                .unwrap_or_default();
+            if entry.is_dir {
+                println!("{:>10}  {}  {}/", "-", modified, entry.name);
+            } else {
+                println!(
+                    "{:>10}  {}  {}",
+                    HumanBytes(entry.size).to_string(),
+                    modified,
+                    entry.name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// 先写 `<文件>.part`，ETag 记在 `<文件>.part.etag`；再次下载时带上
+    /// `Range` 和 `If-Range`，远端文件变了就从头开始。
+    async fn get(&self, remote: &str, local: Option<PathBuf>, verify: bool) -> Result<()> {
+        let name = remote_name(remote).ok_or_else(|| format!("invalid path: {}", remote))?;
+        let local = match local {
+            Some(path) if path.is_dir() => path.join(name),
+            Some(path) => path,
+            None => PathBuf::from(name),
+        };
+        let part = append_ext(&local, "part");
+        let etag_path = append_ext(&part, "etag");
+
+        let offset = tokio::fs::metadata(&part).await.map_or(0, |m| m.len());
+        let etag = tokio::fs::read_to_string(&etag_path).await.ok();
+        // 没有 ETag 记录的断点无法确认远端没变，只能从头下载
+        let download = match &etag {
+            Some(etag) => {
+                self.client
+                    .download_from(remote, offset, Some(etag.trim()))
+                    .await
+            }
+            None => self.client.download(remote).await,
+        }
+        .map_err(describe)?;
+        if download.offset != 0 && download.offset != offset {
+            return Err(format!(
+                "server resumed at {} instead of {}",
+                download.offset, offset
+            ));
+        }
+        if let Some(etag) = &download.etag {
+            tokio::fs::write(&etag_path, etag)
+                .await
+                .map_err(|e| format!("{}: {}", etag_path.display(), e))?;
+        }
+
+        let (start, total) = (download.offset, download.total);
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(start > 0)
+            .write(true)
+            .truncate(start == 0)
+            .open(&part)
+            .await
+            .map_err(|e| format!("{}: {}", part.display(), e))?;
+        let bar = self.progress(total, name.to_string());
+        bar.set_position(start);
+        let mut body = std::pin::pin!(download.into_stream());
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(describe)?;
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| format!("{}: {}", part.display(), e))?;
+            bar.inc(chunk.len() as u64);
+        }
+        file.flush().await.map_err(|e| e.to_string())?;
+        drop(file);
+        bar.finish();
+
+        let received = tokio::fs::metadata(&part).await.map_or(0, |m| m.len());
+        if received != total {
+            return Err(format!(
+                "incomplete download: {} of {} bytes, run again to resume",
+                received, total
+            ));
+        }
+        if verify {
+            self.verify_download(remote, &part).await?;
+        }
+        tokio::fs::rename(&part, &local)
+            .await
+            .map_err(|e| format!("{}: {}", local.display(), e))?;
+        let _ = tokio::fs::remove_file(&etag_path).await;
+        Ok(())
+    }
+
+    async fn verify_download(&self, remote: &str, local: &Path) -> Result<()> {
+        let remote_sum = self
+            .client
+            .file_hash(remote, Some(Algorithm::Blake3))
+            .await
+            .map_err(describe)?;
+        let local_sum = checksum::hash_file(local, &[Algorithm::Blake3])
+            .await
+            .map_err(|e| e.to_string())?;
+        if remote_sum.blake3.as_deref() != Some(local_sum[0].hex().as_str()) {
+            // 坏掉的断点不能再续传
+            let _ = tokio::fs::remove_file(local).await;
+            return Err(format!("checksum mismatch for {}", remote));
+        }
+        Ok(())
+    }
+
+    async fn put(
+        &self,
+        locals: &[PathBuf],
+        remote: &str,
+        recursive: bool,
+        policy: ConflictPolicy,
+        verify: bool,
+    ) -> Result<()> {
+        // 先展开成 (本地文件, 远端目录) 列表，进度条按总字节数显示
+        let mut files = Vec::new();
+        for local in locals {
+            let metadata =
+                std::fs::metadata(local).map_err(|e| format!("{}: {}", local.display(), e))?;
+            if metadata.is_dir() {
+                if !recursive {
+                    return Err(format!("{} is a directory (use -r)", local.display()));
+                }
+                let name = local
+                    .canonicalize()
+                    .ok()
+                    .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+                    .unwrap_or_default();
+                let dir = remote_join(remote, &name);
+                self.mkdir(&dir).await?;
+                self.collect(local, &dir, &mut files).await?;
+            } else {
+                files.push((local.clone(), remote.to_string(), metadata.len()));
+            }
+        }
+
+        let total = files.iter().map(|(_, _, len)| len).sum();
+        let bar = self.progress(total, String::new());
+        for (local, dir, len) in files {
+            self.upload(&local, &dir, len, policy, verify, &bar).await?;
+        }
+        bar.finish();
+        Ok(())
+    }
+
+    /// 递归列出 `local` 下的文件，同时在远端建好对应目录。
+    async fn collect(
+        &self,
+        local: &Path,
+        remote: &str,
+        files: &mut Vec<(PathBuf, String, u64)>,
+    ) -> Result<()> {
+        let mut stack = vec![(local.to_path_buf(), remote.to_string())];
+        while let Some((dir, remote_dir)) = stack.pop() {
+            let entries =
+                std::fs::read_dir(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+            for entry in entries {
+                let entry = entry.map_err(|e| format!("{}: {}", dir.display(), e))?;
+                let path = entry.path();
+                let metadata =
+                    std::fs::metadata(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                if metadata.is_dir() {
+                    let sub = remote_join(&remote_dir, &name);
+                    self.mkdir(&sub).await?;
+                    stack.push((path, sub));
+                } else {
+                    files.push((path, remote_dir.clone(), metadata.len()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn upload(
+        &self,
+        local: &Path,
+        remote_dir: &str,
+        len: u64,
+        policy: ConflictPolicy,
+        verify: bool,
+        bar: &ProgressBar,
+    ) -> Result<()> {
+        let name = local
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        bar.set_message(name.clone());
+
+        let mut options = UploadOptions {
+            on_conflict: policy,
+            checksum: None,
+        };
+        if verify {
+            let sums = checksum::hash_file(local, &[Algorithm::Blake3])
+                .await
+                .map_err(|e| format!("{}: {}", local.display(), e))?;
+            options.checksum = sums.into_iter().next();
+        }
+
+        let file = tokio::fs::File::open(local)
+            .await
+            .map_err(|e| format!("{}: {}", local.display(), e))?;
+        let progress = bar.clone();
+        let stream = tokio_util::io::ReaderStream::new(file).inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                progress.inc(chunk.len() as u64);
+            }
+        });
+        self.client
+            .upload_stream(remote_dir, &name, len, stream, &options)
+            .await
+            .map_err(|e| format!("{}: {}", local.display(), describe(e)))?;
+        Ok(())
+    }
+
+    async fn mkdir(&self, path: &str) -> Result<()> {
+        self.client.mkdir(path).await.map_err(describe)
+    }
+}
+
+/// 远端路径的最后一段，兼容 Windows 主机的反斜杠。
+fn remote_name(path: &str) -> Option<&str> {
+    path.trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\'])
+        .next()
+        .filter(|name| !name.is_empty())
+}
+
+fn remote_join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches(['/', '\\']), name)
+}
+
+fn append_ext(path: &Path, ext: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(ext);
+    PathBuf::from(name)
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let mut remote = Remote::new(&cli.host, cli.fingerprint.as_deref(), cli.token, cli.quiet);
+    match cli.command {
+        Command::Pair { name } => remote.pair(&name).await,
+        Command::Ls { path } => remote.ls(&path).await,
+        Command::Get {
+            remote: path,
+            local,
+            verify,
+        } => remote.get(&path, local, verify).await,
+        Command::Put {
+            local,
+            remote: dir,
+            recursive,
+            on_conflict,
+            verify,
+        } => {
+            remote
+                .put(&local, &dir, recursive, on_conflict, verify)
+                .await
+        }
+        Command::Rm { paths } => {
+            for path in paths {
+                remote
+                    .client
+                    .delete(&path)
+                    .await
+                    .map_err(|e| format!("{}: {}", path, describe(e)))?;
+            }
+            Ok(())
+        }
+        Command::Mv { from, to } => remote.client.rename(&from, &to).await.map_err(describe),
+        Command::Mkdir { paths } => {
+            for path in paths {
+                remote
+                    .mkdir(&path)
+                    .await
+                    .map_err(|e| format!("{}: {}", path, e))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(remote_name("/srv/media/a.mkv"), Some("a.mkv"));
+        assert_eq!(remote_name("C:\\Users\\me\\dir\\"), Some("dir"));
+        assert_eq!(remote_name("/"), None);
+        assert_eq!(remote_join("/srv/", "a"), "/srv/a");
+        assert_eq!(
+            append_ext(Path::new("out/a.mkv"), "part"),
+            Path::new("out/a.mkv.part")
+        );
+    }
+
+    #[test]
+    fn test_args() {
+        let cli = Cli::try_parse_from([
+            "transport-cli",
+            "put",
+            "-r",
+            "--on-conflict",
+            "rename",
+            "build",
+            "out.zip",
+            "/srv/drop",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Put {
+                local,
+                remote,
+                recursive,
+                on_conflict,
+                ..
+            } => {
+                assert_eq!(local.len(), 2);
+                assert_eq!(remote, "/srv/drop");
+                assert!(recursive);
+                assert_eq!(on_conflict, ConflictPolicy::Rename);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(Cli::try_parse_from(["transport-cli", "put", "/srv"]).is_err());
+    }
+}
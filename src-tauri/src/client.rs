@@ -0,0 +1,949 @@
+//! `/api` 的 Rust 客户端：每个路由对应一个方法，请求和响应直接复用
+//! `server::handlers` 等模块里的 serde 类型，服务端改了字段这里会一起编译失败。
+
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use reqwest::header;
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::discovery::DiscoveredDevice;
+use crate::events::Event;
+use crate::identity::{self, Identity};
+use crate::server::archive::ArchiveFormat;
+use crate::server::auth::PairedDevice;
+use crate::server::error::{ApiError, ErrorCode};
+use crate::server::handlers::{
+    ArchiveRequest, ChunkReceived, CreateUploadRequest, DeviceInfo, FileEntry, FileHash,
+    FinalizedUpload, PairConfirmRequest, PairConfirmed, PairRequest, PairRequested,
+    ScheduleSettings, ThrottleRequest, ThrottleSettings, UploadResponse,
+};
+use crate::server::sandbox::SharedRoot;
+use crate::server::tls;
+use crate::settings::Settings;
+use crate::transfer::atomic::ConflictPolicy;
+use crate::transfer::checksum::{Algorithm, Checksum};
+use crate::transfer::manager::TransferInfo;
+use crate::transfer::schedule::{EffectiveRate, ScheduleRule};
+use crate::transfer::session::SessionStatus;
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// 连接失败、超时等
+    Http(reqwest::Error),
+    /// 服务端返回了非 2xx 状态码；`code` 取自 JSON 错误正文
+    Status {
+        status: StatusCode,
+        code: Option<ErrorCode>,
+        message: String,
+    },
+    /// 响应内容不符合约定
+    Protocol(String),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "{}", e),
+            ClientError::Status {
+                status, message, ..
+            } if message.is_empty() => {
+                write!(f, "{}", status)
+            }
+            ClientError::Status {
+                status, message, ..
+            } => write!(f, "{}: {}", status, message),
+            ClientError::Protocol(message) => write!(f, "{}", message),
+            ClientError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl ClientError {
+    /// 服务端给出的错误码，其他错误返回 `None`。
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            ClientError::Status { code, .. } => *code,
+            _ => None,
+        }
+    }
+
+    /// 读取非 2xx 响应的正文，优先按 `ApiError` 解析。
+    async fn from_response(response: Response) -> Self {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        match serde_json::from_str::<ApiError>(&text) {
+            Ok(error) => ClientError::Status {
+                status,
+                code: Some(error.code),
+                message: error.message,
+            },
+            Err(_) => ClientError::Status {
+                status,
+                code: None,
+                message: text,
+            },
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(e: std::io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// 上传时附带的选项。
+#[derive(Clone, Debug, Default)]
+pub struct UploadOptions {
+    pub on_conflict: ConflictPolicy,
+    /// 服务端收完后校验，不一致时拒绝
+    pub checksum: Option<Checksum>,
+}
+
+/// 下载响应：`offset` 为本次数据在文件中的起点，`total` 为文件总长度。
+pub struct Download {
+    pub offset: u64,
+    pub total: u64,
+    pub etag: Option<String>,
+    /// 服务端已知摘要时给出的 `Repr-Digest`
+    pub repr_digest: Option<String>,
+    response: Option<Response>,
+}
+
+impl Download {
+    /// 断点已经是完整文件时没有数据。
+    pub fn into_stream(self) -> impl Stream<Item = Result<Bytes>> {
+        match self.response {
+            Some(response) => response.bytes_stream().map_err(ClientError::from).boxed(),
+            None => futures_util::stream::empty().boxed(),
+        }
+    }
+}
+
+/// `/api/events` 推送的内容。
+#[derive(Debug)]
+pub enum EventMessage {
+    Event(Event),
+    /// 订阅落后太多，需要重新拉取完整状态
+    Resync,
+}
+
+#[derive(Clone)]
+pub struct TransportClient {
+    http: reqwest::Client,
+    /// 以 `/api` 结尾
+    base: String,
+    token: Option<String>,
+    /// 配对时声明的本机身份
+    identity: Option<Identity>,
+    /// 固定的对端证书指纹
+    fingerprint: Option<String>,
+}
+
+impl TransportClient {
+    /// `host` 可以是 `192.168.1.5:8090`，也可以是带协议的 `http://nas.local:8090`。
+    pub fn new(host: &str) -> Self {
+        let host = host.trim_end_matches('/');
+        let base = if host.contains("://") {
+            format!("{}/api", host)
+        } else {
+            format!("http://{}/api", host)
+        };
+        Self {
+            http: reqwest::Client::new(),
+            base,
+            token: None,
+            identity: None,
+            fingerprint: None,
+        }
+    }
+
+    /// 改用 HTTPS，并要求对端证书的指纹等于 `fingerprint`（`DeviceInfo::fingerprint`、
+    /// mDNS 广播或配对时得到的），不认其他证书。
+    pub fn with_pinned_fingerprint(mut self, fingerprint: &str) -> Self {
+        self.http = reqwest::Client::builder()
+            .use_preconfigured_tls(tls::client_config(fingerprint))
+            .build()
+            .expect("failed to build HTTPS client");
+        self.fingerprint = Some(fingerprint.trim().to_lowercase());
+        if let Some(rest) = self.base.strip_prefix("http://") {
+            self.base = format!("https://{}", rest);
+        }
+        self
+    }
+
+    /// 同时探测对端的各个地址（如 `DeviceInfo::interfaces`），用最先响应的那个。
+    /// 任何 HTTP 响应（包括 401）都算连通；全部失败时返回最后一个错误。
+    /// 对端启用了 HTTPS 时传入它的指纹。
+    pub async fn connect(
+        addresses: &[IpAddr],
+        port: u16,
+        fingerprint: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        if addresses.is_empty() {
+            return Err(ClientError::Protocol(
+                "No address to connect to".to_string(),
+            ));
+        }
+        let probes = addresses.iter().map(|ip| {
+            let client = Self::new(&SocketAddr::new(*ip, port).to_string());
+            let client = match fingerprint {
+                Some(fingerprint) => client.with_pinned_fingerprint(fingerprint),
+                None => client,
+            };
+            Box::pin(async move {
+                client
+                    .request(Method::GET, "/device/info")
+                    .timeout(timeout)
+                    .send()
+                    .await?;
+                Ok::<_, ClientError>(client)
+            })
+        });
+        let (client, _) = futures_util::future::select_ok(probes).await?;
+        Ok(client)
+    }
+
+    /// 配对得到的设备令牌；本机访问不需要。
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// 配对时附上本机身份，对端以设备 id 记录配对，地址或主机名变了也不用重新配对。
+    pub fn with_identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    fn request(&self, method: Method, route: &str) -> RequestBuilder {
+        let builder = self.http.request(method, format!("{}{}", self.base, route));
+        match &self.token {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        }
+    }
+
+    /// 发送请求，非 2xx 时把响应正文作为错误信息。
+    async fn send(&self, builder: RequestBuilder) -> Result<Response> {
+        let response = builder.send().await?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        Err(ClientError::from_response(response).await)
+    }
+
+    async fn json<T: DeserializeOwned>(&self, builder: RequestBuilder) -> Result<T> {
+        Ok(self.send(builder).await?.json().await?)
+    }
+
+    /// 只关心是否成功的接口（返回 `{"ok": true}`）。
+    async fn ok(&self, builder: RequestBuilder) -> Result<()> {
+        self.send(builder).await?;
+        Ok(())
+    }
+
+    /// 原样发送一个请求（`route` 相对于 `/api`），不检查状态码，响应交给调用方处理。
+    /// 令牌由 `headers` 决定，见 [`crate::peer`]。
+    pub async fn forward(
+        &self,
+        method: Method,
+        route: &str,
+        headers: header::HeaderMap,
+        body: Option<Bytes>,
+    ) -> Result<Response> {
+        let mut builder = self.request(method, route).headers(headers);
+        if let Some(body) = body {
+            builder = builder.body(body);
+        }
+        Ok(builder.send().await?)
+    }
+
+    // --- 配对 ---
+
+    pub async fn request_pairing(&self, device_name: &str) -> Result<PairRequested> {
+        let body = PairRequest {
+            device_name: device_name.to_string(),
+            device_id: self.identity.as_ref().map(|i| i.id().to_string()),
+            public_key: self.identity.as_ref().map(|i| i.public_key()),
+        };
+        self.json(self.request(Method::POST, "/pair/request").json(&body))
+            .await
+    }
+
+    /// 成功后自动使用新令牌。带有身份时签名针对 `requested` 里的主机；
+    /// 固定了证书指纹时，主机报告的指纹必须与之一致。
+    pub async fn confirm_pairing(
+        &mut self,
+        requested: &PairRequested,
+        pin: &str,
+    ) -> Result<PairConfirmed> {
+        if let Some(fingerprint) = &self.fingerprint {
+            if *fingerprint != requested.fingerprint {
+                return Err(ClientError::Protocol(
+                    "Host fingerprint does not match the pinned certificate".to_string(),
+                ));
+            }
+        }
+        let body = PairConfirmRequest {
+            request_id: requested.request_id.clone(),
+            pin: pin.to_string(),
+            signature: self.identity.as_ref().map(|i| {
+                i.sign(&identity::pairing_message(
+                    &requested.device_id,
+                    &requested.fingerprint,
+                    &requested.request_id,
+                ))
+            }),
+        };
+        let confirmed: PairConfirmed = self
+            .json(self.request(Method::POST, "/pair/confirm").json(&body))
+            .await?;
+        self.token = Some(confirmed.token.clone());
+        Ok(confirmed)
+    }
+
+    pub async fn paired_devices(&self) -> Result<Vec<PairedDevice>> {
+        self.json(self.request(Method::GET, "/pair/devices")).await
+    }
+
+    pub async fn revoke_device(&self, id: &str) -> Result<()> {
+        self.ok(self.request(Method::DELETE, &format!("/pair/devices/{}", id)))
+            .await
+    }
+
+    // --- 设备 ---
+
+    pub async fn device_info(&self) -> Result<DeviceInfo> {
+        self.json(self.request(Method::GET, "/device/info")).await
+    }
+
+    pub async fn devices(&self) -> Result<Vec<DiscoveredDevice>> {
+        self.json(self.request(Method::GET, "/devices")).await
+    }
+
+    pub async fn roots(&self) -> Result<Vec<SharedRoot>> {
+        self.json(self.request(Method::GET, "/roots")).await
+    }
+
+    // --- 文件 ---
+
+    pub async fn list(&self, path: &str) -> Result<Vec<FileEntry>> {
+        self.json(self.request(Method::GET, "/files").query(&[("path", path)]))
+            .await
+    }
+
+    /// 目录连同内容一起删除。
+    pub async fn delete(&self, path: &str) -> Result<()> {
+        self.ok(self
+            .request(Method::DELETE, "/files")
+            .query(&[("path", path)]))
+            .await
+    }
+
+    pub async fn rename(&self, old_path: &str, new_path: &str) -> Result<()> {
+        self.ok(self
+            .request(Method::PUT, "/files/rename")
+            .json(&serde_json::json!({"old_path": old_path, "new_path": new_path})))
+            .await
+    }
+
+    /// 上级目录不存在时一并创建。
+    pub async fn mkdir(&self, path: &str) -> Result<()> {
+        self.ok(self
+            .request(Method::POST, "/files/mkdir")
+            .json(&serde_json::json!({"path": path})))
+            .await
+    }
+
+    pub async fn file_hash(&self, path: &str, algorithm: Option<Algorithm>) -> Result<FileHash> {
+        let mut builder = self
+            .request(Method::GET, "/files/hash")
+            .query(&[("path", path)]);
+        if let Some(algorithm) = algorithm {
+            builder = builder.query(&[("algorithm", algorithm.name())]);
+        }
+        self.json(builder).await
+    }
+
+    pub async fn download(&self, path: &str) -> Result<Download> {
+        self.download_from(path, 0, None).await
+    }
+
+    /// 从 `offset` 处继续下载。给出 `if_range`（之前拿到的 ETag）时，
+    /// 远端文件已变化会返回完整文件，此时 `offset` 为 0。
+    pub async fn download_from(
+        &self,
+        path: &str,
+        offset: u64,
+        if_range: Option<&str>,
+    ) -> Result<Download> {
+        let mut builder = self
+            .request(Method::GET, "/files/download")
+            .query(&[("path", path)]);
+        if offset > 0 {
+            builder = builder.header(header::RANGE, format!("bytes={}-", offset));
+            if let Some(etag) = if_range {
+                builder = builder.header(header::IF_RANGE, etag);
+            }
+        }
+        let response = builder.send().await?;
+        let header = |name: header::HeaderName| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let etag = header(header::ETAG);
+        let repr_digest = header(header::HeaderName::from_static("repr-digest"));
+        let content_range = header(header::CONTENT_RANGE);
+
+        match response.status() {
+            StatusCode::OK => Ok(Download {
+                offset: 0,
+                total: response.content_length().unwrap_or(0),
+                etag,
+                repr_digest,
+                response: Some(response),
+            }),
+            StatusCode::PARTIAL_CONTENT => {
+                let (start, total) = content_range
+                    .as_deref()
+                    .and_then(parse_content_range)
+                    .ok_or_else(|| ClientError::Protocol("Invalid Content-Range".to_string()))?;
+                Ok(Download {
+                    offset: start,
+                    total,
+                    etag,
+                    repr_digest,
+                    response: Some(response),
+                })
+            }
+            // 断点正好在文件末尾
+            StatusCode::RANGE_NOT_SATISFIABLE
+                if content_range.as_deref() == Some(&format!("bytes */{}", offset)) =>
+            {
+                Ok(Download {
+                    offset,
+                    total: offset,
+                    etag,
+                    repr_digest,
+                    response: None,
+                })
+            }
+            _ => Err(ClientError::from_response(response).await),
+        }
+    }
+
+    pub async fn download_archive(
+        &self,
+        path: &str,
+        format: ArchiveFormat,
+    ) -> Result<impl Stream<Item = Result<Bytes>>> {
+        let response = self
+            .send(
+                self.request(Method::GET, "/files/archive")
+                    .query(&[("path", path), ("format", format.extension())]),
+            )
+            .await?;
+        Ok(response.bytes_stream().map_err(ClientError::from))
+    }
+
+    pub async fn download_selection(
+        &self,
+        request: &ArchiveRequest,
+    ) -> Result<impl Stream<Item = Result<Bytes>>> {
+        let response = self
+            .send(self.request(Method::POST, "/files/archive").json(request))
+            .await?;
+        Ok(response.bytes_stream().map_err(ClientError::from))
+    }
+
+    /// 以 multipart 流式上传一个文件到目录 `dir`。
+    pub async fn upload_stream<S>(
+        &self,
+        dir: &str,
+        file_name: &str,
+        len: u64,
+        stream: S,
+        options: &UploadOptions,
+    ) -> Result<UploadResponse>
+    where
+        S: Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        let policy = serde_json::to_value(options.on_conflict)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let mut form = reqwest::multipart::Form::new()
+            .text("path", dir.to_string())
+            .text("on_conflict", policy);
+        // 摘要字段必须在文件字段之前
+        if let Some(checksum) = &options.checksum {
+            form = form.text("checksum", checksum.to_string());
+        }
+        let part =
+            reqwest::multipart::Part::stream_with_length(reqwest::Body::wrap_stream(stream), len)
+                .file_name(file_name.to_string());
+        form = form.part("file", part);
+        self.json(self.request(Method::POST, "/files/upload").multipart(form))
+            .await
+    }
+
+    pub async fn upload_file(
+        &self,
+        dir: &str,
+        local: &Path,
+        options: &UploadOptions,
+    ) -> Result<UploadResponse> {
+        let file = tokio::fs::File::open(local).await?;
+        let len = file.metadata().await?.len();
+        let name = local
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let stream = tokio_util::io::ReaderStream::new(file);
+        self.upload_stream(dir, &name, len, stream, options).await
+    }
+
+    // --- 分块上传会话 ---
+
+    pub async fn create_upload(&self, request: &CreateUploadRequest) -> Result<SessionStatus> {
+        self.json(self.request(Method::POST, "/uploads").json(request))
+            .await
+    }
+
+    pub async fn upload_status(&self, id: &str) -> Result<SessionStatus> {
+        self.json(self.request(Method::GET, &format!("/uploads/{}", id)))
+            .await
+    }
+
+    /// 返回服务端已提交的偏移量。
+    pub async fn upload_chunk(
+        &self,
+        id: &str,
+        offset: u64,
+        body: impl Into<reqwest::Body>,
+    ) -> Result<u64> {
+        let received: ChunkReceived = self
+            .json(
+                self.request(Method::PUT, &format!("/uploads/{}", id))
+                    .query(&[("offset", offset)])
+                    .body(body),
+            )
+            .await?;
+        Ok(received.offset)
+    }
+
+    pub async fn finalize_upload(&self, id: &str) -> Result<FinalizedUpload> {
+        self.json(self.request(Method::POST, &format!("/uploads/{}/finalize", id)))
+            .await
+    }
+
+    pub async fn abort_upload(&self, id: &str) -> Result<()> {
+        self.ok(self.request(Method::DELETE, &format!("/uploads/{}", id)))
+            .await
+    }
+
+    // --- 传输队列 ---
+
+    pub async fn transfers(&self) -> Result<Vec<TransferInfo>> {
+        self.json(self.request(Method::GET, "/transfers")).await
+    }
+
+    pub async fn transfer(&self, id: &str) -> Result<TransferInfo> {
+        self.json(self.request(Method::GET, &format!("/transfers/{}", id)))
+            .await
+    }
+
+    pub async fn pause_transfer(&self, id: &str) -> Result<TransferInfo> {
+        self.json(self.request(Method::POST, &format!("/transfers/{}/pause", id)))
+            .await
+    }
+
+    pub async fn resume_transfer(&self, id: &str) -> Result<TransferInfo> {
+        self.json(self.request(Method::POST, &format!("/transfers/{}/resume", id)))
+            .await
+    }
+
+    pub async fn cancel_transfer(&self, id: &str) -> Result<TransferInfo> {
+        self.json(self.request(Method::POST, &format!("/transfers/{}/cancel", id)))
+            .await
+    }
+
+    /// 清除已结束的项，返回剩下的。
+    pub async fn clear_transfers(&self) -> Result<Vec<TransferInfo>> {
+        self.json(self.request(Method::DELETE, "/transfers")).await
+    }
+
+    /// 订阅 `/api/events`，连接断开时流结束。
+    pub async fn events(&self) -> Result<impl Stream<Item = Result<EventMessage>>> {
+        let response = self
+            .send(
+                self.request(Method::GET, "/events")
+                    .header(header::ACCEPT, "text/event-stream"),
+            )
+            .await?;
+        Ok(sse_messages(response.bytes_stream()))
+    }
+
+    // --- 设置 ---
+
+    pub async fn settings(&self) -> Result<Settings> {
+        self.json(self.request(Method::GET, "/settings")).await
+    }
+
+    /// 只修改 `patch` 中出现的顶层字段，返回修改后生效的设置。
+    pub async fn update_settings(&self, patch: &serde_json::Value) -> Result<Settings> {
+        self.json(self.request(Method::PUT, "/settings").json(patch))
+            .await
+    }
+
+    pub async fn throttle(&self) -> Result<ThrottleSettings> {
+        self.json(self.request(Method::GET, "/settings/throttle"))
+            .await
+    }
+
+    pub async fn set_throttle(&self, request: &ThrottleRequest) -> Result<()> {
+        self.ok(self
+            .request(Method::PUT, "/settings/throttle")
+            .json(request))
+            .await
+    }
+
+    pub async fn schedule(&self) -> Result<ScheduleSettings> {
+        self.json(self.request(Method::GET, "/settings/schedule"))
+            .await
+    }
+
+    pub async fn set_schedule(&self, rules: &[ScheduleRule]) -> Result<EffectiveRate> {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            rules: &'a [ScheduleRule],
+        }
+        self.json(
+            self.request(Method::PUT, "/settings/schedule")
+                .json(&Body { rules }),
+        )
+        .await
+    }
+
+    /// 写入对端的前端日志文件。
+    pub async fn send_logs(&self, text: impl Into<String>) -> Result<()> {
+        self.ok(self.request(Method::POST, "/logs").body(text.into()))
+            .await
+    }
+}
+
+/// `bytes 100-199/1000` -> (100, 1000)
+pub fn parse_content_range(value: &str) -> Option<(u64, u64)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    Some((start.parse().ok()?, total.parse().ok()?))
+}
+
+/// 把响应正文切成 SSE 消息。多字节字符可能被拆在两块里，
+/// 所以按字节缓冲，凑齐一条完整消息后才解码。
+fn sse_messages(
+    mut body: impl Stream<Item = reqwest::Result<Bytes>> + Unpin,
+) -> impl Stream<Item = Result<EventMessage>> {
+    async_stream::stream! {
+        let mut buffer = Vec::new();
+        while let Some(chunk) = body.next().await {
+            match chunk {
+                Ok(chunk) => buffer.extend_from_slice(&chunk),
+                Err(e) => {
+                    yield Err(ClientError::from(e));
+                    return;
+                }
+            }
+            // SSE 以空行分隔各条消息
+            while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+                let message: Vec<u8> = buffer.drain(..end + 2).collect();
+                if let Some(parsed) = parse_sse(&String::from_utf8_lossy(&message)) {
+                    yield parsed;
+                }
+            }
+        }
+    }
+}
+
+/// 解析一条 SSE 消息，注释（保活）返回 `None`。
+fn parse_sse(message: &str) -> Option<Result<EventMessage>> {
+    let mut name = "message";
+    let mut data = Vec::new();
+    for line in message.lines() {
+        if let Some(value) = line.strip_prefix("event:") {
+            name = value.trim();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+    if data.is_empty() {
+        return None;
+    }
+    if name == "resync" {
+        return Some(Ok(EventMessage::Resync));
+    }
+    Some(
+        Event::from_parts(name, &data.join("\n"))
+            .map(EventMessage::Event)
+            .map_err(ClientError::Protocol),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::auth::PairingPrompt;
+    use crate::server::{routes, test_state, AppState};
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    /// 在随机端口上启动只含 `/api` 的服务。
+    async fn serve(state: AppState) -> TransportClient {
+        let app = axum::Router::new()
+            .nest("/api", routes::api_routes(state.clone()))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        });
+        TransportClient::new(&addr.to_string())
+    }
+
+    async fn collect(download: Download) -> Vec<u8> {
+        download
+            .into_stream()
+            .try_fold(Vec::new(), |mut all, chunk| async move {
+                all.extend_from_slice(&chunk);
+                Ok(all)
+            })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_file_round_trip() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let client = serve(test_state(dir.path())).await;
+
+        let sub = format!("{}/sub", root);
+        client.mkdir(&sub).await.unwrap();
+        let local = tempdir().unwrap();
+        let source = local.path().join("data.bin");
+        std::fs::write(&source, b"0123456789").unwrap();
+        let uploaded = client
+            .upload_file(&sub, &source, &UploadOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(uploaded.saved, vec!["data.bin".to_string()]);
+
+        let entries = client.list(&sub).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            (entries[0].name.as_str(), entries[0].size),
+            ("data.bin", 10)
+        );
+
+        let path = format!("{}/data.bin", sub);
+        let full = client.download(&path).await.unwrap();
+        let etag = full.etag.clone().unwrap();
+        assert_eq!(full.total, 10);
+        assert_eq!(collect(full).await, b"0123456789");
+
+        let rest = client.download_from(&path, 6, Some(&etag)).await.unwrap();
+        assert_eq!((rest.offset, rest.total), (6, 10));
+        assert_eq!(collect(rest).await, b"6789");
+        let done = client.download_from(&path, 10, Some(&etag)).await.unwrap();
+        assert_eq!(done.offset, 10);
+        assert!(collect(done).await.is_empty());
+
+        let hash = client
+            .file_hash(&path, Some(Algorithm::Blake3))
+            .await
+            .unwrap();
+        assert_eq!(
+            hash.blake3.as_deref(),
+            Some(blake3::hash(b"0123456789").to_hex().as_str())
+        );
+        assert!(hash.sha256.is_none());
+
+        let renamed = format!("{}/renamed.bin", sub);
+        client.rename(&path, &renamed).await.unwrap();
+        client.delete(&renamed).await.unwrap();
+        assert!(client.list(&sub).await.unwrap().is_empty());
+
+        let err = client.list("/definitely/outside").await.unwrap_err();
+        assert!(
+            matches!(err, ClientError::Status { status, .. } if status == StatusCode::FORBIDDEN)
+        );
+        assert_eq!(err.code(), Some(ErrorCode::Forbidden));
+        let err = client.delete(&renamed).await.unwrap_err();
+        assert_eq!(err.code(), Some(ErrorCode::NotFound));
+    }
+
+    #[tokio::test]
+    async fn test_connect_picks_reachable_address() {
+        let dir = tempdir().unwrap();
+        let client = serve(test_state(dir.path())).await;
+        let port: u16 = client
+            .base
+            .trim_end_matches("/api")
+            .rsplit(':')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        // 服务只监听 IPv4，IPv6 回环地址连不上
+        let addresses = ["::1".parse().unwrap(), "127.0.0.1".parse().unwrap()];
+        let connected = TransportClient::connect(&addresses, port, None, Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert_eq!(connected.base, client.base);
+        connected.device_info().await.unwrap();
+
+        assert!(
+            TransportClient::connect(&[], port, None, Duration::from_secs(1))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pairing_signs_for_the_host() {
+        let dir = tempdir().unwrap();
+        let mut state = test_state(dir.path());
+        let pins = Arc::new(Mutex::new(Vec::new()));
+        let sink = pins.clone();
+        state.pairing = state
+            .pairing
+            .with_notifier(Arc::new(move |prompt: &PairingPrompt| {
+                sink.lock().unwrap().push(prompt.pin.clone());
+            }));
+        let host = state.identity.clone();
+        let device = Identity::generate();
+        let mut client = serve(state).await.with_identity(device.clone());
+
+        let requested = client.request_pairing("Laptop").await.unwrap();
+        assert_eq!(requested.device_id, host.id());
+        assert_eq!(requested.fingerprint, host.fingerprint());
+        let pin = pins.lock().unwrap()[0].clone();
+        let confirmed = client.confirm_pairing(&requested, &pin).await.unwrap();
+        assert_eq!(confirmed.device_id, device.id());
+        assert_eq!(client.token(), Some(confirmed.token.as_str()));
+
+        // 主机报告的指纹与固定的证书不一致时不签名
+        let mut pinned = client.clone().with_pinned_fingerprint(&"0".repeat(64));
+        assert!(matches!(
+            pinned.confirm_pairing(&requested, &pin).await,
+            Err(ClientError::Protocol(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_settings_and_device_info() {
+        let dir = tempdir().unwrap();
+        let state = test_state(dir.path());
+        let identity = state.identity.clone();
+        let client = serve(state).await;
+
+        let info = client.device_info().await.unwrap();
+        assert_eq!(info.port, 8090);
+        assert_eq!(info.id, identity.id());
+        assert_eq!(info.fingerprint, identity.fingerprint());
+        assert_eq!(client.roots().await.unwrap().len(), 1);
+
+        client
+            .set_throttle(&ThrottleRequest {
+                upload_bytes_per_sec: Some(4096),
+                ..ThrottleRequest::default()
+            })
+            .await
+            .unwrap();
+        let throttle = client.throttle().await.unwrap();
+        assert_eq!(throttle.upload_bytes_per_sec, 4096);
+        assert_eq!(throttle.download_bytes_per_sec, 0);
+
+        let settings = client
+            .update_settings(&serde_json::json!({"device_name": "desk"}))
+            .await
+            .unwrap();
+        assert_eq!(settings.upload_bytes_per_sec, 4096);
+        assert_eq!(client.device_info().await.unwrap().name, "desk");
+        assert_eq!(client.schedule().await.unwrap().effective.rule, None);
+        assert!(client.transfers().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(parse_content_range("bytes 100-199/1000"), Some((100, 1000)));
+        assert_eq!(parse_content_range("bytes */1000"), None);
+    }
+
+    #[test]
+    fn test_parse_sse() {
+        assert!(parse_sse(": keep-alive\n\n").is_none());
+        assert!(matches!(
+            parse_sse("event: resync\ndata: {}\n\n"),
+            Some(Ok(EventMessage::Resync))
+        ));
+        let message = "event: fs-changed\ndata: {\"kind\":\"created\",\"path\":\"/a\"}\n\n";
+        assert!(matches!(
+            parse_sse(message),
+            Some(Ok(EventMessage::Event(Event::FsChanged(change)))) if change.path == "/a"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sse_multibyte_split_across_chunks() {
+        let message =
+            "event: fs-changed\ndata: {\"kind\":\"created\",\"path\":\"/照片/夏天.jpg\"}\n\n";
+        // 在“照”字的三个字节中间切开
+        let split = message.find('照').unwrap() + 1;
+        let chunks = vec![
+            Ok(Bytes::copy_from_slice(&message.as_bytes()[..split])),
+            Ok(Bytes::copy_from_slice(&message.as_bytes()[split..])),
+        ];
+        let messages: Vec<_> = sse_messages(futures_util::stream::iter(chunks))
+            .collect()
+            .await;
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(
+            &messages[0],
+            Ok(EventMessage::Event(Event::FsChanged(change))) if change.path == "/照片/夏天.jpg"
+        ));
+    }
+}
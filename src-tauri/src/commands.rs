@@ -0,0 +1,172 @@
+//! 前端在原生应用里通过 `invoke` 调用的命令。直接复用 axum 处理器，
+//! 与浏览器模式走 HTTP 的效果一致；出错时前端收到 `{code, message}`。
+
+use axum::extract::{Path, State as AxumState};
+use axum::{Extension, Json};
+use serde::Serialize;
+use tauri::ipc::Channel;
+use tauri::State;
+
+use crate::discovery::DiscoveredDevice;
+use crate::identity;
+use crate::peer::{PeerBody, PeerRequest, PeerResponse, Peers};
+use crate::server::auth::Caller;
+use crate::server::error::{ApiError, ErrorCode};
+use crate::server::handlers::{self, DeviceInfo, ThrottleRequest, ThrottleSettings};
+use crate::server::{AppState, ServerHandle};
+use crate::transfer::manager::TransferInfo;
+
+#[tauri::command]
+pub async fn get_devices(state: State<'_, AppState>) -> Result<Vec<DiscoveredDevice>, ApiError> {
+    Ok(state.discovery.devices().await)
+}
+
+#[tauri::command]
+pub fn get_local_device_info(state: State<'_, AppState>) -> DeviceInfo {
+    state.device_info()
+}
+
+/// 同时设置上传和下载的基础速率，0 表示不限。
+#[tauri::command]
+pub async fn set_throttle_rate(
+    state: State<'_, AppState>,
+    bytes_per_sec: u64,
+) -> Result<(), ApiError> {
+    let request = ThrottleRequest {
+        bytes_per_sec: Some(bytes_per_sec),
+        ..ThrottleRequest::default()
+    };
+    handlers::set_throttle(
+        AxumState(state.inner().clone()),
+        Extension(Caller::Local),
+        Json(request),
+    )
+    .await
+    .map(|_| ())
+}
+
+#[tauri::command]
+pub async fn get_throttle(state: State<'_, AppState>) -> Result<ThrottleSettings, ApiError> {
+    let Json(settings) = handlers::get_throttle(AxumState(state.inner().clone())).await;
+    Ok(settings)
+}
+
+// --- 传输队列 ---
+
+#[tauri::command]
+pub fn list_transfers(state: State<'_, AppState>) -> Vec<TransferInfo> {
+    state.transfers.list()
+}
+
+#[tauri::command]
+pub async fn pause_transfer(
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<TransferInfo, ApiError> {
+    let Json(info) = handlers::pause_transfer(AxumState(state.inner().clone()), Path(id)).await?;
+    Ok(info)
+}
+
+#[tauri::command]
+pub async fn resume_transfer(
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<TransferInfo, ApiError> {
+    let Json(info) = handlers::resume_transfer(AxumState(state.inner().clone()), Path(id)).await?;
+    Ok(info)
+}
+
+#[tauri::command]
+pub async fn cancel_transfer(
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<TransferInfo, ApiError> {
+    let Json(info) = handlers::cancel_transfer(AxumState(state.inner().clone()), Path(id)).await?;
+    Ok(info)
+}
+
+/// 清除已结束的项，返回剩下的。
+#[tauri::command]
+pub fn clear_transfers(state: State<'_, AppState>) -> Vec<TransferInfo> {
+    state.transfers.clear_finished();
+    state.transfers.list()
+}
+
+// --- 服务 ---
+
+/// 修改端口等设置后重启内置服务，返回新的本机信息。
+#[tauri::command]
+pub async fn restart_server(server: State<'_, ServerHandle>) -> Result<DeviceInfo, ApiError> {
+    server
+        .restart()
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    Ok(server.state().device_info())
+}
+
+// --- 设备身份 ---
+
+#[derive(Serialize)]
+pub struct PairingIdentity {
+    pub device_id: String,
+    pub public_key: String,
+}
+
+/// 向其他设备发起配对时附带的本机身份。
+#[tauri::command]
+pub fn pairing_identity(state: State<'_, AppState>) -> PairingIdentity {
+    PairingIdentity {
+        device_id: state.identity.id().to_string(),
+        public_key: state.identity.public_key(),
+    }
+}
+
+/// 用本机私钥签名对 `host_id` 主机的配对请求，私钥不离开后端。
+/// 只签 [`identity::pairing_message`]，不能用来签任意内容。
+/// `/api/pair/request` 的响应没有经过认证，`host_fingerprint` 必须与发现设备时得到的
+/// `expected_fingerprint` 一致，否则拒绝签名。
+#[tauri::command]
+pub fn sign_pairing(
+    state: State<'_, AppState>,
+    host_id: String,
+    host_fingerprint: String,
+    expected_fingerprint: String,
+    request_id: String,
+) -> Result<String, ApiError> {
+    if expected_fingerprint.is_empty()
+        || !host_fingerprint.eq_ignore_ascii_case(&expected_fingerprint)
+    {
+        return Err(ApiError::new(
+            ErrorCode::Forbidden,
+            "Host fingerprint does not match the discovered device",
+        ));
+    }
+    Ok(state.identity.sign(&identity::pairing_message(
+        &host_id,
+        &host_fingerprint.to_ascii_lowercase(),
+        &request_id,
+    )))
+}
+
+// --- 启用 HTTPS 的设备 ---
+
+/// 经由后端访问启用了 HTTPS 的设备，证书固定为 `request.fingerprint`。
+/// 返回响应头，正文从 `on_body` 分块推送。
+#[tauri::command]
+pub async fn peer_fetch(
+    peers: State<'_, Peers>,
+    request: PeerRequest,
+    on_body: Channel<PeerBody>,
+) -> Result<PeerResponse, ApiError> {
+    peers
+        .fetch(request, move |body| {
+            let _ = on_body.send(body);
+        })
+        .await
+}
+
+/// 前端不再读取 `peer_fetch` 的正文时调用。
+#[tauri::command]
+pub fn peer_abort(peers: State<'_, Peers>, id: u64) {
+    peers.abort(id);
+}
@@ -0,0 +1,380 @@
+//! mDNS 设备发现：把本机作为 `_transport._tcp` 服务广播出去，并维护局域网内的对端列表。
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+use crate::events::{Event, EventBus};
+use crate::server::handlers::DeviceInfo;
+use crate::server::network;
+
+const SERVICE_TYPE: &str = "_transport._tcp.local.";
+
+/// 多久重新发起一次浏览，让仍在线的对端刷新 `last_seen`。
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// 超过这个时间没有被重新解析到的对端视为已离线。
+const PEER_TTL: Duration = Duration::from_secs(180);
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DiscoveredDevice {
+    pub name: String,
+    pub platform: String,
+    pub ip: String,
+    pub port: u16,
+    pub home_dir: String,
+    /// 解析到的全部可用地址，按优先级排序，`ip` 为第一个
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// 设备 id 和公钥指纹，旧版本没有广播时为空
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub fingerprint: String,
+    /// 只接受 HTTPS，连接时固定 `fingerprint`
+    #[serde(default)]
+    pub https: bool,
+}
+
+struct Peer {
+    device: DiscoveredDevice,
+    last_seen: Instant,
+}
+
+/// 以 mDNS 实例全名为键的对端表；同一设备 id 只保留最新的一条。
+#[derive(Default)]
+struct PeerTable {
+    peers: HashMap<String, Peer>,
+}
+
+impl PeerTable {
+    /// 以下三个方法都返回对端列表是否有变化。
+    fn upsert(&mut self, fullname: String, device: DiscoveredDevice, now: Instant) -> bool {
+        let mut changed = self.peers.get(&fullname).map(|p| &p.device) != Some(&device);
+        // 改名或换了地址后重新广播，旧实例要等过期才会消失
+        if !device.id.is_empty() {
+            let before = self.peers.len();
+            self.peers
+                .retain(|name, peer| *name == fullname || peer.device.id != device.id);
+            changed |= self.peers.len() != before;
+        }
+        self.peers.insert(
+            fullname,
+            Peer {
+                device,
+                last_seen: now,
+            },
+        );
+        changed
+    }
+
+    fn remove(&mut self, fullname: &str) -> bool {
+        self.peers.remove(fullname).is_some()
+    }
+
+    fn prune(&mut self, now: Instant) -> bool {
+        let before = self.peers.len();
+        self.peers
+            .retain(|_, peer| now.duration_since(peer.last_seen) < PEER_TTL);
+        self.peers.len() != before
+    }
+
+    fn list(&self) -> Vec<DiscoveredDevice> {
+        let mut devices: Vec<_> = self.peers.values().map(|p| p.device.clone()).collect();
+        devices.sort_by_key(|d| d.name.to_lowercase());
+        devices
+    }
+}
+
+/// 可克隆的发现服务句柄；mDNS 不可用时退化为空列表。
+#[derive(Clone)]
+pub struct Discovery {
+    daemon: Option<ServiceDaemon>,
+    peers: Arc<Mutex<PeerTable>>,
+    /// 本机当前广播的实例全名，浏览时据此排除自己
+    own_fullname: Arc<std::sync::Mutex<Option<String>>>,
+    browsing: Arc<AtomicBool>,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Discovery {
+    pub fn new() -> Self {
+        let daemon = match ServiceDaemon::new() {
+            Ok(d) => Some(d),
+            Err(e) => {
+                log::warn!("mDNS unavailable, device discovery disabled: {}", e);
+                None
+            }
+        };
+        Self {
+            daemon,
+            peers: Arc::new(Mutex::new(PeerTable::default())),
+            own_fullname: Arc::default(),
+            browsing: Arc::default(),
+        }
+    }
+
+    /// 不使用 mDNS 的实例（测试或被禁用时）。
+    pub fn disabled() -> Self {
+        Self {
+            daemon: None,
+            peers: Arc::new(Mutex::new(PeerTable::default())),
+            own_fullname: Arc::default(),
+            browsing: Arc::default(),
+        }
+    }
+
+    /// 当前在线的对端（不含本机）。
+    pub async fn devices(&self) -> Vec<DiscoveredDevice> {
+        self.peers.lock().await.list()
+    }
+
+    /// 广播本机并开始浏览对端，列表变化时发布 `PeersChanged`。
+    /// `bind` 不是通配地址时只广播这一个地址。服务重启后再次调用会替换之前的广播。
+    pub fn start(&self, local: &DeviceInfo, bind: IpAddr, events: EventBus) {
+        let Some(daemon) = self.daemon.clone() else {
+            return;
+        };
+
+        self.stop_advertising();
+        match advertise(&daemon, local, bind) {
+            Ok(fullname) => *self.own_fullname.lock().unwrap() = Some(fullname),
+            Err(e) => log::warn!("Failed to advertise mDNS service: {}", e),
+        }
+
+        if !self.browsing.swap(true, Ordering::SeqCst) {
+            tokio::spawn(browse(
+                daemon,
+                self.peers.clone(),
+                self.own_fullname.clone(),
+                events,
+            ));
+        }
+    }
+
+    /// 撤销本机的广播，对端会收到下线通知；浏览不受影响。
+    pub fn stop_advertising(&self) {
+        let Some(daemon) = &self.daemon else {
+            return;
+        };
+        if let Some(fullname) = self.own_fullname.lock().unwrap().take() {
+            if let Err(e) = daemon.unregister(&fullname) {
+                log::warn!("Failed to unregister mDNS service: {}", e);
+            }
+        }
+    }
+}
+
+/// 注册本机服务，返回实例全名。
+fn advertise(
+    daemon: &ServiceDaemon,
+    local: &DeviceInfo,
+    bind: IpAddr,
+) -> Result<String, mdns_sd::Error> {
+    // 同名主机很常见，实例名加上设备 id 的前几位避免冲突，重启后保持不变；展示名放在 TXT 里
+    let label: String = local
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(40)
+        .collect();
+    let suffix: String = local.id.chars().filter(|c| *c != '-').take(6).collect();
+    let instance = format!("{}-{}", label, suffix);
+    let host = format!("{}.local.", instance);
+
+    let properties = [
+        ("name", local.name.as_str()),
+        ("platform", local.platform.as_str()),
+        ("home_dir", local.home_dir.as_str()),
+        ("id", local.id.as_str()),
+        ("fingerprint", local.fingerprint.as_str()),
+        ("https", if local.https { "1" } else { "0" }),
+    ];
+
+    let addr = if bind.is_unspecified() {
+        String::new()
+    } else {
+        bind.to_string()
+    };
+    let mut service = ServiceInfo::new(
+        SERVICE_TYPE,
+        &instance,
+        &host,
+        addr.as_str(),
+        local.port,
+        &properties[..],
+    )?;
+    if bind.is_unspecified() {
+        service = service.enable_addr_auto();
+    }
+
+    let fullname = service.get_fullname().to_string();
+    daemon.register(service)?;
+    Ok(fullname)
+}
+
+async fn browse(
+    daemon: ServiceDaemon,
+    peers: Arc<Mutex<PeerTable>>,
+    own_fullname: Arc<std::sync::Mutex<Option<String>>>,
+    events: EventBus,
+) {
+    let mut receiver = match daemon.browse(SERVICE_TYPE) {
+        Ok(r) => r,
+        Err(e) => {
+            log::warn!("Failed to browse mDNS services: {}", e);
+            return;
+        }
+    };
+    let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+    refresh.tick().await;
+
+    let publish = |table: &PeerTable| events.publish(Event::PeersChanged(table.list()));
+
+    loop {
+        tokio::select! {
+            event = receiver.recv_async() => {
+                let Ok(event) = event else { break };
+                match event {
+                    ServiceEvent::ServiceResolved(info) => {
+                        if own_fullname.lock().unwrap().as_deref() == Some(info.get_fullname()) {
+                            continue;
+                        }
+                        if let Some(device) = to_device(&info) {
+                            let fullname = info.get_fullname().to_string();
+                            let mut table = peers.lock().await;
+                            if table.upsert(fullname, device, Instant::now()) {
+                                publish(&table);
+                            }
+                        }
+                    }
+                    ServiceEvent::ServiceRemoved(_, fullname) => {
+                        let mut table = peers.lock().await;
+                        if table.remove(&fullname) {
+                            publish(&table);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ = refresh.tick() => {
+                let mut table = peers.lock().await;
+                if table.prune(Instant::now()) {
+                    publish(&table);
+                }
+                drop(table);
+                // 重新浏览会让 daemon 对缓存中的服务再次发出 ServiceResolved
+                let _ = daemon.stop_browse(SERVICE_TYPE);
+                match daemon.browse(SERVICE_TYPE) {
+                    Ok(r) => receiver = r,
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+}
+
+fn to_device(info: &ServiceInfo) -> Option<DiscoveredDevice> {
+    // 优先 IPv4，其次非链路本地的 IPv6；只有链路本地地址时也用它
+    let mut ips: Vec<IpAddr> = info.get_addresses().iter().copied().collect();
+    ips.sort_by_key(|ip| (!network::is_usable(*ip), network::preference(*ip), *ip));
+    let ip = ips.first()?.to_string();
+    let addresses = ips
+        .into_iter()
+        .filter(|ip| network::is_usable(*ip))
+        .map(|ip| ip.to_string())
+        .collect();
+
+    let txt = |key: &str| {
+        info.get_property_val_str(key)
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    Some(DiscoveredDevice {
+        name: txt("name"),
+        platform: txt("platform"),
+        ip,
+        port: info.get_port(),
+        home_dir: txt("home_dir"),
+        addresses,
+        id: txt("id"),
+        fingerprint: txt("fingerprint"),
+        https: txt("https") == "1",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(name: &str) -> DiscoveredDevice {
+        DiscoveredDevice {
+            id: String::new(),
+            fingerprint: String::new(),
+            https: false,
+            name: name.to_string(),
+            platform: "linux".to_string(),
+            ip: "192.168.1.2".to_string(),
+            port: 8090,
+            home_dir: "/home/me".to_string(),
+            addresses: vec!["192.168.1.2".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_peer_table_upsert_and_remove() {
+        let mut table = PeerTable::default();
+        let now = Instant::now();
+        table.upsert("b._transport._tcp.local.".into(), device("beta"), now);
+        assert!(table.upsert("a._transport._tcp.local.".into(), device("Alpha"), now));
+        assert!(!table.upsert("a._transport._tcp.local.".into(), device("Alpha"), now));
+
+        let names: Vec<_> = table.list().into_iter().map(|d| d.name).collect();
+        assert_eq!(names, vec!["Alpha", "beta"]);
+
+        table.remove("a._transport._tcp.local.");
+        assert_eq!(table.list(), vec![device("beta")]);
+    }
+
+    #[test]
+    fn test_peer_table_keys_by_device_id() {
+        let mut table = PeerTable::default();
+        let now = Instant::now();
+        let laptop = |name: &str, id: &str| DiscoveredDevice {
+            id: id.to_string(),
+            ..device(name)
+        };
+        // 两台同名的笔记本各占一条
+        table.upsert("laptop-aaaaaa".into(), laptop("laptop", "a"), now);
+        table.upsert("laptop-bbbbbb".into(), laptop("laptop", "b"), now);
+        assert_eq!(table.list().len(), 2);
+
+        // 改名后以新实例名重新广播，替换旧的一条
+        assert!(table.upsert("work-aaaaaa".into(), laptop("work", "a"), now));
+        let mut names: Vec<_> = table.list().into_iter().map(|d| d.name).collect();
+        names.sort();
+        assert_eq!(names, vec!["laptop", "work"]);
+    }
+
+    #[test]
+    fn test_peer_table_expires_stale_peers() {
+        let mut table = PeerTable::default();
+        let start = Instant::now();
+        table.upsert("old".into(), device("old"), start);
+        table.upsert("fresh".into(), device("fresh"), start + PEER_TTL);
+
+        assert!(table.prune(start + PEER_TTL + Duration::from_secs(1)));
+        assert_eq!(table.list(), vec![device("fresh")]);
+    }
+}
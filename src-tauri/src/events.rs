@@ -0,0 +1,135 @@
+//! 进程内事件总线：传输进度、文件变更和设备发现的变化都发布到这里，
+//! 再由 `/api/events`（SSE）和 Tauri 事件推送给各个界面。
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::discovery::DiscoveredDevice;
+use crate::transfer::manager::TransferInfo;
+
+/// 订阅者落后超过这么多条事件时会收到 `resync`，需要重新拉取完整状态。
+const CAPACITY: usize = 256;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FsChangeKind {
+    Created,
+    Removed,
+    Renamed,
+}
+
+/// 通过 API 对共享目录做的修改。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FsChange {
+    pub kind: FsChangeKind,
+    pub path: String,
+    /// 重命名前的路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+}
+
+/// 序列化后只有负载本身，事件类型由 `name()` 单独给出（SSE 的 `event:` 字段 / Tauri 事件名）。
+#[derive(Serialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Event {
+    TransferStarted(TransferInfo),
+    TransferProgress(TransferInfo),
+    /// 暂停、继续、分块上传等待下一块等状态变化
+    TransferUpdated(TransferInfo),
+    TransferCompleted(TransferInfo),
+    /// 失败或被取消，见 `status`
+    TransferFailed(TransferInfo),
+    FsChanged(FsChange),
+    /// 在线对端列表有变化，负载为完整列表
+    PeersChanged(Vec<DiscoveredDevice>),
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::TransferStarted(_) => "transfer-started",
+            Event::TransferProgress(_) => "transfer-progress",
+            Event::TransferUpdated(_) => "transfer-updated",
+            Event::TransferCompleted(_) => "transfer-completed",
+            Event::TransferFailed(_) => "transfer-failed",
+            Event::FsChanged(_) => "fs-changed",
+            Event::PeersChanged(_) => "peers-changed",
+        }
+    }
+
+    /// `name()` 加序列化负载的逆过程，供客户端解析 SSE。
+    pub fn from_parts(name: &str, data: &str) -> Result<Self, String> {
+        let parse_err = |e: serde_json::Error| format!("Invalid {} event: {}", name, e);
+        let transfer = || serde_json::from_str::<TransferInfo>(data).map_err(parse_err);
+        Ok(match name {
+            "transfer-started" => Event::TransferStarted(transfer()?),
+            "transfer-progress" => Event::TransferProgress(transfer()?),
+            "transfer-updated" => Event::TransferUpdated(transfer()?),
+            "transfer-completed" => Event::TransferCompleted(transfer()?),
+            "transfer-failed" => Event::TransferFailed(transfer()?),
+            "fs-changed" => Event::FsChanged(serde_json::from_str(data).map_err(parse_err)?),
+            "peers-changed" => Event::PeersChanged(serde_json::from_str(data).map_err(parse_err)?),
+            _ => return Err(format!("Unknown event: {}", name)),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(CAPACITY).0,
+        }
+    }
+
+    /// 没有订阅者时事件直接丢弃。
+    pub fn publish(&self, event: Event) {
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publish_reaches_subscribers() {
+        let bus = EventBus::new();
+        bus.publish(Event::PeersChanged(Vec::new())); // 无订阅者，不报错
+
+        let mut rx = bus.subscribe();
+        bus.publish(Event::FsChanged(FsChange {
+            kind: FsChangeKind::Renamed,
+            path: "/a/new.txt".to_string(),
+            from: Some("/a/old.txt".to_string()),
+        }));
+
+        let event = rx.recv().await.unwrap();
+        assert_eq!(event.name(), "fs-changed");
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"kind": "renamed", "path": "/a/new.txt", "from": "/a/old.txt"})
+        );
+
+        let data = serde_json::to_string(&event).unwrap();
+        assert!(matches!(
+            Event::from_parts(event.name(), &data),
+            Ok(Event::FsChanged(change)) if change.from.as_deref() == Some("/a/old.txt")
+        ));
+        assert!(Event::from_parts("resync", "{}").is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! 设备身份：每次安装生成一个 UUID 和 Ed25519 密钥对，保存在
+//! `~/.transport/identity.json`。主机名和 IP 会变、会重名，配对、限速设置和
+//! 传输记录都以这里的 id 区分设备；指纹（公钥的 SHA-256）用于人工核对。
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Serialize, Deserialize)]
+struct StoredIdentity {
+    id: String,
+    /// Ed25519 私钥（32 字节种子），Base64
+    secret_key: String,
+}
+
+/// 本机身份，克隆后共享同一个密钥。
+#[derive(Clone)]
+pub struct Identity {
+    id: String,
+    key: Arc<SigningKey>,
+}
+
+impl std::fmt::Debug for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Identity")
+            .field("id", &self.id)
+            .field("fingerprint", &self.fingerprint())
+            .finish()
+    }
+}
+
+impl Identity {
+    /// 默认位置：`~/.transport/identity.json`，与配对记录放在一起，
+    /// 同一台机器上的桌面应用、`server` 和 `transport-cli` 共用。
+    pub fn default_path() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".transport")
+            .join("identity.json")
+    }
+
+    /// 新生成的身份，不写入文件。
+    pub fn generate() -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            key: Arc::new(SigningKey::generate(&mut OsRng)),
+        }
+    }
+
+    /// 读取 `path`，不存在时生成并保存。文件损坏时报错而不是重新生成，
+    /// 否则其他设备上的配对会悄悄失效。
+    pub fn load_or_create(path: &Path) -> io::Result<Self> {
+        match std::fs::read(path) {
+            Ok(json) => {
+                let stored: StoredIdentity = serde_json::from_slice(&json)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                Self::from_stored(stored)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let identity = Self::generate();
+                identity.save(path)?;
+                log::info!(
+                    "Created device identity {} ({})",
+                    identity.id,
+                    identity.fingerprint()
+                );
+                Ok(identity)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn from_stored(stored: StoredIdentity) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        uuid::Uuid::parse_str(&stored.id).map_err(|_| invalid("Invalid device id"))?;
+        let seed: [u8; 32] = BASE64
+            .decode(stored.secret_key.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| invalid("Invalid device key"))?;
+        Ok(Self {
+            id: stored.id,
+            key: Arc::new(SigningKey::from_bytes(&seed)),
+        })
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let stored = StoredIdentity {
+            id: self.id.clone(),
+            secret_key: BASE64.encode(self.key.to_bytes()),
+        };
+        let json = serde_json::to_vec_pretty(&stored).map_err(io::Error::other)?;
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        // 私钥只允许当前用户读取
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path)?;
+        io::Write::write_all(&mut file, &json)?;
+        file.sync_all()
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.key
+    }
+
+    /// 公钥，Base64
+    pub fn public_key(&self) -> String {
+        BASE64.encode(self.key.verifying_key().as_bytes())
+    }
+
+    pub fn fingerprint(&self) -> String {
+        fingerprint(self.key.verifying_key().as_bytes())
+    }
+
+    /// 签名，Base64
+    pub fn sign(&self, message: &[u8]) -> String {
+        BASE64.encode(self.key.sign(message).to_bytes())
+    }
+}
+
+/// 公钥的 SHA-256，小写十六进制。
+pub fn fingerprint(public_key: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(public_key))
+}
+
+/// 配对确认时签名的内容：固定前缀、被配对主机的 id 和指纹、请求 id，字段间以 NUL 分隔，
+/// 不同的字段组合不会拼出同一条消息。签名只对这一台主机的这一次配对有效，也不能挪作他用。
+pub fn pairing_message(host_id: &str, host_fingerprint: &str, request_id: &str) -> Vec<u8> {
+    [
+        PAIRING_CONTEXT,
+        host_id.as_bytes(),
+        host_fingerprint.as_bytes(),
+        request_id.as_bytes(),
+    ]
+    .join(&0u8)
+}
+
+const PAIRING_CONTEXT: &[u8] = b"transport-pair-v1";
+
+/// 解析 Base64 公钥。
+pub fn parse_public_key(public_key: &str) -> Option<VerifyingKey> {
+    let bytes: [u8; 32] = BASE64.decode(public_key.trim()).ok()?.try_into().ok()?;
+    VerifyingKey::from_bytes(&bytes).ok()
+}
+
+/// 校验 `signature`（Base64）是否为 `public_key` 对 `message` 的签名。
+pub fn verify(public_key: &VerifyingKey, message: &[u8], signature: &str) -> bool {
+    let Some(bytes) = BASE64
+        .decode(signature.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+    else {
+        return false;
+    };
+    public_key
+        .verify(message, &Signature::from_bytes(&bytes))
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_persisted_identity_is_stable() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("nested").join("identity.json");
+        let created = Identity::load_or_create(&path).unwrap();
+        let loaded = Identity::load_or_create(&path).unwrap();
+        assert_eq!(created.id(), loaded.id());
+        assert_eq!(created.fingerprint(), loaded.fingerprint());
+        assert_eq!(created.fingerprint().len(), 64);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::write(&path, b"{\"id\": \"x\", \"secret_key\": \"\"}").unwrap();
+        let err = Identity::load_or_create(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let identity = Identity::generate();
+        let public_key = parse_public_key(&identity.public_key()).unwrap();
+        assert_eq!(fingerprint(public_key.as_bytes()), identity.fingerprint());
+
+        let signature = identity.sign(b"request-1");
+        assert!(verify(&public_key, b"request-1", &signature));
+        assert!(!verify(&public_key, b"request-2", &signature));
+        assert!(!verify(&public_key, b"request-1", "not base64"));
+        let other = parse_public_key(&Identity::generate().public_key()).unwrap();
+        assert!(!verify(&other, b"request-1", &signature));
+    }
+
+    #[test]
+    fn test_pairing_message_separates_fields() {
+        assert_ne!(
+            pairing_message("ab", "c", "d"),
+            pairing_message("a", "bc", "d")
+        );
+        assert_ne!(
+            pairing_message("a", "b", "cd"),
+            pairing_message("a", "bc", "d")
+        );
+    }
+}
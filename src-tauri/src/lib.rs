@@ -1,8 +1,24 @@
+pub mod client;
+mod commands;
+pub mod discovery;
+pub mod events;
+pub mod identity;
+pub mod peer;
 pub mod server;
+pub mod settings;
 pub mod transfer;
 
-use tauri::async_runtime::spawn;
-use transfer::throttle::Throttle;
+use std::sync::Arc;
+
+use discovery::Discovery;
+use events::EventBus;
+use identity::Identity;
+use server::auth::{Pairing, PairingPrompt};
+use server::{AppState, PortFallback, ServerHandle, ServerOptions};
+use settings::SettingsStore;
+use tauri::async_runtime::{block_on, spawn};
+use tauri::{Emitter, Manager};
+use transfer::limiter::Throttles;
 
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -11,14 +27,84 @@ fn greet(name: &str) -> String {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![greet])
-        .setup(|_app| {
-            let throttle = Throttle::new(0); // 0 = unlimited
-            spawn(server::start_server(8090, throttle));
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            commands::get_devices,
+            commands::get_local_device_info,
+            commands::set_throttle_rate,
+            commands::get_throttle,
+            commands::list_transfers,
+            commands::pause_transfer,
+            commands::resume_transfer,
+            commands::cancel_transfer,
+            commands::clear_transfers,
+            commands::restart_server,
+            commands::pairing_identity,
+            commands::sign_pairing,
+            commands::peer_fetch,
+            commands::peer_abort,
+        ])
+        .setup(|app| {
+            let throttles = Throttles::unlimited();
+            let discovery = Discovery::new();
+            let identity = Identity::load_or_create(&Identity::default_path())?;
+
+            // 配对 PIN 发给前端窗口显示
+            let handle = app.handle().clone();
+            let pairing = Pairing::load(Pairing::default_store_path(), &identity).with_notifier(
+                Arc::new(move |prompt: &PairingPrompt| {
+                    let _ = handle.emit("pairing-request", prompt);
+                }),
+            );
+
+            // 服务端事件原样转发给前端窗口
+            let events = EventBus::new();
+            let mut receiver = events.subscribe();
+            let handle = app.handle().clone();
+            spawn(async move {
+                use tokio::sync::broadcast::error::RecvError;
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit(event.name(), &event);
+                        }
+                        Err(RecvError::Lagged(_)) => {
+                            let _ = handle.emit("resync", ());
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            // 命令和 HTTP 接口共用同一份状态，限速、传输队列等保持一致
+            let settings = SettingsStore::load(SettingsStore::default_path());
+            let state = block_on(AppState::new(
+                settings, throttles, discovery, pairing, events, identity,
+            ));
+            // 另一个实例占用了端口时换一个，设备信息和 mDNS 广播都用实际端口
+            let options = ServerOptions {
+                port_fallback: PortFallback::Any,
+                ..ServerOptions::default()
+            };
+            let server = block_on(ServerHandle::start(state.clone(), options))?;
+            app.manage(state);
+            app.manage(server);
+            app.manage(peer::Peers::default());
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // 退出前让进行中的传输有机会完成
+            if let tauri::RunEvent::Exit = event {
+                if let Some(server) = app.try_state::<ServerHandle>() {
+                    if let Err(e) = block_on(server.shutdown()) {
+                        log::error!("{}", e);
+                    }
+                }
+            }
+        });
 }
@@ -0,0 +1,257 @@
+//! 桌面应用访问启用了 HTTPS 的设备：WebView 无法固定自签名证书，前端把请求交给后端，
+//! 由固定了对端指纹的 [`TransportClient`] 发出，响应头作为返回值，正文分块推回前端。
+//! 本机启用 HTTPS 后也走这里（明文 `/api` 只返回 426）。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use futures_util::StreamExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::client::TransportClient;
+use crate::server::error::ApiError;
+
+/// 前端转交的请求。
+#[derive(Debug, Deserialize)]
+pub struct PeerRequest {
+    /// `host:port`
+    pub address: String,
+    /// 对端设备指纹，证书不符时请求失败
+    pub fingerprint: String,
+    pub method: String,
+    /// 以 `/api/` 开头，可以带查询串
+    pub path: String,
+    /// 包括 `Authorization`，令牌由前端管理
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PeerResponse {
+    /// 传给 [`Peers::abort`] 提前结束正文
+    pub id: u64,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+}
+
+/// 响应正文，以 `End` 或 `Error` 结束；被 [`Peers::abort`] 中止的请求不再推送。
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PeerBody {
+    Chunk { data: Vec<u8> },
+    End,
+    Error { message: String },
+}
+
+#[derive(Default)]
+pub struct Peers {
+    /// 按地址和指纹复用连接
+    clients: Mutex<HashMap<(String, String), TransportClient>>,
+    /// 正文还在传输的请求
+    pending: Arc<Mutex<HashMap<u64, CancellationToken>>>,
+    next_id: AtomicU64,
+}
+
+impl Peers {
+    /// 发出请求，收到响应头后返回；正文在后台逐块交给 `on_body`。
+    pub async fn fetch(
+        &self,
+        request: PeerRequest,
+        on_body: impl Fn(PeerBody) + Send + 'static,
+    ) -> Result<PeerResponse, ApiError> {
+        let route = request
+            .path
+            .strip_prefix("/api")
+            .filter(|route| route.starts_with('/'))
+            .ok_or_else(|| ApiError::bad_request("Only /api paths can be forwarded"))?;
+        let method = Method::from_bytes(request.method.as_bytes())
+            .map_err(|_| ApiError::bad_request(format!("Invalid method: {}", request.method)))?;
+        let mut headers = HeaderMap::new();
+        for (name, value) in &request.headers {
+            let invalid = || ApiError::bad_request(format!("Invalid header: {}", name));
+            headers.append(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?,
+                HeaderValue::from_str(value).map_err(|_| invalid())?,
+            );
+        }
+
+        let client = self.client(&request.address, &request.fingerprint);
+        let response = client
+            .forward(method, route, headers, request.body.map(Bytes::from))
+            .await
+            .map_err(|e| ApiError::internal(e.to_string()))?;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = CancellationToken::new();
+        self.pending.lock().unwrap().insert(id, cancel.clone());
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+
+        let pending = self.pending.clone();
+        let mut body = response.bytes_stream();
+        tokio::spawn(async move {
+            let pump = async move {
+                while let Some(chunk) = body.next().await {
+                    match chunk {
+                        Ok(data) => on_body(PeerBody::Chunk {
+                            data: data.to_vec(),
+                        }),
+                        Err(e) => {
+                            return on_body(PeerBody::Error {
+                                message: e.to_string(),
+                            })
+                        }
+                    }
+                }
+                on_body(PeerBody::End);
+            };
+            tokio::select! {
+                _ = pump => {}
+                _ = cancel.cancelled() => {}
+            }
+            pending.lock().unwrap().remove(&id);
+        });
+
+        Ok(PeerResponse {
+            id,
+            status,
+            headers,
+        })
+    }
+
+    /// 前端不再需要正文（请求被中止、事件流退订）时断开连接。
+    pub fn abort(&self, id: u64) {
+        if let Some(cancel) = self.pending.lock().unwrap().remove(&id) {
+            cancel.cancel();
+        }
+    }
+
+    fn client(&self, address: &str, fingerprint: &str) -> TransportClient {
+        let key = (address.to_string(), fingerprint.trim().to_lowercase());
+        self.clients
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| TransportClient::new(address).with_pinned_fingerprint(fingerprint))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::handlers::DeviceInfo;
+    use crate::server::{test_state, ServerHandle, ServerOptions};
+    use std::net::{IpAddr, Ipv4Addr};
+    use tempfile::tempdir;
+    use tokio::sync::mpsc;
+
+    fn request(address: &str, fingerprint: &str, path: &str) -> PeerRequest {
+        PeerRequest {
+            address: address.to_string(),
+            fingerprint: fingerprint.to_string(),
+            method: "GET".to_string(),
+            path: path.to_string(),
+            headers: vec![("Accept".to_string(), "application/json".to_string())],
+            body: None,
+        }
+    }
+
+    /// 收集正文直到 `End`。
+    async fn read_body(mut receiver: mpsc::UnboundedReceiver<PeerBody>) -> Vec<u8> {
+        let mut all = Vec::new();
+        loop {
+            match receiver.recv().await.unwrap() {
+                PeerBody::Chunk { data } => all.extend_from_slice(&data),
+                PeerBody::End => return all,
+                PeerBody::Error { message } => panic!("{}", message),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forwards_to_https_peer() {
+        let dir = tempdir().unwrap();
+        let state = test_state(dir.path());
+        state
+            .settings
+            .update(|s| {
+                s.port = 0;
+                s.https = true;
+            })
+            .await
+            .unwrap();
+        let options = ServerOptions {
+            bind: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            ..ServerOptions::default()
+        };
+        let server = ServerHandle::start(state, options).await.unwrap();
+        let address = server.local_addr().to_string();
+        let fingerprint = server.state().identity.fingerprint();
+        let peers = Peers::default();
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let response = peers
+            .fetch(
+                request(&address, &fingerprint, "/api/device/info"),
+                move |body| {
+                    let _ = sender.send(body);
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert!(response
+            .headers
+            .iter()
+            .any(|(name, value)| name == "content-type" && value == "application/json"));
+        let info: DeviceInfo = serde_json::from_slice(&read_body(receiver).await).unwrap();
+        assert!(info.https);
+        assert_eq!(info.fingerprint, fingerprint);
+
+        // 事件流不会自己结束，中止后不再推送
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let response = peers
+            .fetch(
+                request(&address, &fingerprint, "/api/events"),
+                move |body| {
+                    let _ = sender.send(body);
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status, 200);
+        peers.abort(response.id);
+        while let Some(body) = receiver.recv().await {
+            assert!(matches!(body, PeerBody::Chunk { .. }));
+        }
+        assert!(peers.pending.lock().unwrap().is_empty());
+
+        // 证书不是这台设备签发的
+        let impostor = crate::identity::Identity::generate().fingerprint();
+        assert!(peers
+            .fetch(request(&address, &impostor, "/api/device/info"), |_| {})
+            .await
+            .is_err());
+
+        // 只转发 API
+        let err = peers
+            .fetch(request(&address, &fingerprint, "/app/"), |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        server.shutdown().await.unwrap();
+    }
+}
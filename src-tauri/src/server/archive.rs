@@ -0,0 +1,727 @@
+//! 目录打包下载：按需遍历选中的文件/目录，边读边输出 zip（store 模式，必要时 zip64）
+//! 或 tar 流，不落临时文件。
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use axum::body::Body;
+use bytes::Bytes;
+use chrono::{Datelike, Timelike};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+
+use crate::transfer::limiter::Pacer;
+use crate::transfer::manager::TransferHandle;
+
+const CHUNK_SIZE: usize = 512 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    #[default]
+    Zip,
+    Tar,
+}
+
+impl ArchiveFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Tar => "tar",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            ArchiveFormat::Tar => "application/x-tar",
+        }
+    }
+}
+
+/// 归档中的一项。
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    /// 磁盘上的真实路径
+    pub source: PathBuf,
+    /// 归档内的相对路径，`/` 分隔，目录以 `/` 结尾
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// Unix 权限位（不含文件类型）
+    pub mode: u32,
+}
+
+/// 递归收集选中路径下的全部条目，每个选中项以自身名字作为归档内的顶层。
+/// 不跟随符号链接，避免把共享目录之外的内容打进包里。读不了的目录、遍历途中被删掉的
+/// 条目记日志后跳过，与输出阶段一致，不让一个坏条目拖垮整个下载。
+pub async fn collect_entries(selected: &[PathBuf]) -> Vec<ArchiveEntry> {
+    let mut entries = Vec::new();
+    let mut top_names: Vec<String> = Vec::new();
+
+    for path in selected {
+        let base = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "files".to_string());
+        // 多选时不同目录下可能有同名项
+        let mut name = base.clone();
+        let mut n = 1;
+        while top_names.contains(&name) {
+            n += 1;
+            name = format!("{} ({})", base, n);
+        }
+        top_names.push(name.clone());
+
+        let mut stack = vec![(path.clone(), name)];
+        while let Some((source, name)) = stack.pop() {
+            let metadata = match tokio::fs::symlink_metadata(&source).await {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    log::warn!("Skipping {} in archive: {}", source.display(), e);
+                    continue;
+                }
+            };
+            if metadata.file_type().is_symlink() {
+                continue;
+            }
+            let is_dir = metadata.is_dir();
+
+            if is_dir {
+                let children = list_dir(&source).await.unwrap_or_else(|(children, e)| {
+                    log::warn!("Failed to list {} for archive: {}", source.display(), e);
+                    children
+                });
+                // 倒序入栈，输出时按名字顺序
+                for child in children.into_iter().rev() {
+                    stack.push((source.join(&child), format!("{}/{}", name, child)));
+                }
+            }
+
+            entries.push(ArchiveEntry {
+                name: if is_dir { format!("{}/", name) } else { name },
+                is_dir,
+                size: if is_dir { 0 } else { metadata.len() },
+                modified: metadata.modified().ok(),
+                mode: permission_bits(&metadata, is_dir),
+                source,
+            });
+        }
+    }
+
+    entries
+}
+
+/// 目录下的名字，按名字排序。出错时连同已经读到的部分一起返回。
+async fn list_dir(dir: &Path) -> Result<Vec<String>, (Vec<String>, std::io::Error)> {
+    let mut children = Vec::new();
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) => return Err((children, e)),
+    };
+    loop {
+        match entries.next_entry().await {
+            Ok(Some(child)) => children.push(child.file_name().to_string_lossy().to_string()),
+            Ok(None) => break,
+            Err(e) => return Err((children, e)),
+        }
+    }
+    children.sort();
+    Ok(children)
+}
+
+#[cfg(unix)]
+fn permission_bits(metadata: &std::fs::Metadata, _is_dir: bool) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn permission_bits(_metadata: &std::fs::Metadata, is_dir: bool) -> u32 {
+    if is_dir {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+/// 依次输出每个条目的头部和内容，文件内容以 512KB 分块读取并经过限速器。
+/// 打不开的文件直接跳过；读取中途文件变短时 tar 以零补齐，zip 以实际长度为准。
+pub fn archive_body(
+    entries: Vec<ArchiveEntry>,
+    format: ArchiveFormat,
+    pacer: Pacer,
+    mut transfer: TransferHandle,
+) -> Body {
+    let stream = async_stream::stream! {
+        let mut zip = ZipWriter::default();
+        let mut buf = vec![0u8; CHUNK_SIZE];
+
+        for entry in entries {
+            let file = if entry.is_dir {
+                None
+            } else {
+                match tokio::fs::File::open(&entry.source).await {
+                    Ok(f) => Some(f),
+                    Err(e) => {
+                        log::warn!("Skipping {} in archive: {}", entry.source.display(), e);
+                        continue;
+                    }
+                }
+            };
+
+            let header = match format {
+                ArchiveFormat::Zip => zip.begin(&entry),
+                ArchiveFormat::Tar => tar_header(&entry),
+            };
+            yield Ok::<_, std::io::Error>(Bytes::from(header));
+
+            let mut crc = crc32fast::Hasher::new();
+            let mut written = 0u64;
+            if let Some(file) = file {
+                let mut reader = file.take(entry.size);
+                loop {
+                    let n = match reader.read(&mut buf).await {
+                        Ok(0) => break,
+                        Ok(n) => n,
+                        Err(e) => {
+                            yield Err(e);
+                            return;
+                        }
+                    };
+                    crc.update(&buf[..n]);
+                    written += n as u64;
+                    pacer.consume(n).await;
+                    if let Err(e) = transfer.advance(n).await {
+                        yield Err(e.into());
+                        return;
+                    }
+                    yield Ok(Bytes::copy_from_slice(&buf[..n]));
+                }
+            }
+
+            match format {
+                ArchiveFormat::Zip => {
+                    yield Ok(Bytes::from(zip.finish(crc.finalize(), written)));
+                }
+                ArchiveFormat::Tar => {
+                    let missing = (entry.size - written) as usize;
+                    let padding = missing + tar_padding(entry.size);
+                    if padding > 0 {
+                        yield Ok(Bytes::from(vec![0u8; padding]));
+                    }
+                }
+            }
+        }
+
+        let trailer = match format {
+            ArchiveFormat::Zip => zip.end(),
+            ArchiveFormat::Tar => vec![0u8; 1024],
+        };
+        yield Ok(Bytes::from(trailer));
+        transfer.complete();
+    };
+    Body::from_stream(stream)
+}
+
+// --- zip ---
+
+const ZIP_LOCAL_SIG: u32 = 0x0403_4b50;
+const ZIP_DESCRIPTOR_SIG: u32 = 0x0807_4b50;
+const ZIP_CENTRAL_SIG: u32 = 0x0201_4b50;
+const ZIP64_EOCD_SIG: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x0706_4b50;
+const ZIP_EOCD_SIG: u32 = 0x0605_4b50;
+
+/// 通用标志位：bit 3 = 长度和 CRC 写在数据描述符里，bit 11 = 文件名为 UTF-8
+const FLAG_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+/// 版本号：2.0 基础格式，4.5 = zip64；高字节 3 = Unix（外部属性为 mode）
+const VERSION_BASE: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+const VERSION_MADE_BY: u16 = (3 << 8) | VERSION_ZIP64;
+
+const U32_MAX: u64 = 0xFFFF_FFFF;
+
+struct PendingEntry {
+    name: String,
+    offset: u64,
+    time: u16,
+    date: u16,
+    is_dir: bool,
+    mode: u32,
+    /// 本地头里是否带了 zip64 扩展字段（决定数据描述符的长度字段宽度）
+    zip64: bool,
+}
+
+/// 流式 zip 编码器：内容不压缩，CRC 和长度在写完数据后通过数据描述符给出。
+#[derive(Default)]
+struct ZipWriter {
+    offset: u64,
+    central: Vec<u8>,
+    count: u64,
+    pending: Option<PendingEntry>,
+}
+
+impl ZipWriter {
+    /// 本地文件头。
+    fn begin(&mut self, entry: &ArchiveEntry) -> Vec<u8> {
+        let (time, date) = dos_datetime(entry.modified);
+        let zip64 = entry.size >= U32_MAX;
+        let flags = entry_flags(entry.is_dir);
+
+        let mut buf = Vec::with_capacity(30 + entry.name.len() + 20);
+        put_u32(&mut buf, ZIP_LOCAL_SIG);
+        put_u16(&mut buf, if zip64 { VERSION_ZIP64 } else { VERSION_BASE });
+        put_u16(&mut buf, flags);
+        put_u16(&mut buf, 0); // store
+        put_u16(&mut buf, time);
+        put_u16(&mut buf, date);
+        put_u32(&mut buf, 0); // crc，见数据描述符
+        let size_field = if zip64 { U32_MAX as u32 } else { 0 };
+        put_u32(&mut buf, size_field);
+        put_u32(&mut buf, size_field);
+        put_u16(&mut buf, entry.name.len() as u16);
+        put_u16(&mut buf, if zip64 { 20 } else { 0 });
+        buf.extend_from_slice(entry.name.as_bytes());
+        if zip64 {
+            put_u16(&mut buf, 0x0001);
+            put_u16(&mut buf, 16);
+            put_u64(&mut buf, 0);
+            put_u64(&mut buf, 0);
+        }
+
+        self.pending = Some(PendingEntry {
+            name: entry.name.clone(),
+            offset: self.offset,
+            time,
+            date,
+            is_dir: entry.is_dir,
+            mode: entry.mode,
+            zip64,
+        });
+        self.offset += buf.len() as u64;
+        buf
+    }
+
+    /// 数据写完后的数据描述符，同时记下中央目录项。
+    fn finish(&mut self, crc: u32, size: u64) -> Vec<u8> {
+        let Some(entry) = self.pending.take() else {
+            return Vec::new();
+        };
+        self.offset += size;
+
+        let mut buf = Vec::new();
+        if !entry.is_dir {
+            put_u32(&mut buf, ZIP_DESCRIPTOR_SIG);
+            put_u32(&mut buf, crc);
+            if entry.zip64 {
+                put_u64(&mut buf, size);
+                put_u64(&mut buf, size);
+            } else {
+                put_u32(&mut buf, size as u32);
+                put_u32(&mut buf, size as u32);
+            }
+        }
+        self.offset += buf.len() as u64;
+
+        // 中央目录里只有超出 32 位的字段才放进 zip64 扩展
+        let mut extra = Vec::new();
+        if size >= U32_MAX {
+            put_u64(&mut extra, size);
+            put_u64(&mut extra, size);
+        }
+        if entry.offset >= U32_MAX {
+            put_u64(&mut extra, entry.offset);
+        }
+        let version = if entry.zip64 || !extra.is_empty() {
+            VERSION_ZIP64
+        } else {
+            VERSION_BASE
+        };
+        let extra_len = if extra.is_empty() { 0 } else { extra.len() + 4 };
+
+        let file_type = if entry.is_dir { 0o040000 } else { 0o100000 };
+        let dos_attrs = if entry.is_dir { 0x10 } else { 0 };
+        let c = &mut self.central;
+        put_u32(c, ZIP_CENTRAL_SIG);
+        put_u16(c, VERSION_MADE_BY);
+        put_u16(c, version);
+        put_u16(c, entry_flags(entry.is_dir));
+        put_u16(c, 0);
+        put_u16(c, entry.time);
+        put_u16(c, entry.date);
+        put_u32(c, crc);
+        put_u32(c, size.min(U32_MAX) as u32);
+        put_u32(c, size.min(U32_MAX) as u32);
+        put_u16(c, entry.name.len() as u16);
+        put_u16(c, extra_len as u16);
+        put_u16(c, 0); // 注释
+        put_u16(c, 0); // 起始磁盘
+        put_u16(c, 0); // 内部属性
+        put_u32(c, ((file_type | entry.mode) << 16) | dos_attrs);
+        put_u32(c, entry.offset.min(U32_MAX) as u32);
+        c.extend_from_slice(entry.name.as_bytes());
+        if !extra.is_empty() {
+            put_u16(c, 0x0001);
+            put_u16(c, extra.len() as u16);
+            c.extend_from_slice(&extra);
+        }
+        self.count += 1;
+
+        buf
+    }
+
+    /// 中央目录和结束记录；条目数或偏移超出范围时附带 zip64 结束记录。
+    fn end(self) -> Vec<u8> {
+        let cd_offset = self.offset;
+        let cd_size = self.central.len() as u64;
+        let mut buf = self.central;
+
+        let needs_zip64 = self.count >= 0xFFFF || cd_offset >= U32_MAX || cd_size >= U32_MAX;
+        if needs_zip64 {
+            let record_offset = cd_offset + cd_size;
+            put_u32(&mut buf, ZIP64_EOCD_SIG);
+            put_u64(&mut buf, 44);
+            put_u16(&mut buf, VERSION_MADE_BY);
+            put_u16(&mut buf, VERSION_ZIP64);
+            put_u32(&mut buf, 0);
+            put_u32(&mut buf, 0);
+            put_u64(&mut buf, self.count);
+            put_u64(&mut buf, self.count);
+            put_u64(&mut buf, cd_size);
+            put_u64(&mut buf, cd_offset);
+
+            put_u32(&mut buf, ZIP64_LOCATOR_SIG);
+            put_u32(&mut buf, 0);
+            put_u64(&mut buf, record_offset);
+            put_u32(&mut buf, 1);
+        }
+
+        put_u32(&mut buf, ZIP_EOCD_SIG);
+        put_u16(&mut buf, 0);
+        put_u16(&mut buf, 0);
+        put_u16(&mut buf, self.count.min(0xFFFF) as u16);
+        put_u16(&mut buf, self.count.min(0xFFFF) as u16);
+        put_u32(&mut buf, cd_size.min(U32_MAX) as u32);
+        put_u32(&mut buf, cd_offset.min(U32_MAX) as u32);
+        put_u16(&mut buf, 0);
+        buf
+    }
+}
+
+fn entry_flags(is_dir: bool) -> u16 {
+    if is_dir {
+        FLAG_UTF8
+    } else {
+        FLAG_UTF8 | FLAG_DESCRIPTOR
+    }
+}
+
+/// MS-DOS 日期时间（本地时区，2 秒精度，最早 1980-01-01）。
+fn dos_datetime(modified: Option<SystemTime>) -> (u16, u16) {
+    let dt: chrono::DateTime<chrono::Local> = modified.unwrap_or(SystemTime::UNIX_EPOCH).into();
+    if dt.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let time = (dt.hour() << 11) | (dt.minute() << 5) | (dt.second() / 2);
+    let date = (((dt.year() - 1980) as u32) << 9) | (dt.month() << 5) | dt.day();
+    (time as u16, date as u16)
+}
+
+fn put_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, v: u64) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+// --- tar ---
+
+const TAR_BLOCK: usize = 512;
+/// ustar 的 12 字节八进制长度字段上限（8 GiB）
+const TAR_MAX_OCTAL_SIZE: u64 = 0o77777777777;
+
+fn tar_padding(size: u64) -> usize {
+    (TAR_BLOCK - (size % TAR_BLOCK as u64) as usize) % TAR_BLOCK
+}
+
+/// ustar 头部；路径放不进 name/prefix 字段或文件超过 8 GiB 时，前面再加一个 pax 扩展头。
+fn tar_header(entry: &ArchiveEntry) -> Vec<u8> {
+    let mtime = entry
+        .modified
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let split = split_ustar_path(&entry.name);
+    let large = entry.size > TAR_MAX_OCTAL_SIZE;
+
+    let mut out = Vec::new();
+    if split.is_none() || large {
+        let mut records = Vec::new();
+        if split.is_none() {
+            records.extend(pax_record("path", &entry.name));
+        }
+        if large {
+            records.extend(pax_record("size", &entry.size.to_string()));
+        }
+        let pax_name = format!("PaxHeaders/{}", truncate_utf8(&entry.name, 80));
+        out.extend(ustar_block(
+            (&pax_name, ""),
+            0o644,
+            records.len() as u64,
+            mtime,
+            b'x',
+        ));
+        let len = records.len() as u64;
+        out.extend(records);
+        out.resize(out.len() + tar_padding(len), 0);
+    }
+
+    let fallback = (truncate_utf8(&entry.name, 100), "");
+    let (name, prefix) = split.unwrap_or(fallback);
+    let size = if entry.is_dir || large { 0 } else { entry.size };
+    let typeflag = if entry.is_dir { b'5' } else { b'0' };
+    out.extend(ustar_block(
+        (name, prefix),
+        entry.mode,
+        size,
+        mtime,
+        typeflag,
+    ));
+    out
+}
+
+fn ustar_block(
+    (name, prefix): (&str, &str),
+    mode: u32,
+    size: u64,
+    mtime: u64,
+    typeflag: u8,
+) -> [u8; TAR_BLOCK] {
+    let mut block = [0u8; TAR_BLOCK];
+    block[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut block[100..108], mode as u64);
+    write_octal(&mut block[108..116], 0);
+    write_octal(&mut block[116..124], 0);
+    write_octal(&mut block[124..136], size);
+    write_octal(&mut block[136..148], mtime);
+    block[156] = typeflag;
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+    block[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    // 校验和按校验和字段全为空格计算
+    block[148..156].fill(b' ');
+    let sum: u32 = block.iter().map(|&b| b as u32).sum();
+    write_octal(&mut block[148..155], sum as u64);
+    block
+}
+
+/// 以 NUL 结尾的定宽八进制数字。
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    let digits = &digits.as_bytes()[digits.len() - (field.len() - 1)..];
+    field[..digits.len()].copy_from_slice(digits);
+    field[digits.len()] = 0;
+}
+
+/// 把路径拆成 ustar 的 (name ≤ 100, prefix ≤ 155)，在 `/` 处切分。
+fn split_ustar_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= 100 {
+        return Some((path, ""));
+    }
+    // 目录名末尾的 `/` 不能作为切分点，否则 name 为空
+    let trimmed = path.strip_suffix('/').unwrap_or(path);
+    trimmed
+        .match_indices('/')
+        .map(|(i, _)| i)
+        .filter(|&i| i <= 155 && path.len() - i - 1 <= 100)
+        .map(|i| (&path[i + 1..], &path[..i]))
+        .next()
+}
+
+/// pax 记录 `"<len> <key>=<value>\n"`，len 包含自身的位数。
+fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    let body = format!(" {}={}\n", key, value);
+    let mut len = body.len() + 1;
+    while len.to_string().len() + body.len() > len {
+        len += 1;
+    }
+    format!("{}{}", len, body).into_bytes()
+}
+
+fn truncate_utf8(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer::limiter::Limiter;
+    use crate::transfer::manager::{Direction, Peer, TransferManager};
+    use futures_util::StreamExt;
+    use std::fs;
+    use std::io::Read;
+    use tempfile::tempdir;
+
+    async fn render(entries: Vec<ArchiveEntry>, format: ArchiveFormat) -> Vec<u8> {
+        let transfer = TransferManager::default().begin(
+            Direction::Download,
+            Peer::default(),
+            String::new(),
+            None,
+        );
+        let mut stream = archive_body(
+            entries,
+            format,
+            Limiter::new(0).pacer(Peer::default()),
+            transfer,
+        )
+        .into_data_stream();
+        let mut out = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            out.extend_from_slice(&chunk.unwrap());
+        }
+        out
+    }
+
+    fn sample_tree() -> tempfile::TempDir {
+        let dir = tempdir().unwrap();
+        let photos = dir.path().join("photos");
+        fs::create_dir_all(photos.join("2024")).unwrap();
+        fs::write(photos.join("a.jpg"), b"jpeg-bytes").unwrap();
+        fs::write(photos.join("2024").join("b.jpg"), vec![7u8; 70_000]).unwrap();
+        fs::write(dir.path().join("notes.txt"), b"hello").unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_zip_round_trip() {
+        let dir = sample_tree();
+        let entries =
+            collect_entries(&[dir.path().join("photos"), dir.path().join("notes.txt")]).await;
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "photos/",
+                "photos/2024/",
+                "photos/2024/b.jpg",
+                "photos/a.jpg",
+                "notes.txt"
+            ]
+        );
+
+        let bytes = render(entries, ArchiveFormat::Zip).await;
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.len(), 5);
+
+        let mut content = Vec::new();
+        archive
+            .by_name("photos/2024/b.jpg")
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, vec![7u8; 70_000]);
+        assert!(archive.by_name("photos/2024/").unwrap().is_dir());
+
+        let mut text = String::new();
+        archive
+            .by_name("notes.txt")
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "hello");
+    }
+
+    #[tokio::test]
+    async fn test_tar_round_trip_with_long_path() {
+        let dir = tempdir().unwrap();
+        let deep = dir
+            .path()
+            .join("root")
+            .join("x".repeat(90))
+            .join("y".repeat(90));
+        fs::create_dir_all(&deep).unwrap();
+        let long_name = format!("{}.txt", "z".repeat(120));
+        fs::write(deep.join(&long_name), b"deep").unwrap();
+
+        let entries = collect_entries(&[dir.path().join("root")]).await;
+        let bytes = render(entries, ArchiveFormat::Tar).await;
+        assert_eq!(bytes.len() % TAR_BLOCK, 0);
+
+        let mut archive = tar::Archive::new(std::io::Cursor::new(bytes));
+        let mut found = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().to_string();
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            found.push((path, content));
+        }
+
+        let expected = format!("root/{}/{}/{}", "x".repeat(90), "y".repeat(90), long_name);
+        assert!(found.iter().any(|(p, c)| *p == expected && c == "deep"));
+        assert_eq!(found.len(), 4);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlinks_are_not_followed() {
+        let dir = sample_tree();
+        let secret = tempdir().unwrap();
+        fs::write(secret.path().join("secret.txt"), b"nope").unwrap();
+        std::os::unix::fs::symlink(secret.path(), dir.path().join("photos").join("link")).unwrap();
+
+        let entries = collect_entries(&[dir.path().join("photos")]).await;
+        assert!(entries.iter().all(|e| !e.name.contains("link")));
+    }
+
+    #[tokio::test]
+    async fn test_missing_entries_are_skipped() {
+        let dir = sample_tree();
+        let entries =
+            collect_entries(&[dir.path().join("notes.txt"), dir.path().join("deleted.txt")]).await;
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["notes.txt"]);
+    }
+
+    #[test]
+    fn test_zip64_end_record_for_large_offsets() {
+        let mut zip = ZipWriter::default();
+        let entry = ArchiveEntry {
+            source: PathBuf::from("/big.bin"),
+            name: "big.bin".to_string(),
+            is_dir: false,
+            size: 5 << 30,
+            modified: None,
+            mode: 0o644,
+        };
+        let header = zip.begin(&entry);
+        // 本地头带 zip64 扩展字段，长度字段为 0xFFFFFFFF
+        assert_eq!(&header[18..26], &[0xFF; 8]);
+        let descriptor = zip.finish(0, entry.size);
+        assert_eq!(descriptor.len(), 24);
+
+        let end = zip.end();
+        let locator_sig = ZIP64_LOCATOR_SIG.to_le_bytes();
+        assert!(end.windows(4).any(|w| w == locator_sig));
+        // 传统结束记录中的偏移被标记为 0xFFFFFFFF
+        assert_eq!(&end[end.len() - 6..end.len() - 2], &[0xFF; 4]);
+    }
+}
@@ -0,0 +1,942 @@
+//! 设备配对与 API 鉴权。
+//!
+//! 新客户端先 `POST /api/pair/request`，主机在窗口或控制台显示一个 6 位 PIN；
+//! 用户在客户端输入 PIN 调用 `POST /api/pair/confirm` 后获得长期有效的设备令牌。
+//! 之后所有 `/api/*` 请求都要带 `Authorization: Bearer <token>`（浏览器模式下
+//! 同时下发 HttpOnly cookie，方便 `<a download>` 直接下载；启用 HTTPS 时带 `Secure`）。
+//! 本机发起的请求直接放行，但 Host 和 Origin 必须也指向本机，见 [`is_local_request`]。
+//!
+//! 带有设备身份（`identity` 模块）的客户端在请求时附上设备 id 和公钥，确认时用私钥
+//! 签名 [`identity::pairing_message`]（包含本机 id、指纹和 `request_id`）；配对记录以
+//! 这个 id 为键，重新配对会替换原来的令牌而不是新增一条。
+//!
+//! 输错 PIN 按来源 IP 和全局分别累计，超过额度后暂时锁定。全局额度挡住换着 IP 猜的攻击者，
+//! 代价是局域网里任何未配对的主机都能故意输错，让其他设备一段时间内（最长一小时）无法配对。
+//! 本机发起的配对不计入也不受全局锁定影响，被锁住时仍可以在主机上完成配对。
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, FromRequestParts, Request, State};
+use axum::http::request::Parts;
+use axum::http::uri::Authority;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use ed25519_dalek::VerifyingKey;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use super::error::{ApiError, ErrorCode};
+use super::{network, AppState};
+use crate::identity::{self, Identity};
+use crate::transfer::manager::Peer;
+
+pub const TOKEN_COOKIE: &str = "transport_token";
+
+const PIN_TTL: Duration = Duration::from_secs(120);
+const MAX_PIN_ATTEMPTS: u8 = 5;
+const MAX_PENDING: usize = 16;
+
+/// 同一来源 IP 累计输错多少次 PIN 后开始锁定。请求用完次数后可以再开一个新的，
+/// 所以次数要按来源和全局累计，而不是只按请求计。
+const IP_FAILURE_BUDGET: u32 = 5;
+/// 所有来源合计，攻击者换 IP 也绕不过
+const GLOBAL_FAILURE_BUDGET: u32 = 20;
+/// 第一次锁定的时长，之后每输错一次翻倍
+const LOCKOUT_BASE: Duration = Duration::from_secs(30);
+const LOCKOUT_MAX: Duration = Duration::from_secs(3600);
+/// 这么久没有再输错就清零
+const FAILURE_RESET: Duration = Duration::from_secs(3600);
+
+/// 已配对设备（持久化，只保存令牌的哈希）。
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PairedDevice {
+    /// 对端的设备 id；旧客户端没有身份时为配对时生成的随机 id
+    pub id: String,
+    pub name: String,
+    pub paired_at: u64,
+    /// 对端公钥的指纹，没有身份时为空
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub fingerprint: Option<String>,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    token_hash: String,
+}
+
+/// 交给主机界面显示的配对提示。
+#[derive(Serialize, Clone, Debug)]
+pub struct PairingPrompt {
+    pub request_id: String,
+    pub device_name: String,
+    pub pin: String,
+}
+
+/// 请求方身份，由鉴权中间件放入请求扩展。
+#[derive(Clone, Debug, PartialEq)]
+pub enum Caller {
+    /// 来自本机（回环或本机网卡地址）
+    Local,
+    /// 已配对设备的 id
+    Device(String),
+}
+
+/// 对端在配对请求里声明的身份。
+#[derive(Clone, Debug)]
+pub struct DeviceClaim {
+    pub id: String,
+    pub public_key: VerifyingKey,
+}
+
+#[derive(Debug)]
+pub enum PairingError {
+    NotFound,
+    Expired,
+    WrongPin {
+        remaining: u8,
+    },
+    /// 声明了身份但签名缺失或不对
+    BadSignature,
+    /// 该 id 已经以另一把公钥配对过
+    IdentityMismatch,
+    TooManyRequests,
+    /// 输错太多次，来源或全局被暂时锁定
+    LockedOut {
+        retry_after: Duration,
+    },
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for PairingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PairingError::NotFound => write!(f, "Pairing request not found"),
+            PairingError::Expired => write!(f, "Pairing request expired"),
+            PairingError::WrongPin { remaining } => {
+                write!(f, "Wrong PIN, {} attempts remaining", remaining)
+            }
+            PairingError::BadSignature => write!(f, "Missing or invalid identity signature"),
+            PairingError::IdentityMismatch => {
+                write!(f, "Device id is already paired with a different key")
+            }
+            PairingError::TooManyRequests => write!(f, "Too many pending pairing requests"),
+            PairingError::LockedOut { retry_after } => write!(
+                f,
+                "Too many failed pairing attempts, retry in {} seconds",
+                retry_after.as_secs().max(1)
+            ),
+            PairingError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<PairingError> for ApiError {
+    fn from(e: PairingError) -> Self {
+        let code = match e {
+            PairingError::NotFound => ErrorCode::NotFound,
+            PairingError::Expired => ErrorCode::Expired,
+            PairingError::WrongPin { .. } | PairingError::BadSignature => ErrorCode::Forbidden,
+            PairingError::IdentityMismatch => ErrorCode::Conflict,
+            PairingError::TooManyRequests | PairingError::LockedOut { .. } => {
+                ErrorCode::TooManyRequests
+            }
+            PairingError::Io(e) => return e.into(),
+        };
+        ApiError::new(code, e.to_string())
+    }
+}
+
+struct PendingPairing {
+    device_name: String,
+    claim: Option<DeviceClaim>,
+    pin: String,
+    expires_at: Instant,
+    attempts: u8,
+}
+
+/// 输错 PIN 的计数。达到额度后每输错一次就锁定一段时间，时长指数增长。
+#[derive(Default)]
+struct FailureBudget {
+    failures: u32,
+    last_failure: Option<Instant>,
+    locked_until: Option<Instant>,
+}
+
+impl FailureBudget {
+    fn locked(&self, now: Instant) -> Option<Duration> {
+        self.locked_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    fn fail(&mut self, now: Instant, budget: u32) {
+        if self.expired(now) {
+            self.failures = 0;
+        }
+        self.failures += 1;
+        self.last_failure = Some(now);
+        if self.failures >= budget {
+            let doublings = (self.failures - budget).min(16);
+            let lockout = (LOCKOUT_BASE * 2u32.pow(doublings)).min(LOCKOUT_MAX);
+            self.locked_until = Some(now + lockout);
+        }
+    }
+
+    fn expired(&self, now: Instant) -> bool {
+        self.last_failure
+            .is_some_and(|at| now.duration_since(at) >= FAILURE_RESET)
+    }
+}
+
+#[derive(Default)]
+struct Lockouts {
+    global: FailureBudget,
+    by_ip: HashMap<IpAddr, FailureBudget>,
+}
+
+impl Lockouts {
+    fn check(&self, source: IpAddr, now: Instant) -> Result<(), PairingError> {
+        let global = if network::is_local_ip(source) {
+            None
+        } else {
+            self.global.locked(now)
+        };
+        let source = self.by_ip.get(&source).and_then(|b| b.locked(now));
+        match source.max(global) {
+            Some(retry_after) => Err(PairingError::LockedOut { retry_after }),
+            None => Ok(()),
+        }
+    }
+
+    fn fail(&mut self, source: IpAddr, now: Instant) {
+        if !network::is_local_ip(source) {
+            self.global.fail(now, GLOBAL_FAILURE_BUDGET);
+        }
+        // 换着 IP 猜的攻击者不能把表撑大
+        self.by_ip
+            .retain(|_, b| !b.expired(now) || b.locked(now).is_some());
+        self.by_ip
+            .entry(source)
+            .or_default()
+            .fail(now, IP_FAILURE_BUDGET);
+    }
+}
+
+pub type PinNotifier = Arc<dyn Fn(&PairingPrompt) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Pairing {
+    store_path: PathBuf,
+    /// 本机的设备 id 和指纹，配对签名里要包含
+    host_id: String,
+    host_fingerprint: String,
+    devices: Arc<Mutex<Vec<PairedDevice>>>,
+    pending: Arc<Mutex<HashMap<String, PendingPairing>>>,
+    lockouts: Arc<Mutex<Lockouts>>,
+    notifier: PinNotifier,
+}
+
+impl Pairing {
+    /// 从 `store_path` 加载已配对设备；默认把 PIN 打印到控制台。
+    /// `host` 是本机身份，对方的配对签名必须针对它。
+    pub fn load(store_path: PathBuf, host: &Identity) -> Self {
+        let devices = std::fs::read(&store_path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default();
+
+        Self {
+            store_path,
+            host_id: host.id().to_string(),
+            host_fingerprint: host.fingerprint(),
+            devices: Arc::new(Mutex::new(devices)),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            lockouts: Arc::new(Mutex::new(Lockouts::default())),
+            notifier: Arc::new(|prompt: &PairingPrompt| {
+                println!(
+                    "Pairing request from \"{}\" — PIN: {}",
+                    prompt.device_name, prompt.pin
+                );
+            }),
+        }
+    }
+
+    /// 默认存储位置：`~/.transport/paired_devices.json`
+    pub fn default_store_path() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".transport")
+            .join("paired_devices.json")
+    }
+
+    /// 替换 PIN 的展示方式（例如 Tauri 中发给前端窗口）。
+    pub fn with_notifier(mut self, notifier: PinNotifier) -> Self {
+        self.notifier = notifier;
+        self
+    }
+
+    /// `source` 是请求方的 IP，被锁定的来源不能发起新的配对。
+    pub async fn request(
+        &self,
+        device_name: &str,
+        claim: Option<DeviceClaim>,
+        source: IpAddr,
+    ) -> Result<String, PairingError> {
+        let now = Instant::now();
+        self.lockouts.lock().await.check(source, now)?;
+        let mut pending = self.pending.lock().await;
+        pending.retain(|_, p| p.expires_at > now);
+        if pending.len() >= MAX_PENDING {
+            return Err(PairingError::TooManyRequests);
+        }
+
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let pin = random_pin();
+        let prompt = PairingPrompt {
+            request_id: request_id.clone(),
+            device_name: device_name.to_string(),
+            pin: pin.clone(),
+        };
+        pending.insert(
+            request_id.clone(),
+            PendingPairing {
+                device_name: device_name.to_string(),
+                claim,
+                pin,
+                expires_at: now + PIN_TTL,
+                attempts: 0,
+            },
+        );
+        drop(pending);
+
+        (self.notifier)(&prompt);
+        Ok(request_id)
+    }
+
+    /// 校验 PIN（以及声明了身份时的签名），成功后返回 `(设备 id, 令牌)`；
+    /// 令牌明文只出现这一次。同一 id 再次配对时替换原来的令牌。
+    /// 输错计入 `source` 和全局的失败次数，超过额度后暂时锁定。
+    pub async fn confirm(
+        &self,
+        request_id: &str,
+        pin: &str,
+        signature: Option<&str>,
+        source: IpAddr,
+    ) -> Result<(String, String), PairingError> {
+        let pending = {
+            let now = Instant::now();
+            let mut lockouts = self.lockouts.lock().await;
+            lockouts.check(source, now)?;
+            let mut pending = self.pending.lock().await;
+            let entry = pending.get_mut(request_id).ok_or(PairingError::NotFound)?;
+            if entry.expires_at <= now {
+                pending.remove(request_id);
+                return Err(PairingError::Expired);
+            }
+            if !bool::from(entry.pin.as_bytes().ct_eq(pin.trim().as_bytes())) {
+                lockouts.fail(source, now);
+                entry.attempts += 1;
+                let remaining = MAX_PIN_ATTEMPTS - entry.attempts;
+                if remaining == 0 {
+                    pending.remove(request_id);
+                }
+                return Err(PairingError::WrongPin { remaining });
+            }
+            pending.remove(request_id).unwrap()
+        };
+        let fingerprint = match &pending.claim {
+            Some(claim) => {
+                let signature = signature.ok_or(PairingError::BadSignature)?;
+                let message =
+                    identity::pairing_message(&self.host_id, &self.host_fingerprint, request_id);
+                if !identity::verify(&claim.public_key, &message, signature) {
+                    return Err(PairingError::BadSignature);
+                }
+                Some(identity::fingerprint(claim.public_key.as_bytes()))
+            }
+            None => None,
+        };
+
+        let token = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let device = PairedDevice {
+            id: match pending.claim {
+                Some(claim) => claim.id,
+                None => uuid::Uuid::new_v4().to_string(),
+            },
+            name: pending.device_name,
+            paired_at: chrono::Utc::now().timestamp() as u64,
+            fingerprint,
+            token_hash: hash_token(&token),
+        };
+        let id = device.id.clone();
+
+        let mut devices = self.devices.lock().await;
+        match devices.iter_mut().find(|d| d.id == id) {
+            Some(existing) if existing.fingerprint != device.fingerprint => {
+                return Err(PairingError::IdentityMismatch);
+            }
+            Some(existing) => *existing = device,
+            None => devices.push(device),
+        }
+        self.save(&devices).await?;
+        Ok((id, token))
+    }
+
+    /// 令牌有效时返回设备 id。
+    pub async fn verify(&self, token: &str) -> Option<String> {
+        let hash = hash_token(token);
+        self.devices
+            .lock()
+            .await
+            .iter()
+            .find(|d| d.token_hash == hash)
+            .map(|d| d.id.clone())
+    }
+
+    pub async fn devices(&self) -> Vec<PairedDevice> {
+        self.devices
+            .lock()
+            .await
+            .iter()
+            .map(|d| PairedDevice {
+                token_hash: String::new(),
+                ..d.clone()
+            })
+            .collect()
+    }
+
+    /// 吊销设备令牌，设备不存在时返回 `false`。
+    pub async fn revoke(&self, id: &str) -> Result<bool, PairingError> {
+        let mut devices = self.devices.lock().await;
+        let before = devices.len();
+        devices.retain(|d| d.id != id);
+        if devices.len() == before {
+            return Ok(false);
+        }
+        self.save(&devices).await?;
+        Ok(true)
+    }
+
+    async fn save(&self, devices: &[PairedDevice]) -> Result<(), PairingError> {
+        if let Some(parent) = self.store_path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(PairingError::Io)?;
+        }
+        let json = serde_json::to_vec_pretty(devices)
+            .map_err(|e| PairingError::Io(std::io::Error::other(e)))?;
+        tokio::fs::write(&self.store_path, json)
+            .await
+            .map_err(PairingError::Io)
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// 均匀分布的 6 位 PIN。直接对 10^6 取模会偏向较小的数，落在最后不完整一轮里的值丢弃重抽。
+fn random_pin() -> String {
+    const LIMIT: u32 = u32::MAX - u32::MAX % 1_000_000;
+    loop {
+        let n = OsRng.next_u32();
+        if n < LIMIT {
+            return format!("{:06}", n % 1_000_000);
+        }
+    }
+}
+
+/// Tauri 窗口和开发服务器（`tauri.conf.json` 的 `devUrl`）的 Origin。
+const APP_ORIGINS: &[&str] = &[
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+    "http://localhost:1420",
+    "http://127.0.0.1:1420",
+];
+
+/// 来自回环或本机网卡地址的请求免令牌。只看对端地址不够：本机浏览器里的任意网页都能
+/// 向 `127.0.0.1` 提交表单（不触发 CORS 预检），DNS 重绑定的网页甚至与 API 同源。
+/// 所以还要求 Host 是本机地址，Origin（如果有）是同源或应用自己的页面；
+/// 不满足时按远程请求处理，需要令牌。
+fn is_local_request(req: &Request) -> bool {
+    let peer_is_local = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .is_some_and(|ConnectInfo(addr)| network::is_local_ip(addr.ip()));
+    if !peer_is_local {
+        return false;
+    }
+    // HTTP/2 没有 Host 头，用 URI 里的 authority
+    let Some(host) = req.uri().authority().map(|a| a.as_str()).or_else(|| {
+        req.headers()
+            .get(header::HOST)
+            .and_then(|h| h.to_str().ok())
+    }) else {
+        return false;
+    };
+    if !is_local_host(host) {
+        return false;
+    }
+    match req.headers().get(header::ORIGIN) {
+        None => true,
+        Some(origin) => origin.to_str().is_ok_and(|origin| {
+            APP_ORIGINS.contains(&origin)
+                || origin
+                    .split_once("://")
+                    .is_some_and(|(_, authority)| authority.eq_ignore_ascii_case(host))
+        }),
+    }
+}
+
+/// Host（可带端口）是 `localhost` 或本机 IP。域名一律不算，DNS 重绑定靠的就是域名。
+fn is_local_host(host: &str) -> bool {
+    let Ok(authority) = host.parse::<Authority>() else {
+        return false;
+    };
+    let name = authority.host();
+    name.eq_ignore_ascii_case("localhost")
+        || name
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(network::is_local_ip)
+}
+
+fn request_token(headers: &HeaderMap) -> Option<String> {
+    if let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    {
+        return Some(token.trim().to_string());
+    }
+
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == TOKEN_COOKIE)
+        .map(|(_, value)| value.to_string())
+}
+
+/// `/api/*` 鉴权中间件。
+pub async fn require_auth(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let caller = if is_local_request(&req) {
+        Caller::Local
+    } else {
+        let token = request_token(req.headers())
+            .ok_or(ApiError::new(ErrorCode::Unauthorized, "Pairing required"))?;
+        let id = state.pairing.verify(&token).await.ok_or(ApiError::new(
+            ErrorCode::Unauthorized,
+            "Invalid or revoked token",
+        ))?;
+        Caller::Device(id)
+    };
+
+    req.extensions_mut().insert(caller);
+    Ok(next.run(req).await)
+}
+
+/// 传输记录里的对端：连接地址加上 `require_auth` 识别出的设备 id。
+impl<S: Send + Sync> FromRequestParts<S> for Peer {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let addr = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            // 双栈监听时 IPv4 对端是 `::ffff:a.b.c.d`，换回 IPv4 以便和限速设置对应
+            .map(|ConnectInfo(addr)| addr.ip().to_canonical().to_string())
+            .unwrap_or_default();
+        let device_id = match parts.extensions.get::<Caller>() {
+            Some(Caller::Device(id)) => Some(id.clone()),
+            _ => None,
+        };
+        Ok(Peer { addr, device_id })
+    }
+}
+
+/// 设置浏览器模式使用的令牌 cookie；启用 HTTPS 时加上 `Secure`，不随明文请求发出。
+pub fn token_cookie(token: &str, secure: bool) -> HeaderValue {
+    HeaderValue::from_str(&format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Strict; Max-Age=31536000{}",
+        TOKEN_COOKIE,
+        token,
+        if secure { "; Secure" } else { "" }
+    ))
+    .expect("token is ASCII")
+}
+
+/// 只允许 Tauri 应用和本机开发服务器跨域访问；浏览器模式走同源，不需要 CORS。
+pub fn cors_layer() -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(|origin, _| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| APP_ORIGINS.contains(&origin))
+        }))
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers(Any)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use tempfile::tempdir;
+
+    const PEER: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+
+    fn quiet(
+        store: PathBuf,
+        host: &Identity,
+    ) -> (Pairing, Arc<std::sync::Mutex<Vec<PairingPrompt>>>) {
+        let prompts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = prompts.clone();
+        let pairing =
+            Pairing::load(store, host).with_notifier(Arc::new(move |p: &PairingPrompt| {
+                sink.lock().unwrap().push(p.clone());
+            }));
+        (pairing, prompts)
+    }
+
+    #[tokio::test]
+    async fn test_pair_verify_and_revoke() {
+        let dir = tempdir().unwrap();
+        let store = dir.path().join("paired.json");
+        let host = Identity::generate();
+        let (pairing, prompts) = quiet(store.clone(), &host);
+
+        let request_id = pairing.request("Phone", None, PEER).await.unwrap();
+        let pin = prompts.lock().unwrap()[0].pin.clone();
+        assert_eq!(pin.len(), 6);
+
+        let (id, token) = pairing
+            .confirm(&request_id, &pin, None, PEER)
+            .await
+            .unwrap();
+        assert_eq!(pairing.verify(&token).await, Some(id.clone()));
+        assert_eq!(pairing.verify("bogus").await, None);
+
+        // 持久化后重新加载仍然有效，且列表不泄露哈希
+        let reloaded = Pairing::load(store, &host);
+        assert_eq!(reloaded.verify(&token).await, Some(id.clone()));
+        assert!(reloaded.devices().await[0].token_hash.is_empty());
+
+        assert!(reloaded.revoke(&id).await.unwrap());
+        assert_eq!(reloaded.verify(&token).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_pin_attempts_exhaust_request() {
+        let dir = tempdir().unwrap();
+        let (pairing, prompts) = quiet(dir.path().join("paired.json"), &Identity::generate());
+
+        let request_id = pairing.request("Laptop", None, PEER).await.unwrap();
+        let pin = prompts.lock().unwrap()[0].pin.clone();
+        let wrong = if pin == "000000" { "111111" } else { "000000" };
+
+        for remaining in (0..MAX_PIN_ATTEMPTS).rev() {
+            match pairing.confirm(&request_id, wrong, None, PEER).await {
+                Err(PairingError::WrongPin { remaining: r }) => assert_eq!(r, remaining),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        // 次数用尽的请求已删除；这个来源也被锁定，正确的 PIN 也不再受理
+        assert!(matches!(
+            pairing.confirm(&request_id, &pin, None, PEER).await,
+            Err(PairingError::LockedOut { .. })
+        ));
+        let other = IpAddr::from([192, 0, 2, 2]);
+        assert!(matches!(
+            pairing.confirm(&request_id, &pin, None, other).await,
+            Err(PairingError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failures_lock_out_globally_across_requests() {
+        let dir = tempdir().unwrap();
+        let (pairing, prompts) = quiet(dir.path().join("paired.json"), &Identity::generate());
+
+        // 每个请求的次数用完后换一个来源重新开请求
+        for n in 0..GLOBAL_FAILURE_BUDGET / u32::from(MAX_PIN_ATTEMPTS) {
+            let source = IpAddr::from([198, 51, 100, n as u8]);
+            let request_id = pairing.request("Laptop", None, source).await.unwrap();
+            let pin = prompts.lock().unwrap().last().unwrap().pin.clone();
+            let wrong = if pin == "000000" { "111111" } else { "000000" };
+            for _ in 0..MAX_PIN_ATTEMPTS {
+                assert!(matches!(
+                    pairing.confirm(&request_id, wrong, None, source).await,
+                    Err(PairingError::WrongPin { .. })
+                ));
+            }
+        }
+
+        match pairing.request("Laptop", None, PEER).await {
+            Err(PairingError::LockedOut { retry_after }) => {
+                assert!(retry_after <= LOCKOUT_BASE)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // 本机不受全局锁定影响
+        let loopback = IpAddr::from([127, 0, 0, 1]);
+        assert!(pairing.request("Laptop", None, loopback).await.is_ok());
+    }
+
+    #[test]
+    fn test_lockout_doubles_and_resets() {
+        let now = Instant::now();
+        let mut budget = FailureBudget::default();
+        for _ in 0..IP_FAILURE_BUDGET - 1 {
+            budget.fail(now, IP_FAILURE_BUDGET);
+        }
+        assert_eq!(budget.locked(now), None);
+        budget.fail(now, IP_FAILURE_BUDGET);
+        assert_eq!(budget.locked(now), Some(LOCKOUT_BASE));
+        budget.fail(now, IP_FAILURE_BUDGET);
+        assert_eq!(budget.locked(now), Some(LOCKOUT_BASE * 2));
+
+        // 很久没有再输错：锁定早已过期，计数从头开始
+        let later = now + FAILURE_RESET + LOCKOUT_MAX;
+        budget.fail(later, IP_FAILURE_BUDGET);
+        assert_eq!(budget.locked(later), None);
+    }
+
+    #[test]
+    fn test_random_pin_is_six_digits() {
+        for _ in 0..1000 {
+            let pin = random_pin();
+            assert_eq!(pin.len(), 6);
+            assert!(pin.bytes().all(|b| b.is_ascii_digit()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pairing_keyed_by_device_identity() {
+        let dir = tempdir().unwrap();
+        let host = Identity::generate();
+        let (pairing, prompts) = quiet(dir.path().join("paired.json"), &host);
+        let sign = |identity: &Identity, request_id: &str| {
+            identity.sign(&identity::pairing_message(
+                host.id(),
+                &host.fingerprint(),
+                request_id,
+            ))
+        };
+        let device = Identity::generate();
+        let claim = |identity: &Identity| DeviceClaim {
+            id: device.id().to_string(),
+            public_key: identity::parse_public_key(&identity.public_key()).unwrap(),
+        };
+        let pin = |n: usize| prompts.lock().unwrap()[n].pin.clone();
+
+        // 没有签名不能冒用 id
+        let request_id = pairing
+            .request("Laptop", Some(claim(&device)), PEER)
+            .await
+            .unwrap();
+        assert!(matches!(
+            pairing.confirm(&request_id, &pin(0), None, PEER).await,
+            Err(PairingError::BadSignature)
+        ));
+
+        // 只签请求 id、或者针对别的主机的签名都不算数
+        let other_host = Identity::generate();
+        for forged in 0..2 {
+            let request_id = pairing
+                .request("Laptop", Some(claim(&device)), PEER)
+                .await
+                .unwrap();
+            let signature = match forged {
+                0 => device.sign(request_id.as_bytes()),
+                _ => device.sign(&identity::pairing_message(
+                    other_host.id(),
+                    &other_host.fingerprint(),
+                    &request_id,
+                )),
+            };
+            assert!(matches!(
+                pairing
+                    .confirm(&request_id, &pin(1 + forged), Some(&signature), PEER)
+                    .await,
+                Err(PairingError::BadSignature)
+            ));
+        }
+
+        let request_id = pairing
+            .request("Laptop", Some(claim(&device)), PEER)
+            .await
+            .unwrap();
+        let signature = sign(&device, &request_id);
+        let (id, first) = pairing
+            .confirm(&request_id, &pin(3), Some(&signature), PEER)
+            .await
+            .unwrap();
+        assert_eq!(id, device.id());
+
+        // 重新配对替换令牌，不新增记录
+        let request_id = pairing
+            .request("Laptop (new name)", Some(claim(&device)), PEER)
+            .await
+            .unwrap();
+        let signature = sign(&device, &request_id);
+        let (_, second) = pairing
+            .confirm(&request_id, &pin(4), Some(&signature), PEER)
+            .await
+            .unwrap();
+        assert_eq!(pairing.verify(&first).await, None);
+        assert_eq!(pairing.verify(&second).await.as_deref(), Some(device.id()));
+        let devices = pairing.devices().await;
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].name, "Laptop (new name)");
+        assert_eq!(devices[0].fingerprint, Some(device.fingerprint()));
+
+        // 另一把密钥声明同一个 id
+        let impostor = Identity::generate();
+        let request_id = pairing
+            .request("Laptop", Some(claim(&impostor)), PEER)
+            .await
+            .unwrap();
+        let signature = sign(&impostor, &request_id);
+        assert!(matches!(
+            pairing
+                .confirm(&request_id, &pin(5), Some(&signature), PEER)
+                .await,
+            Err(PairingError::IdentityMismatch)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pairing_body_is_limited() {
+        use axum::body::Body;
+        use tower::ServiceExt;
+
+        let dir = tempdir().unwrap();
+        let state = crate::server::test_state(dir.path());
+        let app = crate::server::routes::api_routes(state.clone()).with_state(state);
+        let request = |name: String| {
+            let body = serde_json::json!({ "device_name": name }).to_string();
+            let mut req = Request::builder()
+                .method("POST")
+                .uri("/pair/request")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([203, 0, 113, 7], 50000))));
+            req
+        };
+
+        let res = app.clone().oneshot(request("Phone".into())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app.oneshot(request("x".repeat(64 * 1024))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_api_requires_token_for_remote_callers() {
+        use axum::body::Body;
+        use tower::ServiceExt;
+
+        let dir = tempdir().unwrap();
+        let state = crate::server::test_state(dir.path());
+        let app = crate::server::routes::api_routes(state.clone()).with_state(state.clone());
+        let remote = ConnectInfo(SocketAddr::from(([203, 0, 113, 7], 50000)));
+
+        let request = |token: Option<&str>| {
+            let mut req = Request::builder().uri("/device/info");
+            if let Some(token) = token {
+                req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            let mut req = req.body(Body::empty()).unwrap();
+            req.extensions_mut().insert(remote);
+            req
+        };
+
+        let res = app.clone().oneshot(request(None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let request_id = state.pairing.request("Phone", None, PEER).await.unwrap();
+        let pin = state.pairing.pending.lock().await[&request_id].pin.clone();
+        let (_, token) = state
+            .pairing
+            .confirm(&request_id, &pin, None, PEER)
+            .await
+            .unwrap();
+
+        let res = app.clone().oneshot(request(Some(&token))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // 本机请求免令牌，但 Host 和 Origin 也要指向本机
+        let local = |host: &str, origin: Option<&str>| {
+            let mut req = Request::builder()
+                .uri("/device/info")
+                .header(header::HOST, host);
+            if let Some(origin) = origin {
+                req = req.header(header::ORIGIN, origin);
+            }
+            let mut req = req.body(Body::empty()).unwrap();
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 50000))));
+            req
+        };
+        for (host, origin, status) in [
+            ("127.0.0.1:8080", None, StatusCode::OK),
+            (
+                "localhost:8080",
+                Some("http://localhost:8080"),
+                StatusCode::OK,
+            ),
+            ("[::1]:8080", Some("tauri://localhost"), StatusCode::OK),
+            // DNS 重绑定：对端是回环，Host 是攻击者的域名
+            ("rebind.example:8080", None, StatusCode::UNAUTHORIZED),
+            // 本机浏览器里其他网页发起的跨域请求
+            (
+                "127.0.0.1:8080",
+                Some("https://evil.example"),
+                StatusCode::UNAUTHORIZED,
+            ),
+        ] {
+            let res = app.clone().oneshot(local(host, origin)).await.unwrap();
+            assert_eq!(res.status(), status, "{} {:?}", host, origin);
+        }
+    }
+
+    #[test]
+    fn test_request_token_from_header_or_cookie() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, "a=1; transport_token=abc".parse().unwrap());
+        assert_eq!(request_token(&headers), Some("abc".to_string()));
+
+        headers.insert(header::AUTHORIZATION, "Bearer xyz".parse().unwrap());
+        assert_eq!(request_token(&headers), Some("xyz".to_string()));
+    }
+
+    #[test]
+    fn test_token_cookie_secure_with_https() {
+        let plain = token_cookie("abc", false);
+        assert!(!plain.to_str().unwrap().contains("Secure"));
+        let secure = token_cookie("abc", true);
+        assert!(secure.to_str().unwrap().ends_with("; Secure"));
+        assert!(secure.to_str().unwrap().starts_with("transport_token=abc;"));
+    }
+}
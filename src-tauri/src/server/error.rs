@@ -0,0 +1,167 @@
+//! API 错误：状态码加上固定格式的 JSON 正文 `{"code": "not_found", "message": "..."}`。
+//! 客户端按 `code` 区分错误，`message` 只用于展示，内容可能随版本变化。
+
+use std::io::ErrorKind;
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// 参数缺失或格式错误
+    BadRequest,
+    /// 需要配对，或令牌无效
+    Unauthorized,
+    /// 共享目录之外，或系统拒绝访问
+    Forbidden,
+    /// 共享目录或文件系统只读
+    ReadOnly,
+    NotFound,
+    /// 目标已存在（冲突策略为 `fail`）
+    AlreadyExists,
+    /// 与当前状态冲突：偏移量不符、目录非空、传输已结束等
+    Conflict,
+    /// 传输被用户取消
+    Cancelled,
+    /// 配对请求已过期
+    Expired,
+    TooLarge,
+    ChecksumMismatch,
+    TooManyRequests,
+    /// 磁盘已满或超出配额
+    InsufficientStorage,
+    /// 对端启用了 HTTPS，不接受明文请求
+    HttpsRequired,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden | ErrorCode::ReadOnly => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::AlreadyExists | ErrorCode::Conflict | ErrorCode::Cancelled => {
+                StatusCode::CONFLICT
+            }
+            ErrorCode::Expired => StatusCode::GONE,
+            ErrorCode::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::ChecksumMismatch => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::InsufficientStorage => StatusCode::INSUFFICIENT_STORAGE,
+            ErrorCode::HttpsRequired => StatusCode::UPGRADE_REQUIRED,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn from_io(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::NotFound => ErrorCode::NotFound,
+            ErrorKind::PermissionDenied => ErrorCode::Forbidden,
+            ErrorKind::ReadOnlyFilesystem => ErrorCode::ReadOnly,
+            ErrorKind::AlreadyExists => ErrorCode::AlreadyExists,
+            ErrorKind::DirectoryNotEmpty | ErrorKind::ResourceBusy | ErrorKind::CrossesDevices => {
+                ErrorCode::Conflict
+            }
+            ErrorKind::StorageFull | ErrorKind::QuotaExceeded => ErrorCode::InsufficientStorage,
+            ErrorKind::FileTooLarge => ErrorCode::TooLarge,
+            ErrorKind::InvalidInput
+            | ErrorKind::InvalidFilename
+            | ErrorKind::NotADirectory
+            | ErrorKind::IsADirectory => ErrorCode::BadRequest,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::BadRequest, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.code.status()
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<std::io::Error> for ApiError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(ErrorCode::from_io(e.kind()), e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_mapping() {
+        let status = |kind: ErrorKind| ApiError::from(std::io::Error::from(kind)).status();
+        assert_eq!(status(ErrorKind::NotFound), StatusCode::NOT_FOUND);
+        assert_eq!(status(ErrorKind::PermissionDenied), StatusCode::FORBIDDEN);
+        assert_eq!(status(ErrorKind::AlreadyExists), StatusCode::CONFLICT);
+        assert_eq!(status(ErrorKind::DirectoryNotEmpty), StatusCode::CONFLICT);
+        assert_eq!(
+            status(ErrorKind::StorageFull),
+            StatusCode::INSUFFICIENT_STORAGE
+        );
+        assert_eq!(
+            status(ErrorKind::UnexpectedEof),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn test_json_body() {
+        let response =
+            ApiError::new(ErrorCode::InsufficientStorage, "No space left").into_response();
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"code": "insufficient_storage", "message": "No space left"})
+        );
+    }
+}
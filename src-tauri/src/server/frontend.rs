@@ -0,0 +1,351 @@
+//! `/app` 前端页面：构建时把 `dist` 嵌入二进制，开发时可以用 `--frontend-dist`
+//! 指定目录直接读磁盘。文本类文件首次请求时在阻塞线程池里压缩一次（br / gzip）并缓存，
+//! 不占用处理请求的异步线程。
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use bytes::Bytes;
+use tokio::sync::OnceCell;
+use tower_http::services::{ServeDir, ServeFile};
+
+#[derive(rust_embed::Embed)]
+#[folder = "../dist"]
+#[allow_missing = true]
+struct Dist;
+
+/// 小于这个大小的文件压缩收益太小，原样返回。
+const MIN_COMPRESS_SIZE: usize = 1024;
+
+/// Vite 输出到 `assets/` 的文件名带内容哈希，可以永久缓存。
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// `index.html` 等入口每次都用 ETag 向服务端确认。
+const REVALIDATE: &str = "no-cache";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut out = Vec::new();
+                {
+                    let mut writer = brotli::CompressorWriter::new(&mut out, 4096, 11, 22);
+                    writer.write_all(data)?;
+                }
+                Ok(out)
+            }
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+struct Asset {
+    data: Bytes,
+    mime: String,
+    /// 内容哈希，不含引号
+    hash: String,
+    compressible: bool,
+    brotli: OnceCell<Option<Bytes>>,
+    gzip: OnceCell<Option<Bytes>>,
+}
+
+impl Asset {
+    fn new(path: &str, data: Bytes) -> Self {
+        let mime = mime_guess::from_path(path).first_or_octet_stream();
+        let compressible = data.len() >= MIN_COMPRESS_SIZE
+            && (mime.type_() == mime_guess::mime::TEXT
+                || matches!(mime.subtype().as_str(), "javascript" | "json" | "wasm")
+                || mime.suffix().is_some_and(|s| s == "xml" || s == "json"));
+        let hash = blake3::hash(&data).to_hex()[..16].to_string();
+        Self {
+            data,
+            mime: mime.to_string(),
+            hash,
+            compressible,
+            brotli: OnceCell::new(),
+            gzip: OnceCell::new(),
+        }
+    }
+
+    /// 压缩后的内容，没有变小时为 `None`。同时到达的请求等同一次压缩。
+    async fn encoded(&self, encoding: Encoding) -> Option<Bytes> {
+        if !self.compressible {
+            return None;
+        }
+        let cell = match encoding {
+            Encoding::Brotli => &self.brotli,
+            Encoding::Gzip => &self.gzip,
+        };
+        cell.get_or_init(|| async {
+            let data = self.data.clone();
+            // 最高压缩级别处理一个打包后的脚本要几百毫秒
+            let compressed = tokio::task::spawn_blocking(move || encoding.compress(&data))
+                .await
+                .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+            match compressed {
+                Ok(out) if out.len() < self.data.len() => Some(Bytes::from(out)),
+                Ok(_) => None,
+                Err(e) => {
+                    log::warn!("Failed to compress frontend asset: {}", e);
+                    None
+                }
+            }
+        })
+        .await
+        .clone()
+    }
+
+    /// 不同编码的内容不同，ETag 也要区分。
+    fn etag(&self, encoding: Option<Encoding>) -> String {
+        match encoding {
+            Some(encoding) => format!("\"{}-{}\"", self.hash, encoding.name()),
+            None => format!("\"{}\"", self.hash),
+        }
+    }
+
+    /// `If-None-Match` 中任一 ETag 对应当前内容（不论编码）即可返回 304。
+    fn matches(&self, if_none_match: &str) -> bool {
+        if_none_match.split(',').any(|tag| {
+            let tag = tag.trim().trim_start_matches("W/").trim_matches('"');
+            tag == "*" || tag.split('-').next() == Some(self.hash.as_str())
+        })
+    }
+}
+
+/// 内存中的前端文件表，以相对路径（如 `assets/index-1a2b.js`）为键。
+#[derive(Clone)]
+pub struct Frontend {
+    assets: Arc<HashMap<String, Asset>>,
+}
+
+impl Frontend {
+    /// 编译时嵌入的 `dist`；构建前端之前编译的二进制里为空。
+    pub fn embedded() -> Self {
+        Self::from_files(Dist::iter().filter_map(|path| {
+            let file = Dist::get(&path)?;
+            Some((path.to_string(), file.data.into_owned()))
+        }))
+    }
+
+    pub fn from_files(files: impl IntoIterator<Item = (String, Vec<u8>)>) -> Self {
+        let assets = files
+            .into_iter()
+            .map(|(path, data)| {
+                let asset = Asset::new(&path, Bytes::from(data));
+                (path, asset)
+            })
+            .collect();
+        Self {
+            assets: Arc::new(assets),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// 找不到的路径交给前端路由，返回 `index.html`。
+    pub async fn response(&self, path: &str, headers: &HeaderMap) -> Response {
+        let path = path.trim_start_matches('/');
+        let (path, asset) = match self.assets.get_key_value(path) {
+            Some((path, asset)) => (path.as_str(), asset),
+            None => match self.assets.get("index.html") {
+                Some(asset) => ("index.html", asset),
+                None => return (StatusCode::NOT_FOUND, "Frontend not built").into_response(),
+            },
+        };
+        let cache_control = if path.starts_with("assets/") {
+            IMMUTABLE
+        } else {
+            REVALIDATE
+        };
+
+        let accepted = headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .map(accepted_encodings)
+            .unwrap_or_default();
+        let mut encoding = None;
+        for accepted in accepted {
+            if let Some(data) = asset.encoded(accepted).await {
+                encoding = Some((accepted, data));
+                break;
+            }
+        }
+        let etag = asset.etag(encoding.as_ref().map(|(encoding, _)| *encoding));
+
+        let mut builder = Response::builder()
+            .header(header::ETAG, &etag)
+            .header(header::CACHE_CONTROL, cache_control);
+        if asset.compressible {
+            builder = builder.header(header::VARY, "Accept-Encoding");
+        }
+        let not_modified = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| asset.matches(v));
+        if not_modified {
+            return builder
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap();
+        }
+
+        let body = match encoding {
+            Some((encoding, data)) => {
+                builder = builder.header(header::CONTENT_ENCODING, encoding.name());
+                data
+            }
+            None => asset.data.clone(),
+        };
+        builder
+            .header(header::CONTENT_TYPE, &asset.mime)
+            .header(header::CONTENT_LENGTH, HeaderValue::from(body.len()))
+            .body(Body::from(body))
+            .unwrap()
+    }
+}
+
+/// 客户端接受的压缩方式，按本服务的偏好排序（br 优先）。
+fn accepted_encodings(value: &str) -> Vec<Encoding> {
+    let accepted: Vec<&str> = value
+        .split(',')
+        .filter_map(|part| {
+            let mut params = part.split(';');
+            let name = params.next()?.trim();
+            let refused = params.any(|p| {
+                p.trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    == Some(0.0)
+            });
+            (!refused).then_some(name)
+        })
+        .collect();
+    [Encoding::Brotli, Encoding::Gzip]
+        .into_iter()
+        .filter(|e| {
+            accepted
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(e.name()) || *a == "*")
+        })
+        .collect()
+}
+
+async fn serve(State(frontend): State<Frontend>, req: Request) -> Response {
+    frontend.response(req.uri().path(), req.headers()).await
+}
+
+/// 挂载在 `/app` 下的服务。给出 `dist` 目录时直接读磁盘，改完前端重新构建即可生效。
+pub fn service(dist: Option<PathBuf>) -> Router {
+    if let Some(dist) = dist {
+        log::info!("Serving frontend from {}", dist.display());
+        let index = dist.join("index.html");
+        return Router::new().fallback_service(
+            ServeDir::new(&dist)
+                .precompressed_br()
+                .precompressed_gzip()
+                .fallback(ServeFile::new(index)),
+        );
+    }
+    let frontend = Frontend::embedded();
+    if frontend.is_empty() {
+        log::warn!(
+            "Frontend was not embedded in this build; run `pnpm build` and rebuild, or pass --frontend-dist"
+        );
+    }
+    Router::new().fallback(get(serve)).with_state(frontend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frontend() -> Frontend {
+        let script = "console.log('transport');\n".repeat(200);
+        Frontend::from_files([
+            ("index.html".to_string(), b"<!doctype html>".to_vec()),
+            ("assets/index-1a2b.js".to_string(), script.into_bytes()),
+        ])
+    }
+
+    async fn body(response: Response) -> Bytes {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_compressed_variants_and_cache_headers() {
+        let frontend = frontend();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            "gzip, deflate, br".parse().unwrap(),
+        );
+
+        let response = frontend.response("/assets/index-1a2b.js", &headers).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+        assert_eq!(response.headers()[header::CACHE_CONTROL], IMMUTABLE);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/javascript");
+        let etag = response.headers()[header::ETAG].clone();
+        let compressed = body(response).await;
+        let mut plain = Vec::new();
+        brotli::BrotliDecompress(&mut compressed.as_ref(), &mut plain).unwrap();
+        assert!(plain.starts_with(b"console.log"));
+
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            "gzip;q=1.0, br;q=0".parse().unwrap(),
+        );
+        let response = frontend.response("/assets/index-1a2b.js", &headers).await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_ne!(response.headers()[header::ETAG], etag);
+
+        // 编码不同的 ETag 也能让缓存重新验证成功
+        headers.insert(header::IF_NONE_MATCH, etag);
+        let response = frontend.response("/assets/index-1a2b.js", &headers).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(body(response).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_spa_fallback() {
+        let frontend = frontend();
+        let response = frontend.response("/settings", &HeaderMap::new()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], REVALIDATE);
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(body(response).await.as_ref(), b"<!doctype html>");
+
+        let empty = Frontend::from_files([]);
+        let response = empty.response("/", &HeaderMap::new()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
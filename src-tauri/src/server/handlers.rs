@@ -1,52 +1,261 @@
+use std::net::{IpAddr, SocketAddr};
+
 use axum::body::Body;
-use axum::extract::{Query, State};
-use axum::http::{header, StatusCode};
+use axum::extract::{ConnectInfo, Extension, Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::sse::{self, Sse};
 use axum::response::Response;
 use axum::Json;
-use serde::Serialize;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-
-use super::AppState;
+use futures_util::StreamExt;
+use http_body::Frame;
+use http_body_util::StreamBody;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+
+use super::archive::{self, ArchiveFormat};
+use super::auth::{self, Caller, DeviceClaim, PairedDevice};
+use super::error::{ApiError, ErrorCode};
+use super::network::{self, InterfaceAddress};
+use super::range::{self, ByteRange, RangeRequest};
+use super::sandbox::{Access, SharedRoot};
+use super::{apply_settings, AppState};
+use crate::discovery::DiscoveredDevice;
+use crate::events::{Event, FsChange, FsChangeKind};
+use crate::identity::{self, Identity};
+use crate::settings::Settings;
+use crate::transfer::atomic::{self, ConflictPolicy, Outcome};
+use crate::transfer::checksum::{self, Algorithm, Checksum, ChecksumCache, Hasher};
+use crate::transfer::limiter::{Pacer, PeerRate};
+use crate::transfer::manager::{Direction, Peer, TransferError, TransferHandle, TransferInfo};
+use crate::transfer::schedule::{self, EffectiveRate, ScheduleRule};
+use crate::transfer::session::{SessionError, SessionStatus};
 
 // --- Device Info ---
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DeviceInfo {
     pub name: String,
     pub platform: String,
+    /// 首选地址，即 `interfaces` 的第一个
     pub ip: String,
     pub port: u16,
     pub home_dir: String,
+    /// 所有可以连接的地址，按优先级排序；对端应依次尝试
+    #[serde(default)]
+    pub interfaces: Vec<InterfaceAddress>,
+    /// 设备 id，重装前不变；旧版本没有这一项时为空
+    #[serde(default)]
+    pub id: String,
+    /// 设备公钥的指纹，启用 HTTPS 时也是证书的指纹
+    #[serde(default)]
+    pub fingerprint: String,
+    /// 是否只接受 HTTPS
+    #[serde(default)]
+    pub https: bool,
+}
+
+impl DeviceInfo {
+    /// 本机信息，同时用于 `/api/device/info` 和 mDNS 广播。
+    /// 只列出 `addr` 上能连到的地址：`[::]` 为全部，`0.0.0.0` 为 IPv4，否则只有它本身。
+    pub fn local(addr: SocketAddr, identity: &Identity) -> Self {
+        let bind = addr.ip();
+        let interfaces: Vec<_> = network::local_addresses()
+            .into_iter()
+            .filter(|a| match bind {
+                IpAddr::V6(v6) if v6.is_unspecified() => true,
+                IpAddr::V4(v4) if v4.is_unspecified() => a.ip.is_ipv4(),
+                _ => a.ip == bind,
+            })
+            .collect();
+        let ip = match interfaces.first() {
+            Some(first) => first.ip.to_string(),
+            None if !bind.is_unspecified() => bind.to_string(),
+            None => local_ip_address::local_ip()
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|_| "unknown".to_string()),
+        };
+
+        let home_dir = dirs::home_dir()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| "/".to_string());
+
+        DeviceInfo {
+            name: hostname::get()
+                .map(|h| h.to_string_lossy().to_string())
+                .unwrap_or_else(|_| "unknown".to_string()),
+            platform: std::env::consts::OS.to_string(),
+            ip,
+            port: addr.port(),
+            home_dir,
+            interfaces,
+            id: identity.id().to_string(),
+            fingerprint: identity.fingerprint(),
+            https: false,
+        }
+    }
+}
+
+pub async fn device_info(State(state): State<AppState>) -> Json<DeviceInfo> {
+    Json(state.device_info())
+}
+
+// --- Discovered Peers (mDNS) ---
+
+pub async fn list_devices(State(state): State<AppState>) -> Json<Vec<DiscoveredDevice>> {
+    Json(state.discovery.devices().await)
+}
+
+// --- Pairing ---
+
+#[derive(Serialize, Deserialize)]
+pub struct PairRequest {
+    pub device_name: String,
+    /// 设备身份，两项要么都给要么都不给
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    /// Ed25519 公钥，Base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PairConfirmRequest {
+    pub request_id: String,
+    pub pin: String,
+    /// 声明了身份时必填：私钥对 [`identity::pairing_message`] 的签名，Base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
-pub async fn device_info() -> Json<DeviceInfo> {
-    let ip = local_ip_address::local_ip()
-        .map(|ip| ip.to_string())
-        .unwrap_or_else(|_| "unknown".to_string());
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PairRequested {
+    pub request_id: String,
+    /// 本机（被配对一方）的设备 id 和指纹，配对签名里要包含
+    #[serde(default)]
+    pub device_id: String,
+    #[serde(default)]
+    pub fingerprint: String,
+}
 
-    let home_dir = dirs::home_dir()
-        .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_else(|| "/".to_string());
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PairConfirmed {
+    pub device_id: String,
+    pub token: String,
+    /// 本机（被配对一方）的设备指纹，客户端记下后用来固定 HTTPS 证书
+    #[serde(default)]
+    pub fingerprint: String,
+}
+
+pub async fn request_pairing(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(body): Json<PairRequest>,
+) -> Result<Json<PairRequested>, ApiError> {
+    let name: String = body.device_name.trim().chars().take(64).collect();
+    let claim = match (body.device_id, body.public_key) {
+        (Some(id), Some(public_key)) => {
+            if uuid::Uuid::parse_str(&id).is_err() {
+                return Err(ApiError::bad_request("Invalid device id"));
+            }
+            let public_key = identity::parse_public_key(&public_key)
+                .ok_or_else(|| ApiError::bad_request("Invalid public key"))?;
+            Some(DeviceClaim { id, public_key })
+        }
+        (None, None) => None,
+        _ => {
+            return Err(ApiError::bad_request(
+                "device_id and public_key must be given together",
+            ))
+        }
+    };
+    let request_id = state
+        .pairing
+        .request(&name, claim, addr.ip().to_canonical())
+        .await?;
+    Ok(Json(PairRequested {
+        request_id,
+        device_id: state.identity.id().to_string(),
+        fingerprint: state.identity.fingerprint(),
+    }))
+}
 
-    Json(DeviceInfo {
-        name: hostname::get()
-            .map(|h| h.to_string_lossy().to_string())
-            .unwrap_or_else(|_| "unknown".to_string()),
-        platform: std::env::consts::OS.to_string(),
-        ip,
-        port: 8090,
-        home_dir,
+pub async fn confirm_pairing(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(body): Json<PairConfirmRequest>,
+) -> Result<Response, ApiError> {
+    let (device_id, token) = state
+        .pairing
+        .confirm(
+            &body.request_id,
+            &body.pin,
+            body.signature.as_deref(),
+            addr.ip().to_canonical(),
+        )
+        .await?;
+    let cookie = auth::token_cookie(&token, state.https());
+    let json = serde_json::to_string(&PairConfirmed {
+        device_id,
+        token,
+        fingerprint: state.identity.fingerprint(),
     })
+    .unwrap();
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::SET_COOKIE, cookie)
+        .body(Body::from(json))
+        .unwrap())
+}
+
+/// 本机可以看到全部已配对设备，其他设备只能看到自己。
+pub async fn list_paired_devices(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+) -> Json<Vec<PairedDevice>> {
+    let devices = state.pairing.devices().await;
+    Json(match caller {
+        Caller::Local => devices,
+        Caller::Device(id) => devices.into_iter().filter(|d| d.id == id).collect(),
+    })
+}
+
+/// 本机可以吊销任意设备，其他设备只能吊销自己。
+pub async fn revoke_device(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    if let Caller::Device(own) = &caller {
+        if *own != id {
+            return Err(ApiError::new(
+                ErrorCode::Forbidden,
+                "Only the host can revoke other devices",
+            ));
+        }
+    }
+    if !state.pairing.revoke(&id).await? {
+        return Err(ApiError::not_found("Device not found"));
+    }
+    Ok(Json(serde_json::json!({"ok": true})))
+}
+
+// --- Shared Roots ---
+
+pub async fn list_roots(State(state): State<AppState>) -> Json<Vec<SharedRoot>> {
+    Json(state.sandbox.roots().await)
 }
 
 // --- File Listing ---
 
-#[derive(serde::Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct FileListQuery {
     pub path: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct FileEntry {
     pub name: String,
     pub is_dir: bool,
@@ -55,20 +264,21 @@ pub struct FileEntry {
 }
 
 pub async fn list_files(
+    State(state): State<AppState>,
     Query(query): Query<FileListQuery>,
-) -> Result<Json<Vec<FileEntry>>, (StatusCode, String)> {
-    let path = std::path::Path::new(&query.path);
+) -> Result<Json<Vec<FileEntry>>, ApiError> {
+    let path = state.sandbox.resolve(&query.path, Access::Read).await?;
     let mut entries = Vec::new();
 
-    let mut read_dir = tokio::fs::read_dir(path)
-        .await
-        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let mut read_dir = tokio::fs::read_dir(&path).await?;
+
+    while let Some(entry) = read_dir.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // 正在上传的临时文件不对外展示
+        if atomic::is_temp_name(&name) {
+            continue;
+        }
 
-    while let Some(entry) = read_dir
-        .next_entry()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-    {
         // 跳过无法读取 metadata 的条目（权限不足等）
         let metadata = match entry.metadata().await {
             Ok(m) => m,
@@ -83,7 +293,7 @@ pub async fn list_files(
             .unwrap_or(0);
 
         entries.push(FileEntry {
-            name: entry.file_name().to_string_lossy().to_string(),
+            name,
             is_dir: metadata.is_dir(),
             size: metadata.len(),
             modified,
@@ -101,7 +311,7 @@ pub async fn list_files(
 
 // --- File Download (streaming) ---
 
-#[derive(serde::Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct FilePathQuery {
     pub path: String,
 }
@@ -109,72 +319,428 @@ pub struct FilePathQuery {
 pub async fn download_file(
     State(state): State<AppState>,
     Query(query): Query<FilePathQuery>,
-) -> Result<Response, (StatusCode, String)> {
-    let path = std::path::Path::new(&query.path);
+    peer: Peer,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let path = state.sandbox.resolve(&query.path, Access::Read).await?;
 
     if !path.is_file() {
-        return Err((StatusCode::NOT_FOUND, "File not found".to_string()));
+        return Err(ApiError::not_found("File not found"));
     }
 
-    let file = tokio::fs::File::open(path)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let file = tokio::fs::File::open(&path).await?;
 
-    let metadata = file
-        .metadata()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let metadata = file.metadata().await?;
 
     let file_name = path
         .file_name()
         .map(|n| n.to_string_lossy().to_string())
         .unwrap_or_else(|| "download".to_string());
 
-    let content_length = metadata.len();
-    let throttle = state.throttle.clone();
+    let total = metadata.len();
+    let modified = metadata.modified().ok();
+    let validators = range::Validators::new(total, modified);
+    let known_digests = state.checksums.lookup(&path, total, modified);
 
+    let mut builder = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, &validators.etag)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", file_name),
+        );
+    if let Some(last_modified) = &validators.last_modified {
+        builder = builder.header(header::LAST_MODIFIED, last_modified);
+    }
+    // Repr-Digest 描述的是完整文件，对 206 响应同样适用
+    if !known_digests.is_empty() {
+        builder = builder.header(REPR_DIGEST, checksum::repr_digest(&known_digests));
+    }
+
+    let mut trailer = None;
+    let segments = match range::evaluate(&headers, total, &validators) {
+        RangeRequest::Full => {
+            builder = builder
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/octet-stream");
+            // 摘要未知且客户端接受 trailer 时边发边算
+            if known_digests.is_empty() && accepts_trailers(&headers) {
+                let algorithms = headers
+                    .get(WANT_REPR_DIGEST)
+                    .and_then(|v| v.to_str().ok())
+                    .map(checksum::parse_want_digest)
+                    .filter(|a| !a.is_empty())
+                    .unwrap_or_else(|| vec![Algorithm::Sha256]);
+                builder = builder.header(header::TRAILER, REPR_DIGEST.as_str());
+                trailer = Some(DigestTrailer {
+                    hashers: algorithms.into_iter().map(Hasher::new).collect(),
+                    cache: state.checksums.clone(),
+                    path: path.clone(),
+                    len: total,
+                    modified,
+                });
+            }
+            vec![Segment::File {
+                start: 0,
+                len: total,
+            }]
+        }
+        RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+            let r = ranges[0];
+            builder = builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .header(header::CONTENT_RANGE, r.content_range(total));
+            vec![Segment::File {
+                start: r.start,
+                len: r.length(),
+            }]
+        }
+        RangeRequest::Partial(ranges) => {
+            let boundary = format!("transport-{}", validators.etag.trim_matches('"'));
+            builder = builder.status(StatusCode::PARTIAL_CONTENT).header(
+                header::CONTENT_TYPE,
+                format!("multipart/byteranges; boundary={}", boundary),
+            );
+            multipart_segments(&ranges, total, &boundary)
+        }
+        RangeRequest::Unsatisfiable => {
+            return Ok(builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", total))
+                .body(Body::empty())
+                .unwrap());
+        }
+    };
+
+    let content_length: u64 = segments.iter().map(Segment::len).sum();
+    let transfer = state.transfers.begin(
+        Direction::Download,
+        peer.clone(),
+        path.to_string_lossy().to_string(),
+        Some(content_length),
+    );
+    // 带 trailer 时必须使用分块传输，不能声明长度
+    if trailer.is_none() {
+        builder = builder.header(header::CONTENT_LENGTH, content_length);
+    }
+    let body = segment_body(
+        file,
+        segments,
+        state.throttles.download.pacer(peer),
+        transfer,
+        trailer,
+    );
+
+    Ok(builder.body(body).unwrap())
+}
+
+const REPR_DIGEST: HeaderName = HeaderName::from_static("repr-digest");
+const WANT_REPR_DIGEST: HeaderName = HeaderName::from_static("want-repr-digest");
+
+fn accepts_trailers(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::TE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|t| t.trim().eq_ignore_ascii_case("trailers"))
+}
+
+/// 发送完毕后以 `Repr-Digest` trailer 给出的摘要，算出的结果同时写入缓存。
+struct DigestTrailer {
+    hashers: Vec<Hasher>,
+    cache: ChecksumCache,
+    path: std::path::PathBuf,
+    len: u64,
+    modified: Option<std::time::SystemTime>,
+}
+
+/// 响应体的组成部分：一段内存中的字节（multipart 分隔头）或文件中的一个区间。
+enum Segment {
+    Bytes(bytes::Bytes),
+    File { start: u64, len: u64 },
+}
+
+impl Segment {
+    fn len(&self) -> u64 {
+        match self {
+            Segment::Bytes(b) => b.len() as u64,
+            Segment::File { len, .. } => *len,
+        }
+    }
+}
+
+fn multipart_segments(ranges: &[ByteRange], total: u64, boundary: &str) -> Vec<Segment> {
+    let mut segments = Vec::with_capacity(ranges.len() * 2 + 1);
+    for r in ranges {
+        let part_header = format!(
+            "\r\n--{}\r\nContent-Type: application/octet-stream\r\nContent-Range: {}\r\n\r\n",
+            boundary,
+            r.content_range(total)
+        );
+        segments.push(Segment::Bytes(part_header.into()));
+        segments.push(Segment::File {
+            start: r.start,
+            len: r.length(),
+        });
+    }
+    segments.push(Segment::Bytes(format!("\r\n--{}--\r\n", boundary).into()));
+    segments
+}
+
+/// 按顺序输出各段内容，文件区间以 512KB 分块读取并经过限速器。
+fn segment_body(
+    file: tokio::fs::File,
+    segments: Vec<Segment>,
+    pacer: Pacer,
+    mut transfer: TransferHandle,
+    mut trailer: Option<DigestTrailer>,
+) -> Body {
     let stream = async_stream::stream! {
         let mut reader = tokio::io::BufReader::with_capacity(512 * 1024, file);
         let mut buf = vec![0u8; 512 * 1024]; // 512KB chunks
-        loop {
-            let n = match reader.read(&mut buf).await {
-                Ok(0) => break,
-                Ok(n) => n,
-                Err(e) => {
-                    yield Err(std::io::Error::new(std::io::ErrorKind::Other, e));
-                    break;
+        for segment in segments {
+            let (start, len) = match segment {
+                Segment::Bytes(b) => {
+                    yield Ok::<_, std::io::Error>(Frame::data(b));
+                    continue;
                 }
+                Segment::File { start, len } => (start, len),
             };
-            throttle.consume(n).await;
-            yield Ok::<_, std::io::Error>(bytes::Bytes::from(buf[..n].to_vec()));
+            if let Err(e) = reader.seek(std::io::SeekFrom::Start(start)).await {
+                yield Err(e);
+                return;
+            }
+            let mut remaining = len;
+            while remaining > 0 {
+                let want = remaining.min(buf.len() as u64) as usize;
+                let n = match reader.read(&mut buf[..want]).await {
+                    Ok(0) => {
+                        yield Err(std::io::ErrorKind::UnexpectedEof.into());
+                        return;
+                    }
+                    Ok(n) => n,
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                };
+                remaining -= n as u64;
+                pacer.consume(n).await;
+                if let Err(e) = transfer.advance(n).await {
+                    yield Err(e.into());
+                    return;
+                }
+                if let Some(trailer) = trailer.as_mut() {
+                    for hasher in &mut trailer.hashers {
+                        hasher.update(&buf[..n]);
+                    }
+                }
+                yield Ok(Frame::data(bytes::Bytes::copy_from_slice(&buf[..n])));
+            }
         }
+        transfer.complete();
+
+        if let Some(trailer) = trailer {
+            let sums: Vec<Checksum> = trailer.hashers.into_iter().map(Hasher::finalize).collect();
+            for sum in &sums {
+                trailer.cache.insert(&trailer.path, trailer.len, trailer.modified, sum.clone());
+            }
+            let mut trailers = HeaderMap::new();
+            if let Ok(value) = HeaderValue::from_str(&checksum::repr_digest(&sums)) {
+                trailers.insert(REPR_DIGEST, value);
+            }
+            yield Ok(Frame::trailers(trailers));
+        }
+    };
+    Body::new(StreamBody::new(stream))
+}
+
+// --- File Checksums ---
+
+#[derive(Serialize, Deserialize)]
+pub struct HashQuery {
+    pub path: String,
+    /// `blake3` 或 `sha256`，不填时两种都算
+    pub algorithm: Option<String>,
+}
+
+/// 未请求的算法不出现在 JSON 中。
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileHash {
+    pub path: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blake3: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+pub async fn file_hash(
+    State(state): State<AppState>,
+    Query(query): Query<HashQuery>,
+) -> Result<Json<FileHash>, ApiError> {
+    let path = state.sandbox.resolve(&query.path, Access::Read).await?;
+    if !path.is_file() {
+        return Err(ApiError::not_found("File not found"));
+    }
+
+    let algorithms = match &query.algorithm {
+        Some(name) => vec![Algorithm::parse(name)
+            .ok_or_else(|| ApiError::bad_request(format!("Unsupported algorithm: {}", name)))?],
+        None => Algorithm::ALL.to_vec(),
+    };
+
+    let size = tokio::fs::metadata(&path).await?.len();
+    let sums = state.checksums.compute(&path, &algorithms).await?;
+
+    let mut result = FileHash {
+        path: path.to_string_lossy().to_string(),
+        size,
+        blake3: None,
+        sha256: None,
     };
+    for sum in sums {
+        match sum.algorithm {
+            Algorithm::Blake3 => result.blake3 = Some(sum.hex()),
+            Algorithm::Sha256 => result.sha256 = Some(sum.hex()),
+        }
+    }
+    Ok(Json(result))
+}
+
+// --- Directory Archive (streaming zip / tar) ---
+
+#[derive(Serialize, Deserialize)]
+pub struct ArchiveQuery {
+    pub path: String,
+    #[serde(default)]
+    pub format: ArchiveFormat,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ArchiveRequest {
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub format: ArchiveFormat,
+    /// 下载文件名（不含扩展名），多选时默认为 "transport"
+    pub name: Option<String>,
+}
+
+/// 把一个目录（或文件）打包下载。
+pub async fn download_archive(
+    State(state): State<AppState>,
+    Query(query): Query<ArchiveQuery>,
+    peer: Peer,
+) -> Result<Response, ApiError> {
+    archive_response(&state, peer, &[query.path], query.format, None).await
+}
+
+/// 把多选的若干文件/目录打成一个包下载。
+pub async fn download_selection(
+    State(state): State<AppState>,
+    peer: Peer,
+    Json(body): Json<ArchiveRequest>,
+) -> Result<Response, ApiError> {
+    archive_response(&state, peer, &body.paths, body.format, body.name).await
+}
+
+async fn archive_response(
+    state: &AppState,
+    peer: Peer,
+    paths: &[String],
+    format: ArchiveFormat,
+    name: Option<String>,
+) -> Result<Response, ApiError> {
+    if paths.is_empty() {
+        return Err(ApiError::bad_request("No paths selected"));
+    }
 
-    let body = Body::from_stream(stream);
+    let mut selected = Vec::with_capacity(paths.len());
+    for raw in paths {
+        let path = state.sandbox.resolve(raw, Access::Read).await?;
+        if !path.exists() {
+            return Err(ApiError::not_found(format!("Not found: {}", raw)));
+        }
+        selected.push(path);
+    }
 
+    let entries = archive::collect_entries(&selected).await;
+
+    let base = name
+        .map(|n| n.replace(['/', '\\', '"'], "_"))
+        .filter(|n| !n.is_empty())
+        .or_else(|| match selected.as_slice() {
+            [single] => single.file_name().map(|n| n.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .unwrap_or_else(|| "transport".to_string());
+
+    // 进度按文件内容计算；打包后的总长度未知，使用分块传输
+    let total = entries.iter().map(|e| e.size).sum();
+    let transfer = state.transfers.begin(
+        Direction::Download,
+        peer.clone(),
+        paths.join(", "),
+        Some(total),
+    );
     Ok(Response::builder()
-        .header(header::CONTENT_TYPE, "application/octet-stream")
-        .header(header::CONTENT_LENGTH, content_length)
+        .header(header::CONTENT_TYPE, format.content_type())
         .header(
             header::CONTENT_DISPOSITION,
-            format!("attachment; filename=\"{}\"", file_name),
+            format!("attachment; filename=\"{}.{}\"", base, format.extension()),
         )
-        .body(body)
+        .body(archive::archive_body(
+            entries,
+            format,
+            state.throttles.download.pacer(peer),
+            transfer,
+        ))
         .unwrap())
 }
 
 // --- File Upload (multipart, streaming, no size limit) ---
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UploadResponse {
+    /// 实际保存的文件名，不含被跳过的
+    pub saved: Vec<String>,
+    pub count: usize,
+    pub files: Vec<UploadedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UploadedFile {
+    /// 客户端给出的文件名
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved_as: Option<String>,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<Checksum>,
+    /// 没有给出期望摘要时为空
+    #[serde(default)]
+    pub verified: Option<bool>,
+}
+
 pub async fn upload_file(
+    State(state): State<AppState>,
+    peer: Peer,
     mut multipart: axum::extract::Multipart,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+) -> Result<Json<UploadResponse>, ApiError> {
     let mut target_dir = String::new();
     let mut files_saved: Vec<String> = Vec::new();
+    let mut uploaded: Vec<UploadedFile> = Vec::new();
+    // 期望的摘要只对紧随其后的那个文件字段有效
+    let mut expected: Option<Checksum> = None;
+    let mut flag_mismatch = false;
+    let mut on_conflict = ConflictPolicy::default();
 
     while let Some(mut field) = multipart
         .next_field()
         .await
-        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+        .map_err(|e| ApiError::bad_request(e.to_string()))?
     {
         let field_name = field.name().unwrap_or("").to_string();
 
@@ -182,114 +748,717 @@ pub async fn upload_file(
             target_dir = field
                 .text()
                 .await
-                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+                .map_err(|e| ApiError::bad_request(e.to_string()))?;
+            continue;
+        }
+
+        if field_name == "checksum" {
+            let text = field
+                .text()
+                .await
+                .map_err(|e| ApiError::bad_request(e.to_string()))?;
+            expected = Some(
+                Checksum::parse(&text)
+                    .ok_or_else(|| ApiError::bad_request(format!("Invalid checksum: {}", text)))?,
+            );
+            continue;
+        }
+
+        if field_name == "on_mismatch" {
+            let text = field
+                .text()
+                .await
+                .map_err(|e| ApiError::bad_request(e.to_string()))?;
+            flag_mismatch = match text.as_str() {
+                "reject" => false,
+                "flag" => true,
+                _ => {
+                    return Err(ApiError::bad_request(format!(
+                        "Invalid on_mismatch: {}",
+                        text
+                    )))
+                }
+            };
+            continue;
+        }
+
+        if field_name == "on_conflict" {
+            let text = field
+                .text()
+                .await
+                .map_err(|e| ApiError::bad_request(e.to_string()))?;
+            on_conflict = ConflictPolicy::parse(&text)
+                .ok_or_else(|| ApiError::bad_request(format!("Invalid on_conflict: {}", text)))?;
             continue;
         }
 
         if field_name == "file" {
-            let file_name = field.file_name().unwrap_or("unnamed").to_string();
+            // 只取文件名部分，忽略客户端附带的任何目录
+            let file_name = field
+                .file_name()
+                .and_then(|n| std::path::Path::new(n).file_name())
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| "unnamed".to_string());
             let dest = std::path::Path::new(&target_dir).join(&file_name);
+            let dest = state
+                .sandbox
+                .resolve(&dest.to_string_lossy(), Access::Write)
+                .await?;
+
+            let expected = expected.take();
+
+            // 跳过时不接收内容，剩余数据由 next_field 丢弃
+            if atomic::precheck(&dest, on_conflict)? {
+                uploaded.push(UploadedFile {
+                    name: file_name,
+                    saved_as: None,
+                    outcome: Outcome::Skipped,
+                    checksum: None,
+                    verified: None,
+                });
+                continue;
+            }
 
-            let mut file = tokio::fs::File::create(&dest)
-                .await
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            // 先写隐藏的临时文件，收完再改名，其他客户端看不到写了一半的文件
+            let temp = atomic::temp_path(&dest);
+            let mut file = tokio::fs::File::create(&temp).await?;
+
+            let mut transfer = state.transfers.begin(
+                Direction::Upload,
+                peer.clone(),
+                dest.to_string_lossy().to_string(),
+                None,
+            );
+
+            // 未指定期望值时也算一份 SHA-256，供之后的下载直接给出摘要
+            let mut hasher =
+                Hasher::new(expected.as_ref().map_or(Algorithm::Sha256, |c| c.algorithm));
+            let mut written = 0u64;
+            let pacer = state.throttles.upload.pacer(peer.clone());
 
             // 流式写入，不把整个文件加载到内存
-            while let Some(chunk) = field
-                .chunk()
-                .await
-                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
-            {
-                file.write_all(&chunk)
+            let received = async {
+                while let Some(chunk) = field
+                    .chunk()
                     .await
-                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                    .map_err(|e| ApiError::bad_request(e.to_string()))?
+                {
+                    file.write_all(&chunk).await?;
+                    hasher.update(&chunk);
+                    written += chunk.len() as u64;
+                    // 限速期间不读取下一块，数据积压在套接字缓冲区，发送端自然减速
+                    pacer.consume(chunk.len()).await;
+                    transfer
+                        .advance(chunk.len())
+                        .await
+                        .map_err(|e| ApiError::new(ErrorCode::Cancelled, e.to_string()))?;
+                }
+                file.flush().await.map_err(ApiError::from)
+            }
+            .await;
+            drop(file);
+            if let Err(e) = received {
+                let _ = tokio::fs::remove_file(&temp).await;
+                transfer.fail(&e.message);
+                return Err(e);
             }
 
-            files_saved.push(file_name);
+            let actual = hasher.finalize();
+            let verified = expected.as_ref().map(|e| *e == actual);
+            if verified == Some(false) && !flag_mismatch {
+                let message = format!(
+                    "Checksum mismatch for {}: expected {}, got {}",
+                    file_name,
+                    expected.as_ref().unwrap(),
+                    actual
+                );
+                let _ = tokio::fs::remove_file(&temp).await;
+                transfer.fail(&message);
+                return Err(ApiError::new(ErrorCode::ChecksumMismatch, message));
+            }
+
+            let (saved, outcome) = match atomic::persist(&temp, &dest, on_conflict).await {
+                Ok(placed) => placed,
+                Err(e) => {
+                    transfer.fail(e.to_string());
+                    return Err(e.into());
+                }
+            };
+            transfer.complete();
+
+            let saved_name = saved
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if outcome != Outcome::Skipped {
+                let modified = tokio::fs::metadata(&saved)
+                    .await
+                    .ok()
+                    .and_then(|m| m.modified().ok());
+                state
+                    .checksums
+                    .insert(&saved, written, modified, actual.clone());
+                fs_changed(&state, FsChangeKind::Created, &saved, None);
+                files_saved.push(saved_name.clone());
+            }
+            uploaded.push(UploadedFile {
+                name: file_name,
+                saved_as: Some(saved_name),
+                outcome,
+                checksum: Some(actual),
+                verified,
+            });
+        }
+    }
+
+    Ok(Json(UploadResponse {
+        count: files_saved.len(),
+        saved: files_saved,
+        files: uploaded,
+    }))
+}
+
+// --- Resumable Upload Sessions (chunked, survives disconnects) ---
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateUploadRequest {
+    pub path: String,
+    pub file_name: String,
+    pub total_size: u64,
+    /// 期望的摘要（`"sha256:<hex>"`），合并时校验
+    #[serde(default)]
+    pub checksum: Option<Checksum>,
+    #[serde(default)]
+    pub on_conflict: ConflictPolicy,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChunkQuery {
+    pub offset: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChunkReceived {
+    /// 已提交的偏移量，下一块从这里开始
+    pub offset: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FinalizedUpload {
+    /// 实际保存的文件名，跳过时为空
+    pub saved: Option<String>,
+    pub path: String,
+    pub outcome: Outcome,
+}
+
+fn session_error(e: SessionError) -> ApiError {
+    let code = match e {
+        SessionError::NotFound => ErrorCode::NotFound,
+        SessionError::Busy
+        | SessionError::OffsetMismatch { .. }
+        | SessionError::Incomplete { .. } => ErrorCode::Conflict,
+        SessionError::TooLarge => ErrorCode::TooLarge,
+        SessionError::ChecksumMismatch { .. } => ErrorCode::ChecksumMismatch,
+        SessionError::AlreadyExists => ErrorCode::AlreadyExists,
+        SessionError::Io(e) => return e.into(),
+    };
+    ApiError::new(code, e.to_string())
+}
+
+pub async fn create_upload(
+    State(state): State<AppState>,
+    Json(body): Json<CreateUploadRequest>,
+) -> Result<Json<SessionStatus>, ApiError> {
+    let dir = state.sandbox.resolve(&body.path, Access::Write).await?;
+    if !dir.is_dir() {
+        return Err(ApiError::bad_request("Target directory not found"));
+    }
+    let status = state
+        .uploads
+        .create(
+            dir.to_string_lossy().to_string(),
+            &body.file_name,
+            body.total_size,
+            body.checksum,
+            body.on_conflict,
+        )
+        .await
+        .map_err(session_error)?;
+    Ok(Json(status))
+}
+
+pub async fn upload_status(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SessionStatus>, ApiError> {
+    let status = state.uploads.status(&id).await.map_err(session_error)?;
+    Ok(Json(status))
+}
+
+/// 在 `offset` 处追加请求体；连接中途断开时已收到的字节仍会保留。
+pub async fn upload_chunk(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ChunkQuery>,
+    peer: Peer,
+    body: Body,
+) -> Result<Json<ChunkReceived>, ApiError> {
+    let mut writer = state
+        .uploads
+        .writer(&id, query.offset)
+        .await
+        .map_err(session_error)?;
+    let meta = state.uploads.status(&id).await.map_err(session_error)?.meta;
+
+    // 同一会话的各个分块在传输队列里是同一项；断开后会话仍可续传，记录也保持等待
+    let mut transfer = state
+        .transfers
+        .begin_with_id(
+            id.clone(),
+            Direction::Upload,
+            peer.clone(),
+            std::path::Path::new(&meta.path)
+                .join(&meta.file_name)
+                .to_string_lossy()
+                .to_string(),
+            Some(meta.total_size),
+            query.offset,
+        )
+        .resumable();
+
+    let pacer = state.throttles.upload.pacer(peer);
+    let mut stream = body.into_data_stream();
+    let mut failure = None;
+    let mut cancelled = false;
+    let mut interrupted = false;
+    while let Some(chunk) = stream.next().await {
+        let result = match chunk {
+            Ok(chunk) => match writer.write(&chunk).await {
+                Ok(()) => {
+                    pacer.consume(chunk.len()).await;
+                    transfer.advance(chunk.len()).await.map_err(|e| {
+                        cancelled = true;
+                        ApiError::new(ErrorCode::Cancelled, e.to_string())
+                    })
+                }
+                Err(e) => Err(session_error(e)),
+            },
+            Err(e) => {
+                interrupted = true;
+                Err(ApiError::bad_request(e.to_string()))
+            }
+        };
+        if let Err(e) = result {
+            failure = Some(e);
+            break;
         }
     }
 
-    Ok(Json(serde_json::json!({
-        "saved": files_saved,
-        "count": files_saved.len()
-    })))
+    let offset = match writer.commit().await {
+        Ok(offset) => offset,
+        Err(e) => {
+            let e = session_error(e);
+            transfer.fail(&e.message);
+            return Err(e);
+        }
+    };
+    match failure {
+        // 读请求体出错多半是客户端断开，已收到的字节已提交，等它续传
+        Some(e) if interrupted => {
+            transfer.suspend();
+            Err(e)
+        }
+        Some(e) => {
+            transfer.fail(&e.message);
+            if cancelled {
+                let _ = state.uploads.abort(&id).await;
+            }
+            Err(e)
+        }
+        None => {
+            if offset == meta.total_size {
+                transfer.complete();
+            } else {
+                transfer.suspend();
+            }
+            Ok(Json(ChunkReceived { offset }))
+        }
+    }
+}
+
+pub async fn finalize_upload(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<FinalizedUpload>, ApiError> {
+    // 共享目录可能在会话创建之后被修改，合并前重新校验
+    let status = state.uploads.status(&id).await.map_err(session_error)?;
+    state
+        .sandbox
+        .resolve(&status.meta.path, Access::Write)
+        .await?;
+    let (dest, outcome) = state.uploads.finalize(&id).await.map_err(session_error)?;
+    if outcome == Outcome::Skipped {
+        return Ok(Json(FinalizedUpload {
+            saved: None,
+            path: dest.to_string_lossy().to_string(),
+            outcome,
+        }));
+    }
+    if let Some(checksum) = status.meta.checksum {
+        let modified = tokio::fs::metadata(&dest)
+            .await
+            .ok()
+            .and_then(|m| m.modified().ok());
+        state
+            .checksums
+            .insert(&dest, status.meta.total_size, modified, checksum);
+    }
+    fs_changed(&state, FsChangeKind::Created, &dest, None);
+    Ok(Json(FinalizedUpload {
+        saved: dest.file_name().map(|n| n.to_string_lossy().to_string()),
+        path: dest.to_string_lossy().to_string(),
+        outcome,
+    }))
+}
+
+pub async fn abort_upload(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    state.uploads.abort(&id).await.map_err(session_error)?;
+    let _ = state.transfers.cancel(&id);
+    Ok(Json(serde_json::json!({"ok": true})))
+}
+
+// --- Transfer Queue ---
+
+fn transfer_error(e: TransferError) -> ApiError {
+    let code = match e {
+        TransferError::NotFound => ErrorCode::NotFound,
+        TransferError::Finished => ErrorCode::Conflict,
+    };
+    ApiError::new(code, e.to_string())
+}
+
+pub async fn list_transfers(State(state): State<AppState>) -> Json<Vec<TransferInfo>> {
+    Json(state.transfers.list())
+}
+
+pub async fn transfer_detail(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<TransferInfo>, ApiError> {
+    state
+        .transfers
+        .get(&id)
+        .map(Json)
+        .ok_or_else(|| transfer_error(TransferError::NotFound))
+}
+
+pub async fn pause_transfer(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<TransferInfo>, ApiError> {
+    state.transfers.pause(&id).map(Json).map_err(transfer_error)
+}
+
+pub async fn resume_transfer(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<TransferInfo>, ApiError> {
+    state
+        .transfers
+        .resume(&id)
+        .map(Json)
+        .map_err(transfer_error)
+}
+
+pub async fn cancel_transfer(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<TransferInfo>, ApiError> {
+    let info = state.transfers.cancel(&id).map_err(transfer_error)?;
+    // 分块上传的队列项 id 就是会话 id；正在写入时由 upload_chunk 自己清理
+    let _ = state.uploads.abort(&id).await;
+    Ok(Json(info))
+}
+
+pub async fn clear_transfers(State(state): State<AppState>) -> Json<Vec<TransferInfo>> {
+    state.transfers.clear_finished();
+    Json(state.transfers.list())
 }
 
 // --- File Operations: Delete, Rename, Mkdir ---
 
-#[derive(serde::Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct MkdirRequest {
     pub path: String,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct RenameRequest {
     pub old_path: String,
     pub new_path: String,
 }
 
 pub async fn create_directory(
+    State(state): State<AppState>,
     Json(body): Json<MkdirRequest>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    tokio::fs::create_dir_all(&body.path)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let path = state.sandbox.resolve(&body.path, Access::Write).await?;
+    tokio::fs::create_dir_all(&path).await?;
+    fs_changed(&state, FsChangeKind::Created, &path, None);
     Ok(Json(serde_json::json!({"ok": true})))
 }
 
 pub async fn rename_file(
+    State(state): State<AppState>,
     Json(body): Json<RenameRequest>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    tokio::fs::rename(&body.old_path, &body.new_path)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let old_path = state
+        .sandbox
+        .resolve(&body.old_path, Access::Remove)
+        .await?;
+    // 目标已存在时会被替换，按删除处理
+    let new_path = state
+        .sandbox
+        .resolve(&body.new_path, Access::Remove)
+        .await?;
+    tokio::fs::rename(&old_path, &new_path).await?;
+    fs_changed(&state, FsChangeKind::Renamed, &new_path, Some(&old_path));
     Ok(Json(serde_json::json!({"ok": true})))
 }
 
 pub async fn delete_file(
+    State(state): State<AppState>,
     Query(query): Query<FilePathQuery>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let path = std::path::Path::new(&query.path);
-    if path.is_dir() {
-        tokio::fs::remove_dir_all(path).await
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let path = state.sandbox.resolve(&query.path, Access::Remove).await?;
+    // 符号链接只删链接本身
+    if tokio::fs::symlink_metadata(&path).await?.is_dir() {
+        tokio::fs::remove_dir_all(&path).await
     } else {
-        tokio::fs::remove_file(path).await
-    }
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        tokio::fs::remove_file(&path).await
+    }?;
+    fs_changed(&state, FsChangeKind::Removed, &path, None);
     Ok(Json(serde_json::json!({"ok": true})))
 }
 
+/// 通知其他界面共享目录里有内容被修改。
+fn fs_changed(
+    state: &AppState,
+    kind: FsChangeKind,
+    path: &std::path::Path,
+    from: Option<&std::path::Path>,
+) {
+    state.events.publish(Event::FsChanged(FsChange {
+        kind,
+        path: path.to_string_lossy().to_string(),
+        from: from.map(|p| p.to_string_lossy().to_string()),
+    }));
+}
+
+// --- Live Events (SSE) ---
+
+/// 推送 `events::Event`；订阅者处理不过来丢了事件时发送 `resync`，客户端应重新拉取完整状态。
+/// 服务停止时结束，不阻塞优雅关闭。
+pub async fn event_stream(
+    State(state): State<AppState>,
+    shutdown: Option<Extension<CancellationToken>>,
+) -> Sse<impl futures_util::Stream<Item = Result<sse::Event, std::convert::Infallible>>> {
+    let mut receiver = state.events.subscribe();
+    let shutdown = shutdown.map(|Extension(token)| token).unwrap_or_default();
+    let stream = async_stream::stream! {
+        loop {
+            let received = tokio::select! {
+                received = receiver.recv() => received,
+                _ = shutdown.cancelled() => break,
+            };
+            match received {
+                Ok(event) => {
+                    let data = serde_json::to_string(&event).unwrap_or_default();
+                    yield Ok(sse::Event::default().event(event.name()).data(data));
+                }
+                Err(RecvError::Lagged(_)) => {
+                    yield Ok(sse::Event::default().event("resync").data("{}"));
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    };
+    Sse::new(stream).keep_alive(sse::KeepAlive::default())
+}
+
+// --- Settings ---
+
+pub async fn get_settings(State(state): State<AppState>) -> Json<Settings> {
+    Json(state.settings.get())
+}
+
+/// 设置只能在本机修改；已配对设备可以读取，但不能改共享目录、端口和 HTTPS 等。
+fn require_host(caller: &Caller) -> Result<(), ApiError> {
+    match caller {
+        Caller::Local => Ok(()),
+        Caller::Device(_) => Err(ApiError::new(
+            ErrorCode::Forbidden,
+            "Only the host can change settings",
+        )),
+    }
+}
+
+/// 请求体中出现的顶层字段覆盖当前设置，其余保持不变。
+pub async fn update_settings(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Json(patch): Json<serde_json::Value>,
+) -> Result<Json<Settings>, ApiError> {
+    require_host(&caller)?;
+    let serde_json::Value::Object(patch) = patch else {
+        return Err(ApiError::bad_request("Expected a JSON object"));
+    };
+    let mut merged = serde_json::to_value(state.settings.saved())
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    if let Some(current) = merged.as_object_mut() {
+        current.extend(patch);
+    }
+    let settings: Settings =
+        serde_json::from_value(merged).map_err(|e| ApiError::bad_request(e.to_string()))?;
+    schedule::validate_rules(&settings.schedule).map_err(ApiError::bad_request)?;
+
+    let settings = state.settings.replace(settings).await?;
+    apply_settings(&state, &settings).await;
+    Ok(Json(settings))
+}
+
+/// 修改设置、写回文件并立即应用。
+async fn save_settings(state: &AppState, f: impl FnOnce(&mut Settings)) -> Result<(), ApiError> {
+    let settings = state.settings.update(f).await?;
+    apply_settings(state, &settings).await;
+    Ok(())
+}
+
 // --- Throttle Settings (for browser mode) ---
 
-#[derive(serde::Deserialize)]
+/// 字段都可省略；`bytes_per_sec` 是旧接口，同时设置上传和下载的全局上限。
+/// `*_peers` 只更新列出的对端，速率为 0 表示取消该对端的上限。
+#[derive(Serialize, Deserialize, Default)]
 pub struct ThrottleRequest {
+    pub bytes_per_sec: Option<u64>,
+    pub upload_bytes_per_sec: Option<u64>,
+    pub download_bytes_per_sec: Option<u64>,
+    #[serde(default)]
+    pub upload_peers: Vec<PeerRate>,
+    #[serde(default)]
+    pub download_peers: Vec<PeerRate>,
+    /// 单个传输的上限，对之后开始的传输生效
+    pub upload_transfer_bytes_per_sec: Option<u64>,
+    pub download_transfer_bytes_per_sec: Option<u64>,
+}
+
+/// 当前生效的全局速率（已计入时段规则）和各对端上限。
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThrottleSettings {
+    /// 旧接口字段，等于下载速率
     pub bytes_per_sec: u64,
+    pub upload_bytes_per_sec: u64,
+    pub download_bytes_per_sec: u64,
+    pub upload_peers: Vec<PeerRate>,
+    pub download_peers: Vec<PeerRate>,
+    #[serde(default)]
+    pub upload_transfer_bytes_per_sec: u64,
+    #[serde(default)]
+    pub download_transfer_bytes_per_sec: u64,
 }
 
-pub async fn get_throttle(State(state): State<AppState>) -> Json<serde_json::Value> {
-    let rate = state.throttle.get_rate().await;
-    Json(serde_json::json!({"bytes_per_sec": rate}))
+pub async fn get_throttle(State(state): State<AppState>) -> Json<ThrottleSettings> {
+    let upload = state.throttles.upload.global().get_rate().await;
+    let download = state.throttles.download.global().get_rate().await;
+    Json(ThrottleSettings {
+        bytes_per_sec: download,
+        upload_bytes_per_sec: upload,
+        download_bytes_per_sec: download,
+        upload_peers: state.throttles.upload.peer_rates().await,
+        download_peers: state.throttles.download.peer_rates().await,
+        upload_transfer_bytes_per_sec: state.throttles.upload.transfer_rate(),
+        download_transfer_bytes_per_sec: state.throttles.download.transfer_rate(),
+    })
 }
 
 pub async fn set_throttle(
     State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
     Json(body): Json<ThrottleRequest>,
-) -> Json<serde_json::Value> {
-    state.throttle.set_rate(body.bytes_per_sec).await;
-    Json(serde_json::json!({"ok": true}))
+) -> Result<Json<serde_json::Value>, ApiError> {
+    require_host(&caller)?;
+    // 设置的是基础速率，时段规则命中时以规则为准
+    save_settings(&state, |settings| {
+        if let Some(rate) = body.upload_bytes_per_sec.or(body.bytes_per_sec) {
+            settings.upload_bytes_per_sec = rate;
+        }
+        if let Some(rate) = body.download_bytes_per_sec.or(body.bytes_per_sec) {
+            settings.download_bytes_per_sec = rate;
+        }
+        merge_peer_rates(&mut settings.upload_peers, body.upload_peers);
+        merge_peer_rates(&mut settings.download_peers, body.download_peers);
+        if let Some(rate) = body.upload_transfer_bytes_per_sec {
+            settings.upload_transfer_bytes_per_sec = rate;
+        }
+        if let Some(rate) = body.download_transfer_bytes_per_sec {
+            settings.download_transfer_bytes_per_sec = rate;
+        }
+    })
+    .await?;
+    Ok(Json(serde_json::json!({"ok": true})))
+}
+
+fn merge_peer_rates(rates: &mut Vec<PeerRate>, updates: Vec<PeerRate>) {
+    for update in updates {
+        rates.retain(|r| r.peer != update.peer);
+        if update.bytes_per_sec > 0 {
+            rates.push(update);
+        }
+    }
+}
+
+// --- Bandwidth Schedule ---
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ScheduleSettings {
+    pub rules: Vec<ScheduleRule>,
+    pub effective: EffectiveRate,
+}
+
+pub async fn get_schedule(State(state): State<AppState>) -> Json<ScheduleSettings> {
+    Json(ScheduleSettings {
+        rules: state.schedule.rules(),
+        effective: state.schedule.effective(),
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ScheduleRequest {
+    pub rules: Vec<ScheduleRule>,
+}
+
+pub async fn set_schedule(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Json(body): Json<ScheduleRequest>,
+) -> Result<Json<EffectiveRate>, ApiError> {
+    require_host(&caller)?;
+    schedule::validate_rules(&body.rules).map_err(ApiError::bad_request)?;
+    save_settings(&state, |settings| settings.schedule = body.rules).await?;
+    Ok(Json(state.schedule.effective()))
 }
 
 // --- Log Sink (receives browser pino logs, writes to logs/ folder) ---
 
-pub async fn receive_logs(
-    body: axum::body::Bytes,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+pub async fn receive_logs(body: axum::body::Bytes) -> Result<Json<serde_json::Value>, ApiError> {
     use std::io::Write;
 
     let logs_dir = std::path::Path::new("logs");
     if !logs_dir.exists() {
-        std::fs::create_dir_all(logs_dir)
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        std::fs::create_dir_all(logs_dir)?;
     }
 
     let today = chrono::Local::now().format("%Y-%m-%d").to_string();
@@ -298,12 +1467,10 @@ pub async fn receive_logs(
     let mut file = std::fs::OpenOptions::new()
         .create(true)
         .append(true)
-        .open(&log_file)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .open(&log_file)?;
 
     let timestamp = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f");
-    writeln!(file, "[{}] {}", timestamp, String::from_utf8_lossy(&body))
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    writeln!(file, "[{}] {}", timestamp, String::from_utf8_lossy(&body))?;
 
     Ok(Json(serde_json::json!({"ok": true})))
 }
@@ -313,6 +1480,7 @@ pub async fn receive_logs(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::server::test_state;
     use std::fs;
     use tempfile::tempdir;
 
@@ -325,7 +1493,9 @@ mod tests {
         let query = FileListQuery {
             path: dir.path().to_string_lossy().to_string(),
         };
-        let Json(entries) = list_files(Query(query)).await.unwrap();
+        let Json(entries) = list_files(State(test_state(dir.path())), Query(query))
+            .await
+            .unwrap();
 
         assert_eq!(entries.len(), 2);
         let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
@@ -335,10 +1505,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_list_files_invalid_path_returns_error() {
+        let dir = tempdir().unwrap();
         let query = FileListQuery {
             path: "/nonexistent/path/xyz".to_string(),
         };
-        let result = list_files(Query(query)).await;
+        let result = list_files(State(test_state(dir.path())), Query(query)).await;
         assert!(result.is_err());
     }
 
@@ -349,7 +1520,7 @@ mod tests {
         let body = MkdirRequest {
             path: new_dir.to_string_lossy().to_string(),
         };
-        let result = create_directory(Json(body)).await;
+        let result = create_directory(State(test_state(dir.path())), Json(body)).await;
         assert!(result.is_ok());
         assert!(new_dir.exists());
     }
@@ -365,7 +1536,7 @@ mod tests {
             old_path: old.to_string_lossy().to_string(),
             new_path: new_path.to_string_lossy().to_string(),
         };
-        let result = rename_file(Json(body)).await;
+        let result = rename_file(State(test_state(dir.path())), Json(body)).await;
         assert!(result.is_ok());
         assert!(!old.exists());
         assert!(new_path.exists());
@@ -380,8 +1551,583 @@ mod tests {
         let query = FilePathQuery {
             path: file.to_string_lossy().to_string(),
         };
-        let result = delete_file(Query(query)).await;
+        let result = delete_file(State(test_state(dir.path())), Query(query)).await;
         assert!(result.is_ok());
         assert!(!file.exists());
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_delete_and_rename_symlink_act_on_link() {
+        let dir = tempdir().unwrap();
+        let state = test_state(dir.path());
+        let target = dir.path().join("photos");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("a.jpg"), "jpg").unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let body = RenameRequest {
+            old_path: link.to_string_lossy().to_string(),
+            new_path: dir.path().join("moved").to_string_lossy().to_string(),
+        };
+        let _ = rename_file(State(state.clone()), Json(body)).await.unwrap();
+        let moved = dir.path().join("moved");
+        assert!(fs::symlink_metadata(&moved).unwrap().is_symlink());
+        assert!(target.join("a.jpg").exists());
+
+        let query = FilePathQuery {
+            path: moved.to_string_lossy().to_string(),
+        };
+        let _ = delete_file(State(state), Query(query)).await.unwrap();
+        assert!(fs::symlink_metadata(&moved).is_err());
+        assert!(target.join("a.jpg").exists());
+    }
+
+    #[tokio::test]
+    async fn test_io_errors_map_to_error_codes() {
+        let dir = tempdir().unwrap();
+        let state = test_state(dir.path());
+        let missing = dir.path().join("missing.txt");
+
+        let query = FilePathQuery {
+            path: missing.to_string_lossy().to_string(),
+        };
+        let err = delete_file(State(state.clone()), Query(query))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+        assert_eq!(err.code, ErrorCode::NotFound);
+
+        let query = FileListQuery {
+            path: missing.to_string_lossy().to_string(),
+        };
+        let err = list_files(State(state), Query(query)).await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_paths_outside_shared_roots_are_forbidden() {
+        let shared = tempdir().unwrap();
+        let other = tempdir().unwrap();
+        let victim = other.path().join("keep.txt");
+        fs::write(&victim, "test").unwrap();
+
+        let query = FilePathQuery {
+            path: victim.to_string_lossy().to_string(),
+        };
+        let status = delete_file(State(test_state(shared.path())), Query(query))
+            .await
+            .unwrap_err()
+            .status();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(victim.exists());
+
+        let query = FilePathQuery {
+            path: shared.path().to_string_lossy().to_string(),
+        };
+        let status = delete_file(State(test_state(shared.path())), Query(query))
+            .await
+            .unwrap_err()
+            .status();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(shared.path().exists());
+    }
+
+    async fn download(path: &std::path::Path, range: Option<&str>) -> Response {
+        let root = path.parent().unwrap();
+        let mut headers = HeaderMap::new();
+        if let Some(range) = range {
+            headers.insert(header::RANGE, range.parse().unwrap());
+        }
+        let query = FilePathQuery {
+            path: path.to_string_lossy().to_string(),
+        };
+        download_file(
+            State(test_state(root)),
+            Query(query),
+            Peer::default(),
+            headers,
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn body_string(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_download_full_advertises_ranges() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        fs::write(&file, "0123456789").unwrap();
+
+        let response = download(&file, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+        assert!(response.headers().contains_key(header::ETAG));
+        assert_eq!(body_string(response).await, "0123456789");
+    }
+
+    #[tokio::test]
+    async fn test_download_single_range() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        fs::write(&file, "0123456789").unwrap();
+
+        let response = download(&file, Some("bytes=4-")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 4-9/10");
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "6");
+        assert_eq!(body_string(response).await, "456789");
+    }
+
+    #[tokio::test]
+    async fn test_download_multi_range() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        fs::write(&file, "0123456789").unwrap();
+
+        let response = download(&file, Some("bytes=0-1,8-9")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(content_type.starts_with("multipart/byteranges; boundary="));
+        let declared: usize = response.headers()[header::CONTENT_LENGTH]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        let body = body_string(response).await;
+        assert_eq!(body.len(), declared);
+        assert!(body.contains("Content-Range: bytes 0-1/10\r\n\r\n01\r\n"));
+        assert!(body.contains("Content-Range: bytes 8-9/10\r\n\r\n89\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_download_unsatisfiable_range() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        fs::write(&file, "0123456789").unwrap();
+
+        let response = download(&file, Some("bytes=10-")).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */10");
+    }
+
+    #[tokio::test]
+    async fn test_download_is_tracked_in_transfer_queue() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        fs::write(&file, "0123456789").unwrap();
+        let state = test_state(dir.path());
+
+        let query = FilePathQuery {
+            path: file.to_string_lossy().to_string(),
+        };
+        let response = download_file(
+            State(state.clone()),
+            Query(query),
+            Peer::default(),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(body_string(response).await, "0123456789");
+
+        let Json(transfers) = list_transfers(State(state.clone())).await;
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].transferred, 10);
+        assert_eq!(transfers[0].total, Some(10));
+        assert_eq!(
+            transfers[0].status,
+            crate::transfer::manager::TransferStatus::Completed
+        );
+
+        let status = cancel_transfer(State(state), Path(transfers[0].id.clone()))
+            .await
+            .unwrap_err()
+            .status();
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    // "0123456789" 的 SHA-256
+    const DIGITS_SHA256: &str = "84d89877f0d4041efb6bf91a16f0248f2fd573e6af05c19f96bedb9f882f7882";
+
+    #[tokio::test]
+    async fn test_download_sends_digest_trailer_then_header() {
+        use http_body_util::BodyExt;
+
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        fs::write(&file, "0123456789").unwrap();
+        let state = test_state(dir.path());
+        let query = || FilePathQuery {
+            path: file.to_string_lossy().to_string(),
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::TE, "trailers".parse().unwrap());
+        let response = download_file(
+            State(state.clone()),
+            Query(query()),
+            Peer::default(),
+            headers,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.headers()[header::TRAILER], "repr-digest");
+        assert!(!response.headers().contains_key(header::CONTENT_LENGTH));
+        let collected = response.into_body().collect().await.unwrap();
+        let expected =
+            checksum::repr_digest(
+                &[Checksum::parse(&format!("sha256:{}", DIGITS_SHA256)).unwrap()],
+            );
+        assert_eq!(
+            collected.trailers().unwrap()[REPR_DIGEST],
+            expected.as_str()
+        );
+        assert_eq!(collected.to_bytes(), "0123456789");
+
+        // 摘要已缓存，后续下载直接放在响应头里
+        let response = download_file(
+            State(state),
+            Query(query()),
+            Peer::default(),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.headers()[REPR_DIGEST], expected.as_str());
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "10");
+    }
+
+    #[tokio::test]
+    async fn test_file_hash() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        fs::write(&file, "0123456789").unwrap();
+        let state = test_state(dir.path());
+
+        let query = HashQuery {
+            path: file.to_string_lossy().to_string(),
+            algorithm: None,
+        };
+        let Json(result) = file_hash(State(state.clone()), Query(query)).await.unwrap();
+        let result = serde_json::to_value(result).unwrap();
+        assert_eq!(result["size"], 10);
+        assert_eq!(result["sha256"], DIGITS_SHA256);
+        assert_eq!(
+            result["blake3"],
+            blake3::hash(b"0123456789").to_hex().as_str()
+        );
+
+        let query = HashQuery {
+            path: file.to_string_lossy().to_string(),
+            algorithm: Some("md5".to_string()),
+        };
+        let status = file_hash(State(state), Query(query))
+            .await
+            .unwrap_err()
+            .status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    async fn upload(
+        state: &AppState,
+        dir: &std::path::Path,
+        fields: &[(&str, &str)],
+    ) -> Result<Json<serde_json::Value>, ApiError> {
+        use axum::extract::FromRequest;
+
+        let mut body = format!(
+            "--X\r\nContent-Disposition: form-data; name=\"path\"\r\n\r\n{}\r\n",
+            dir.display()
+        );
+        for (name, value) in fields {
+            let disposition = if *name == "file" {
+                "name=\"file\"; filename=\"data.txt\""
+            } else {
+                &format!("name=\"{}\"", name)
+            };
+            body += &format!(
+                "--X\r\nContent-Disposition: form-data; {}\r\n\r\n{}\r\n",
+                disposition, value
+            );
+        }
+        body += "--X--\r\n";
+        let request = axum::http::Request::builder()
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=X")
+            .body(Body::from(body))
+            .unwrap();
+        let multipart = axum::extract::Multipart::from_request(request, &())
+            .await
+            .unwrap();
+        upload_file(State(state.clone()), Peer::default(), multipart)
+            .await
+            .map(|Json(response)| Json(serde_json::to_value(response).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn test_upload_verifies_checksum() {
+        let dir = tempdir().unwrap();
+        let state = test_state(dir.path());
+        let file = dir.path().join("data.txt");
+        let good = format!("sha256:{}", DIGITS_SHA256);
+        let bad = format!("sha256:{}", "0".repeat(64));
+
+        let Json(result) = upload(
+            &state,
+            dir.path(),
+            &[("checksum", &good), ("file", "0123456789")],
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["files"][0]["verified"], true);
+        assert_eq!(result["files"][0]["checksum"], good);
+
+        let err = upload(
+            &state,
+            dir.path(),
+            &[("checksum", &bad), ("file", "0123456789")],
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(err.code, ErrorCode::ChecksumMismatch);
+        assert!(err.message.contains("mismatch"));
+        // 校验失败的上传不会动到已有文件，也不留下临时文件
+        assert_eq!(fs::read_to_string(&file).unwrap(), "0123456789");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let Json(result) = upload(
+            &state,
+            dir.path(),
+            &[
+                ("on_mismatch", "flag"),
+                ("checksum", &bad),
+                ("file", "0123456789"),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["files"][0]["verified"], false);
+        assert!(file.exists());
+
+        let status = upload(&state, dir.path(), &[("checksum", "sha256:zz")])
+            .await
+            .unwrap_err()
+            .status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_upload_conflict_policy() {
+        let dir = tempdir().unwrap();
+        let state = test_state(dir.path());
+
+        let Json(result) = upload(&state, dir.path(), &[("file", "one")])
+            .await
+            .unwrap();
+        assert_eq!(result["files"][0]["outcome"], "created");
+        let Json(result) = upload(&state, dir.path(), &[("file", "two")])
+            .await
+            .unwrap();
+        assert_eq!(result["files"][0]["outcome"], "overwritten");
+
+        let Json(result) = upload(
+            &state,
+            dir.path(),
+            &[("on_conflict", "rename"), ("file", "three")],
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["files"][0]["outcome"], "renamed");
+        assert_eq!(result["saved"][0], "data (1).txt");
+
+        let Json(result) = upload(
+            &state,
+            dir.path(),
+            &[("on_conflict", "skip"), ("file", "four")],
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["files"][0]["outcome"], "skipped");
+        assert_eq!(result["count"], 0);
+
+        let status = upload(
+            &state,
+            dir.path(),
+            &[("on_conflict", "fail"), ("file", "five")],
+        )
+        .await
+        .unwrap_err()
+        .status();
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("data.txt")).unwrap(),
+            "two"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("data (1).txt")).unwrap(),
+            "three"
+        );
+        // 没有残留的临时文件
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_upload_and_download_rates_are_independent() {
+        let dir = tempdir().unwrap();
+        let state = test_state(dir.path());
+
+        let body = ThrottleRequest {
+            bytes_per_sec: None,
+            upload_bytes_per_sec: Some(1000),
+            download_bytes_per_sec: None,
+            upload_peers: Vec::new(),
+            download_peers: vec![PeerRate {
+                peer: "10.0.0.2".to_string(),
+                bytes_per_sec: 500,
+            }],
+            upload_transfer_bytes_per_sec: None,
+            download_transfer_bytes_per_sec: Some(200),
+        };
+        let _ = set_throttle(State(state.clone()), Extension(Caller::Local), Json(body)).await;
+        let Json(rates) = get_throttle(State(state.clone())).await;
+        let rates = serde_json::to_value(rates).unwrap();
+        assert_eq!(rates["upload_bytes_per_sec"], 1000);
+        assert_eq!(rates["download_bytes_per_sec"], 0);
+        assert_eq!(
+            rates["download_peers"],
+            serde_json::json!([{"peer": "10.0.0.2", "bytes_per_sec": 500}])
+        );
+        assert_eq!(rates["download_transfer_bytes_per_sec"], 200);
+        assert_eq!(rates["upload_transfer_bytes_per_sec"], 0);
+
+        // 初始 1000 字节的额度用完后，剩下的 500 字节要等约 500ms
+        let start = std::time::Instant::now();
+        let _ = upload(&state, dir.path(), &[("file", &"x".repeat(1500))])
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_settings_patch_is_persisted_and_applied() {
+        let dir = tempdir().unwrap();
+        let state = test_state(dir.path());
+
+        let patch = serde_json::json!({
+            "device_name": "desk",
+            "download_bytes_per_sec": 2048,
+            "upload_peers": [{"peer": "phone", "bytes_per_sec": 100}],
+        });
+        let Json(settings) =
+            update_settings(State(state.clone()), Extension(Caller::Local), Json(patch))
+                .await
+                .unwrap();
+        assert_eq!(settings.device_name.as_deref(), Some("desk"));
+        // 未出现的字段保持不变
+        assert_eq!(settings.shared_roots[0].path, dir.path());
+        assert_eq!(state.device_info().name, "desk");
+        assert_eq!(state.throttles.download.global().get_rate().await, 2048);
+        assert_eq!(state.throttles.upload.peer_rates().await.len(), 1);
+
+        let saved = crate::settings::SettingsStore::load(dir.path().join(".settings.json"));
+        assert_eq!(saved.get(), settings);
+
+        let bad = serde_json::json!({"port": "http"});
+        let status = update_settings(State(state.clone()), Extension(Caller::Local), Json(bad))
+            .await
+            .unwrap_err()
+            .status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let bad =
+            serde_json::json!({"schedule": [{"days": [9], "start": "01:00", "end": "02:00"}]});
+        let status = update_settings(State(state.clone()), Extension(Caller::Local), Json(bad))
+            .await
+            .unwrap_err()
+            .status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(get_settings(State(state)).await.0, settings);
+    }
+
+    #[tokio::test]
+    async fn test_devices_cannot_change_settings() {
+        let dir = tempdir().unwrap();
+        let state = test_state(dir.path());
+        let device = || Extension(Caller::Device("phone".to_string()));
+
+        let patch = serde_json::json!({"shared_roots": [], "https": true});
+        let err = update_settings(State(state.clone()), device(), Json(patch))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+        let body: ThrottleRequest =
+            serde_json::from_value(serde_json::json!({"bytes_per_sec": 1})).unwrap();
+        let err = set_throttle(State(state.clone()), device(), Json(body))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+        let rules = ScheduleRequest { rules: Vec::new() };
+        let err = set_schedule(State(state.clone()), device(), Json(rules))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+
+        let settings = state.settings.get();
+        assert_eq!(settings.shared_roots[0].path, dir.path());
+        assert!(!settings.https);
+        assert_eq!(settings.download_bytes_per_sec, 0);
+    }
+
+    #[tokio::test]
+    async fn test_schedule_overrides_base_rate() {
+        let dir = tempdir().unwrap();
+        let state = test_state(dir.path());
+        let rules: Vec<ScheduleRule> = serde_json::from_value(serde_json::json!([
+            {"start": "00:00", "end": "00:00", "download_bytes_per_sec": 4096}
+        ]))
+        .unwrap();
+        let Json(effective) = set_schedule(
+            State(state.clone()),
+            Extension(Caller::Local),
+            Json(ScheduleRequest { rules }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(effective.rule, Some(0));
+
+        let body: ThrottleRequest =
+            serde_json::from_value(serde_json::json!({"bytes_per_sec": 1000})).unwrap();
+        let _ = set_throttle(State(state.clone()), Extension(Caller::Local), Json(body)).await;
+        let Json(current) = get_schedule(State(state.clone())).await;
+        assert_eq!(current.effective.rule, Some(0));
+        assert_eq!(current.effective.download_bytes_per_sec, 4096);
+        assert_eq!(current.effective.upload_bytes_per_sec, 1000);
+        assert_eq!(state.throttles.download.global().get_rate().await, 4096);
+
+        let rules: Vec<ScheduleRule> = serde_json::from_value(serde_json::json!([
+            {"days": [0], "start": "09:00", "end": "18:00"}
+        ]))
+        .unwrap();
+        let status = set_schedule(
+            State(state),
+            Extension(Caller::Local),
+            Json(ScheduleRequest { rules }),
+        )
+        .await
+        .unwrap_err()
+        .status();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
 }
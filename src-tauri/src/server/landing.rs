@@ -1,11 +1,13 @@
 use axum::body::Body;
 use axum::extract::Path;
-use axum::http::{header, StatusCode};
+use axum::http::header;
 use axum::response::{Html, Response};
 use axum::Json;
 use serde::Serialize;
 use std::path::PathBuf;
 
+use super::error::ApiError;
+
 const LANDING_HTML: &str = include_str!("../../assets/landing.html");
 
 fn installers_dir() -> PathBuf {
@@ -47,30 +49,26 @@ pub async fn list_installers() -> Json<InstallerInfo> {
     Json(InstallerInfo { available })
 }
 
-pub async fn download_installer(
-    Path(platform): Path<String>,
-) -> Result<Response, (StatusCode, String)> {
+pub async fn download_installer(Path(platform): Path<String>) -> Result<Response, ApiError> {
     let dir = installers_dir();
     let extensions: &[&str] = match platform.as_str() {
         "windows" => &["exe", "msi"],
         "macos" => &["dmg"],
         "android" => &["apk"],
-        _ => return Err((StatusCode::BAD_REQUEST, "Invalid platform".to_string())),
+        _ => return Err(ApiError::bad_request("Invalid platform")),
     };
 
     let file_path = std::fs::read_dir(&dir)
-        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?
+        .map_err(|e| ApiError::not_found(e.to_string()))?
         .flatten()
         .find(|entry| {
             let name = entry.file_name().to_string_lossy().to_lowercase();
             extensions.iter().any(|ext| name.ends_with(ext))
         })
         .map(|e| e.path())
-        .ok_or((StatusCode::NOT_FOUND, "Installer not found".to_string()))?;
+        .ok_or(ApiError::not_found("Installer not found"))?;
 
-    let file = tokio::fs::File::open(&file_path)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let file = tokio::fs::File::open(&file_path).await?;
 
     let file_name = file_path
         .file_name()
@@ -1,67 +1,821 @@
+pub mod archive;
+pub mod auth;
+pub mod error;
+pub mod frontend;
 pub mod handlers;
 pub mod landing;
+pub mod network;
+pub mod range;
 pub mod routes;
+pub mod sandbox;
+pub mod tls;
 
-use std::net::SocketAddr;
-use axum::Router;
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
-use tower_http::cors::CorsLayer;
-use tower_http::services::{ServeDir, ServeFile};
+use axum::serve::ListenerExt;
+use axum::{Extension, Router};
+use bytes::Bytes;
+use http_body::{Frame, SizeHint};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+use crate::discovery::Discovery;
+use crate::events::EventBus;
+use crate::identity::Identity;
+use crate::settings::{Settings, SettingsStore};
+use crate::transfer::checksum::ChecksumCache;
+use crate::transfer::limiter::Throttles;
+use crate::transfer::manager::TransferManager;
+use crate::transfer::schedule::BandwidthSchedule;
+use crate::transfer::session::UploadSessions;
+use auth::Pairing;
+use error::{ApiError, ErrorCode};
+use sandbox::Sandbox;
+
+/// 强制断开后等服务任务结束的时间，超过就直接中止。
+const FORCE_CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
 
-use crate::transfer::throttle::Throttle;
+/// 超过这个时间没有新数据的上传会话会在启动时被清理。
+const UPLOAD_SESSION_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 3600);
 
 #[derive(Clone)]
 pub struct AppState {
-    pub throttle: Throttle,
-}
-
-fn find_frontend_dist() -> std::path::PathBuf {
-    // Try multiple locations for the frontend dist
-    let candidates = [
-        // When running via `cargo run` from src-tauri/
-        std::path::PathBuf::from("../dist"),
-        // When running from the project root
-        std::path::PathBuf::from("dist"),
-        // Relative to the executable
-        std::env::current_exe()
-            .ok()
-            .and_then(|p| p.parent().map(|p| p.join("../dist")))
-            .unwrap_or_else(|| std::path::PathBuf::from("../dist")),
-    ];
-
-    for candidate in &candidates {
-        if candidate.join("index.html").exists() {
-            println!("Frontend dist found at: {}", candidate.display());
-            return candidate.clone();
+    /// 实际监听的地址；绑定成功前为配置的端口
+    local_addr: Arc<RwLock<SocketAddr>>,
+    /// 当前是否启用了 HTTPS；修改设置后重启服务才变
+    https: Arc<AtomicBool>,
+    /// 上传、下载分别限速
+    pub throttles: Throttles,
+    /// 按时段调整 `throttles` 的全局速率
+    pub schedule: BandwidthSchedule,
+    pub discovery: Discovery,
+    pub sandbox: Sandbox,
+    pub settings: SettingsStore,
+    pub pairing: Pairing,
+    pub uploads: UploadSessions,
+    pub transfers: TransferManager,
+    pub checksums: ChecksumCache,
+    pub events: EventBus,
+    /// 本机设备身份
+    pub identity: Identity,
+}
+
+/// 启动时确定、运行中不再变化的选项。
+#[derive(Clone, Debug)]
+pub struct ServerOptions {
+    /// 监听地址，端口来自设置；默认 `[::]` 同时接受 IPv4 和 IPv6
+    pub bind: IpAddr,
+    /// 配置的端口被占用时怎么办
+    pub port_fallback: PortFallback,
+    /// 停止时最多等进行中的传输多久
+    pub drain_timeout: Duration,
+    /// 从磁盘读取前端的目录，`None` 时使用编译时嵌入的版本
+    pub frontend_dist: Option<PathBuf>,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            port_fallback: PortFallback::default(),
+            drain_timeout: Duration::from_secs(30),
+            frontend_dist: None,
+        }
+    }
+}
+
+/// 端口被占用时的处理方式。其他绑定错误（权限不足、地址不存在）换端口也没用，直接报错。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PortFallback {
+    /// 只使用配置的端口
+    Strict,
+    /// 依次尝试其后的 n 个端口
+    Range(u16),
+    /// 由系统分配一个空闲端口
+    Any,
+}
+
+impl Default for PortFallback {
+    fn default() -> Self {
+        PortFallback::Range(10)
+    }
+}
+
+impl PortFallback {
+    /// 按顺序尝试的端口，`0` 表示由系统分配。
+    fn candidates(self, port: u16) -> Vec<u16> {
+        let mut ports = vec![port];
+        if port == 0 {
+            return ports;
+        }
+        match self {
+            PortFallback::Strict => {}
+            PortFallback::Range(n) => {
+                ports.extend((1..=n).map_while(|i| port.checked_add(i)));
+            }
+            PortFallback::Any => ports.push(0),
+        }
+        ports
+    }
+}
+
+/// `strict`、`any` 或要尝试的后续端口数。
+impl FromStr for PortFallback {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "strict" => Ok(PortFallback::Strict),
+            "any" => Ok(PortFallback::Any),
+            n => n.parse().map(PortFallback::Range).map_err(|_| {
+                format!(
+                    "invalid port fallback '{}', expected strict, any or a count",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ServerError {
+    /// 无法监听；端口都被占用时 `addr` 是最后尝试的一个
+    Bind {
+        addr: SocketAddr,
+        source: io::Error,
+    },
+    /// 无法生成证书
+    Tls(io::Error),
+    Serve(io::Error),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::Bind { addr, source } if source.kind() == io::ErrorKind::AddrInUse => {
+                write!(f, "Cannot listen on {}: port is already in use", addr)
+            }
+            ServerError::Bind { addr, source } => {
+                write!(f, "Cannot listen on {}: {}", addr, source)
+            }
+            ServerError::Tls(e) => write!(f, "Cannot set up HTTPS: {}", e),
+            ServerError::Serve(e) => write!(f, "Server error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ServerError::Bind { source, .. } => Some(source),
+            ServerError::Tls(e) | ServerError::Serve(e) => Some(e),
+        }
+    }
+}
+
+/// 监听 `ip:port`，端口被占用时按 `fallback` 换一个。
+pub async fn bind(
+    ip: IpAddr,
+    port: u16,
+    fallback: PortFallback,
+) -> Result<TcpListener, ServerError> {
+    let mut last = None;
+    for candidate in fallback.candidates(port) {
+        let addr = SocketAddr::new(ip, candidate);
+        match network::listen(addr) {
+            Ok(listener) => {
+                if candidate != port {
+                    let actual = listener.local_addr().map_err(ServerError::Serve)?;
+                    log::warn!("Port {} is in use, using {} instead", port, actual.port());
+                }
+                return Ok(listener);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => last = Some((addr, e)),
+            Err(source) => return Err(ServerError::Bind { addr, source }),
+        }
+    }
+    let (addr, source) = last.expect("at least one candidate port");
+    Err(ServerError::Bind { addr, source })
+}
+
+/// 把设置应用到运行中的服务；端口在 [`ServerHandle::restart`] 之后生效。
+pub async fn apply_settings(state: &AppState, settings: &Settings) {
+    state.sandbox.set_roots(settings.shared_roots.clone()).await;
+    state
+        .throttles
+        .upload
+        .replace_peer_rates(&settings.upload_peers)
+        .await;
+    state
+        .throttles
+        .download
+        .replace_peer_rates(&settings.download_peers)
+        .await;
+    state
+        .throttles
+        .upload
+        .set_transfer_rate(settings.upload_transfer_bytes_per_sec);
+    state
+        .throttles
+        .download
+        .set_transfer_rate(settings.download_transfer_bytes_per_sec);
+    if let Err(e) = state.schedule.set_rules(settings.schedule.clone()).await {
+        log::warn!("Ignoring invalid bandwidth schedule: {}", e);
+    }
+    state
+        .schedule
+        .set_base(
+            Some(settings.upload_bytes_per_sec),
+            Some(settings.download_bytes_per_sec),
+        )
+        .await;
+}
+
+impl AppState {
+    /// 创建共享状态并启动后台任务（清理上传会话、按时段限速、监视配置文件）。
+    /// Tauri 命令和 axum 处理器使用同一份状态。
+    pub async fn new(
+        settings: SettingsStore,
+        throttles: Throttles,
+        discovery: Discovery,
+        pairing: Pairing,
+        events: EventBus,
+        identity: Identity,
+    ) -> Self {
+        let uploads = UploadSessions::new(UploadSessions::default_staging_dir());
+        tokio::spawn({
+            let uploads = uploads.clone();
+            async move { uploads.prune(UPLOAD_SESSION_MAX_AGE).await }
+        });
+
+        let schedule = BandwidthSchedule::new(throttles.clone());
+        tokio::spawn(schedule.clone().run());
+
+        let port = settings.get().port;
+        let state = AppState {
+            local_addr: Arc::new(RwLock::new(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                port,
+            ))),
+            https: Arc::default(),
+            throttles,
+            schedule,
+            discovery,
+            sandbox: Sandbox::default(),
+            settings: settings.clone(),
+            pairing,
+            uploads,
+            transfers: TransferManager::new(events.clone()),
+            checksums: ChecksumCache::default(),
+            events: events.clone(),
+            identity,
+        };
+        apply_settings(&state, &settings.get()).await;
+
+        // 配置文件被外部修改时重新应用
+        tokio::spawn(settings.clone().watch_file());
+        tokio::spawn({
+            let state = state.clone();
+            let mut changes = settings.subscribe();
+            async move {
+                while changes.changed().await.is_ok() {
+                    changes.borrow_and_update();
+                    apply_settings(&state, &state.settings.get()).await;
+                }
+            }
+        });
+        state
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        *self.local_addr.read().unwrap()
+    }
+
+    pub fn https(&self) -> bool {
+        self.https.load(Ordering::Relaxed)
+    }
+
+    /// 本机信息，设置了设备名时以设置为准。
+    pub fn device_info(&self) -> handlers::DeviceInfo {
+        let mut info = handlers::DeviceInfo::local(self.local_addr(), &self.identity);
+        info.https = self.https();
+        if let Some(name) = self.settings.get().device_name {
+            info.name = name;
+        }
+        info
+    }
+}
+
+/// 按设置中的端口监听，并把实际地址记到 `state` 里。
+pub async fn listen(state: &AppState, options: &ServerOptions) -> Result<TcpListener, ServerError> {
+    let port = state.settings.get().port;
+    let listener = bind(options.bind, port, options.port_fallback).await?;
+    let addr = listener.local_addr().map_err(ServerError::Serve)?;
+    *state.local_addr.write().unwrap() = addr;
+    Ok(listener)
+}
+
+/// 运行中的服务。可以随时优雅地停止、用新设置重启，Tauri 和测试共用。
+pub struct ServerHandle {
+    state: AppState,
+    options: ServerOptions,
+    running: tokio::sync::Mutex<Option<Running>>,
+}
+
+struct Running {
+    /// 停止接受新连接
+    shutdown: CancellationToken,
+    /// 断开还没结束的请求
+    force: CancellationToken,
+    task: JoinHandle<Result<(), ServerError>>,
+}
+
+impl ServerHandle {
+    /// 按设置中的端口监听并在后台提供服务。
+    pub async fn start(state: AppState, options: ServerOptions) -> Result<Self, ServerError> {
+        let running = run(&state, &options).await?;
+        Ok(Self {
+            state,
+            options,
+            running: tokio::sync::Mutex::new(Some(running)),
+        })
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// 实际监听的地址；停止后为最后一次监听的地址。
+    pub fn local_addr(&self) -> SocketAddr {
+        self.state.local_addr()
+    }
+
+    pub async fn is_running(&self) -> bool {
+        self.running.lock().await.is_some()
+    }
+
+    /// 停止接受新连接，等进行中的请求（传输）结束；超过 `drain_timeout` 强制断开。
+    /// 已经停止时什么也不做。
+    pub async fn shutdown(&self) -> Result<(), ServerError> {
+        let Some(running) = self.running.lock().await.take() else {
+            return Ok(());
+        };
+        self.state.discovery.stop_advertising();
+        running.shutdown.cancel();
+
+        let mut task = running.task;
+        let joined = match tokio::time::timeout(self.options.drain_timeout, &mut task).await {
+            Ok(joined) => joined,
+            Err(_) => {
+                log::warn!(
+                    "Requests still running after {:?}, closing connections",
+                    self.options.drain_timeout
+                );
+                running.force.cancel();
+                match tokio::time::timeout(FORCE_CLOSE_TIMEOUT, &mut task).await {
+                    Ok(joined) => joined,
+                    Err(_) => {
+                        task.abort();
+                        // 等任务真正结束，确保端口已释放
+                        let _ = task.await;
+                        return Ok(());
+                    }
+                }
+            }
+        };
+        joined.map_err(|e| ServerError::Serve(io::Error::other(e)))?
+    }
+
+    /// 停止后按当前设置（端口等）重新启动，返回新的监听地址。
+    pub async fn restart(&self) -> Result<SocketAddr, ServerError> {
+        self.shutdown().await?;
+        let mut running = self.running.lock().await;
+        if running.is_none() {
+            *running = Some(run(&self.state, &self.options).await?);
         }
+        Ok(self.local_addr())
     }
+}
 
-    println!("Warning: Frontend dist not found, SPA serving may not work. Build with `pnpm build` first.");
-    std::path::PathBuf::from("../dist")
+async fn run(state: &AppState, options: &ServerOptions) -> Result<Running, ServerError> {
+    let tls = if state.settings.get().https {
+        Some(tls::server_config(&state.identity).map_err(ServerError::Tls)?)
+    } else {
+        None
+    };
+    let listener = listen(state, options).await?;
+    state.https.store(tls.is_some(), Ordering::Relaxed);
+    let shutdown = CancellationToken::new();
+    let force = CancellationToken::new();
+    let task = tokio::spawn(serve_on(
+        state.clone(),
+        listener,
+        tls,
+        options.frontend_dist.clone(),
+        shutdown.clone(),
+        force.clone(),
+    ));
+    Ok(Running {
+        shutdown,
+        force,
+        task,
+    })
 }
 
-pub async fn start_server(port: u16, throttle: Throttle) {
-    let state = AppState { throttle };
-    let frontend_dist = find_frontend_dist();
+/// `force` 取消后：还在处理的请求直接丢弃，正在发送的正文以错误结束，连接随之断开。
+/// axum 的每个连接跑在单独的任务里，只停掉监听任务断不开它们。
+async fn abort_on(State(force): State<CancellationToken>, req: Request, next: Next) -> Response {
+    let response = tokio::select! {
+        response = next.run(req) => response,
+        _ = force.cancelled() => {
+            return ApiError::new(ErrorCode::Cancelled, "Server is shutting down").into_response();
+        }
+    };
+    response.map(|body| {
+        Body::new(AbortableBody {
+            body,
+            cancelled: Box::pin(force.cancelled_owned()),
+        })
+    })
+}
+
+struct AbortableBody {
+    body: Body,
+    cancelled: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+impl http_body::Body for AbortableBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.cancelled.as_mut().poll(cx).is_ready() {
+            let e = io::Error::new(io::ErrorKind::ConnectionAborted, "Server is shutting down");
+            return Poll::Ready(Some(Err(axum::Error::new(e))));
+        }
+        Pin::new(&mut self.body).poll_frame(cx)
+    }
 
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// 在已经 [`listen`] 好的端口上提供服务，直到 `shutdown` 被取消且请求都已结束。
+async fn serve_on(
+    state: AppState,
+    listener: TcpListener,
+    tls: Option<Arc<rustls::ServerConfig>>,
+    frontend_dist: Option<PathBuf>,
+    shutdown: CancellationToken,
+    force: CancellationToken,
+) -> Result<(), ServerError> {
     let app = Router::new()
         .route("/", get(landing::landing_page))
         .route("/download/{platform}", get(landing::download_installer))
-        .nest("/api", routes::api_routes()
-            .route("/installers", get(landing::list_installers)))
-        .nest_service(
-            "/app",
-            ServeDir::new(&frontend_dist)
-                .fallback(ServeFile::new(frontend_dist.join("index.html"))),
+        .nest(
+            "/api",
+            routes::api_routes(state.clone()).route("/installers", get(landing::list_installers)),
         )
-        .layer(CorsLayer::permissive())
-        .with_state(state);
+        .nest_service("/app", frontend::service(frontend_dist))
+        .layer(auth::cors_layer())
+        // 事件流不会自己结束，停止时据此断开
+        .layer(Extension(shutdown.clone()))
+        .layer(middleware::from_fn_with_state(force, abort_on))
+        .with_state(state.clone());
+    let local_info = state.device_info();
+
+    let addr = state.local_addr();
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    log::info!("Transport server listening on {}://{}", scheme, addr);
+    if tls.is_some() {
+        log::info!("  Certificate fingerprint: {}", local_info.fingerprint);
+    }
+    for interface in &local_info.interfaces {
+        let url = SocketAddr::new(interface.ip, addr.port());
+        log::info!(
+            "  Web UI:  {}://{}/app ({})",
+            scheme,
+            url,
+            interface.interface
+        );
+    }
+
+    state
+        .discovery
+        .start(&local_info, addr.ip(), state.events.clone());
+    match tls {
+        None => {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await
+            .map_err(ServerError::Serve)?;
+        }
+        Some(config) => {
+            let (secure, plain, dispatch) = tls::accept(listener, config, shutdown.clone());
+            // tap_io 什么也不做，只是让自定义的 Listener 也能用 ConnectInfo<SocketAddr>
+            let secure = axum::serve(
+                secure.tap_io(|_| {}),
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+            let redirect = Router::new().fallback(tls::redirect_to_https);
+            let plain =
+                axum::serve(plain, redirect).with_graceful_shutdown(shutdown.cancelled_owned());
+            let (_, secure, plain) =
+                tokio::join!(dispatch, secure.into_future(), plain.into_future());
+            secure.and(plain).map_err(ServerError::Serve)?;
+        }
+    }
+    log::info!("Transport server on {} stopped", addr);
+    Ok(())
+}
+
+pub async fn start_server(
+    settings: SettingsStore,
+    options: ServerOptions,
+    throttles: Throttles,
+    discovery: Discovery,
+    pairing: Pairing,
+    events: EventBus,
+    identity: Identity,
+) -> Result<ServerHandle, ServerError> {
+    let state = AppState::new(settings, throttles, discovery, pairing, events, identity).await;
+    ServerHandle::start(state, options).await
+}
+
+/// 以 `root` 作为唯一可写共享目录的状态，供各模块测试使用。
+#[cfg(test)]
+pub(crate) fn test_state(root: &std::path::Path) -> AppState {
+    let throttles = Throttles::unlimited();
+    let shared_roots = vec![sandbox::SharedRoot {
+        path: root.to_path_buf(),
+        read_only: false,
+    }];
+    let settings = Settings {
+        shared_roots: shared_roots.clone(),
+        ..Settings::default()
+    };
+    let identity = Identity::generate();
+    AppState {
+        local_addr: Arc::new(RwLock::new(SocketAddr::from((Ipv4Addr::LOCALHOST, 8090)))),
+        https: Arc::default(),
+        schedule: BandwidthSchedule::new(throttles.clone()),
+        throttles,
+        discovery: Discovery::disabled(),
+        sandbox: Sandbox::new(shared_roots),
+        settings: SettingsStore::new(root.join(".settings.json"), settings),
+        pairing: Pairing::load(root.join(".paired.json"), &identity),
+        uploads: UploadSessions::new(tempfile::tempdir().unwrap().keep()),
+        transfers: TransferManager::default(),
+        checksums: ChecksumCache::default(),
+        events: EventBus::new(),
+        identity,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::TransportClient;
+    use futures_util::StreamExt;
+    use handlers::ThrottleRequest;
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    println!("Transport server listening on {}", addr);
-    println!("  API:     http://0.0.0.0:{}/api", port);
-    println!("  Web UI:  http://0.0.0.0:{}/app", port);
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    /// 在系统分配的端口上启动完整的服务。
+    async fn start(root: &std::path::Path, drain_timeout: Duration) -> ServerHandle {
+        let state = test_state(root);
+        state.settings.update(|s| s.port = 0).await.unwrap();
+        let options = ServerOptions {
+            bind: LOCALHOST,
+            drain_timeout,
+            ..ServerOptions::default()
+        };
+        ServerHandle::start(state, options).await.unwrap()
+    }
+
+    /// 开始一个约 3 秒才能下完的下载，返回已收到响应头的正文流。
+    async fn slow_download(
+        server: &ServerHandle,
+        root: &std::path::Path,
+    ) -> impl futures_util::Stream<Item = crate::client::Result<bytes::Bytes>> {
+        let path = root.join("big.bin");
+        std::fs::write(&path, vec![7u8; 256 * 1024]).unwrap();
+        let client = TransportClient::new(&server.local_addr().to_string());
+        client
+            .set_throttle(&ThrottleRequest {
+                download_bytes_per_sec: Some(64 * 1024),
+                ..ThrottleRequest::default()
+            })
+            .await
+            .unwrap();
+        let download = client.download(&path.to_string_lossy()).await.unwrap();
+        download.into_stream()
+    }
+
+    #[test]
+    fn test_port_fallback_candidates() {
+        assert_eq!(PortFallback::Strict.candidates(8090), vec![8090]);
+        assert_eq!(
+            PortFallback::Range(2).candidates(8090),
+            vec![8090, 8091, 8092]
+        );
+        assert_eq!(PortFallback::Range(3).candidates(65534), vec![65534, 65535]);
+        assert_eq!(PortFallback::Any.candidates(8090), vec![8090, 0]);
+        assert_eq!(PortFallback::Range(5).candidates(0), vec![0]);
+        assert_eq!("any".parse(), Ok(PortFallback::Any));
+        assert_eq!("3".parse(), Ok(PortFallback::Range(3)));
+        assert!("later".parse::<PortFallback>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bind_falls_back_when_port_is_busy() {
+        let busy = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+        let port = busy.local_addr().unwrap().port();
+
+        let err = bind(LOCALHOST, port, PortFallback::Strict)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            ServerError::Bind { addr, source }
+                if addr.port() == port && source.kind() == io::ErrorKind::AddrInUse
+        ));
+        assert!(err.to_string().contains("already in use"));
+
+        let listener = bind(LOCALHOST, port, PortFallback::Any).await.unwrap();
+        assert_ne!(listener.local_addr().unwrap().port(), port);
+    }
+
+    #[tokio::test]
+    async fn test_device_info_reports_bound_address() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = test_state(dir.path());
+        let busy = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+        let port = busy.local_addr().unwrap().port();
+        state.settings.update(|s| s.port = port).await.unwrap();
+
+        let options = ServerOptions {
+            bind: LOCALHOST,
+            port_fallback: PortFallback::Any,
+            ..ServerOptions::default()
+        };
+        let listener = listen(&state, &options).await.unwrap();
+        let bound = listener.local_addr().unwrap();
+        assert_ne!(bound.port(), port);
+
+        let info = state.device_info();
+        assert_eq!(info.port, bound.port());
+        assert_eq!(info.ip, "127.0.0.1");
+    }
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    #[tokio::test]
+    async fn test_shutdown_ends_event_streams_and_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = start(dir.path(), Duration::from_secs(10)).await;
+        let first = server.local_addr();
+        let client = TransportClient::new(&first.to_string());
+        assert_eq!(client.device_info().await.unwrap().port, first.port());
+        let mut events = Box::pin(client.events().await.unwrap());
+
+        let started = std::time::Instant::now();
+        server.shutdown().await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!server.is_running().await);
+        assert!(events.next().await.is_none());
+        assert!(client.device_info().await.is_err());
+        // 重复停止没有影响
+        server.shutdown().await.unwrap();
+
+        // 重启时使用新的端口设置
+        let busy = TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+        let port = busy.local_addr().unwrap().port();
+        drop(busy);
+        server
+            .state()
+            .settings
+            .update(|s| s.port = port)
+            .await
+            .unwrap();
+        let addr = server.restart().await.unwrap();
+        assert_eq!(addr.port(), port);
+        let client = TransportClient::new(&addr.to_string());
+        assert_eq!(client.device_info().await.unwrap().port, port);
+        server.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_https_pins_certificate_and_redirects_http() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = start(dir.path(), Duration::from_secs(5)).await;
+        server
+            .state()
+            .settings
+            .update(|s| s.https = true)
+            .await
+            .unwrap();
+        let addr = server.restart().await.unwrap();
+        let fingerprint = server.state().identity.fingerprint();
+
+        let client = TransportClient::new(&addr.to_string()).with_pinned_fingerprint(&fingerprint);
+        let info = client.device_info().await.unwrap();
+        assert!(info.https);
+        assert_eq!(info.fingerprint, fingerprint);
+        // 本机免配对仍然有效，说明拿到了对端地址
+        assert_eq!(client.roots().await.unwrap().len(), 1);
+
+        let impostor = crate::identity::Identity::generate().fingerprint();
+        let impostor = TransportClient::new(&addr.to_string()).with_pinned_fingerprint(&impostor);
+        assert!(matches!(
+            impostor.device_info().await,
+            Err(crate::client::ClientError::Http(_))
+        ));
+
+        // 明文 API 被拒绝，网页重定向到 HTTPS
+        let plain = TransportClient::new(&addr.to_string());
+        assert!(matches!(
+            plain.device_info().await,
+            Err(crate::client::ClientError::Status {
+                code: Some(ErrorCode::HttpsRequired),
+                ..
+            })
+        ));
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let response = http
+            .get(format!("http://{}/app/?x=1", addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[reqwest::header::LOCATION],
+            format!("https://{}/app/?x=1", addr)
+        );
+
+        server.shutdown().await.unwrap();
+        TcpListener::bind(addr).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_transfers() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = start(dir.path(), Duration::from_secs(30)).await;
+        let body = slow_download(&server, dir.path()).await;
+
+        let received = body.fold(
+            0,
+            |total, chunk| async move { total + chunk.unwrap().len() },
+        );
+        let (shutdown, received) = tokio::join!(server.shutdown(), received);
+        shutdown.unwrap();
+        assert_eq!(received, 256 * 1024);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = start(dir.path(), Duration::from_millis(200)).await;
+        let mut body = Box::pin(slow_download(&server, dir.path()).await);
+
+        let started = std::time::Instant::now();
+        server.shutdown().await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let mut failed = false;
+        while let Some(chunk) = body.next().await {
+            failed |= chunk.is_err();
+        }
+        assert!(failed);
+        // 端口已经释放，可以立即重新监听
+        TcpListener::bind(server.local_addr()).await.unwrap();
+    }
 }
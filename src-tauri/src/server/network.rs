@@ -0,0 +1,257 @@
+//! 本机网卡地址和监听套接字。以太网、Wi-Fi、VPN 可能同时存在，全部报告给对端，
+//! 由对端挑能连通的地址；默认同时监听 IPv4 和 IPv6。
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::TcpListener;
+
+/// 按网卡名推断的类型，只用于排序和展示。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InterfaceKind {
+    Ethernet,
+    Wifi,
+    Other,
+    Vpn,
+    /// 容器、虚拟机的桥接网卡，局域网里的其他设备一般连不到
+    Virtual,
+}
+
+/// 网卡名前缀和包含的关键字（均为小写）。Hyper-V 的 "vEthernet (WSL)" 也含 ethernet，
+/// 所以先判断虚拟网卡。
+const KIND_RULES: &[(InterfaceKind, &[&str], &[&str])] = &[
+    (
+        InterfaceKind::Virtual,
+        &[
+            "docker", "br-", "veth", "virbr", "vmnet", "vboxnet", "lxc", "cni", "awdl", "llw",
+            "bridge",
+        ],
+        &["vethernet", "virtualbox", "vmware", "hyper-v"],
+    ),
+    (
+        InterfaceKind::Vpn,
+        &[
+            "tun",
+            "tap",
+            "utun",
+            "wg",
+            "ppp",
+            "ipsec",
+            "tailscale",
+            "zt",
+        ],
+        &["vpn", "wireguard"],
+    ),
+    (
+        InterfaceKind::Wifi,
+        &["wl", "ath", "wifi"],
+        &["wi-fi", "wlan", "wireless", "无线"],
+    ),
+    (
+        InterfaceKind::Ethernet,
+        &["eth", "en", "em"],
+        &["ethernet", "以太网", "local area"],
+    ),
+];
+
+impl InterfaceKind {
+    pub fn from_name(name: &str) -> Self {
+        let name = name.to_lowercase();
+        KIND_RULES
+            .iter()
+            .find(|(_, prefixes, parts)| {
+                prefixes.iter().any(|p| name.starts_with(p))
+                    || parts.iter().any(|p| name.contains(p))
+            })
+            .map_or(InterfaceKind::Other, |(kind, _, _)| *kind)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceAddress {
+    /// 网卡名，如 `eth0`、`Wi-Fi`
+    pub interface: String,
+    pub ip: IpAddr,
+    pub kind: InterfaceKind,
+}
+
+impl InterfaceAddress {
+    pub fn new(interface: impl Into<String>, ip: IpAddr) -> Self {
+        let interface = interface.into();
+        let kind = InterfaceKind::from_name(&interface);
+        Self {
+            interface,
+            ip,
+            kind,
+        }
+    }
+}
+
+/// 其他设备能直接连的地址：排除回环，以及必须带网卡后缀（`%eth0`）才能用的 IPv6 链路本地地址。
+pub fn is_usable(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => !v4.is_loopback() && !v4.is_unspecified(),
+        IpAddr::V6(v6) => {
+            !v6.is_loopback() && !v6.is_unspecified() && (v6.segments()[0] & 0xffc0) != 0xfe80
+        }
+    }
+}
+
+/// 同一网卡内越小越优先：IPv4 优先于 IPv6，
+/// IPv4 链路本地地址（169.254/16，通常是 DHCP 失败）排在最后。
+pub fn preference(ip: IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(v4) if v4.is_link_local() => 2,
+        IpAddr::V4(_) => 0,
+        IpAddr::V6(_) => 1,
+    }
+}
+
+/// 按优先级排好序的本机可用地址。
+pub fn local_addresses() -> Vec<InterfaceAddress> {
+    let interfaces = match local_ip_address::list_afinet_netifas() {
+        Ok(interfaces) => interfaces,
+        Err(e) => {
+            log::warn!("Failed to list network interfaces: {}", e);
+            return Vec::new();
+        }
+    };
+    sorted(
+        interfaces
+            .into_iter()
+            .filter(|(_, ip)| is_usable(*ip))
+            .map(|(name, ip)| InterfaceAddress::new(name, ip))
+            .collect(),
+    )
+}
+
+/// 网卡地址的缓存时间。鉴权中间件每个请求都要判断对端是不是本机，不能每次都列一遍网卡。
+const LOCAL_IPS_TTL: Duration = Duration::from_secs(10);
+
+/// `ip` 是否是本机地址（回环或任一网卡上的地址）。
+pub fn is_local_ip(ip: IpAddr) -> bool {
+    static CACHE: Mutex<Option<(Instant, Vec<IpAddr>)>> = Mutex::new(None);
+
+    let ip = ip.to_canonical();
+    if ip.is_loopback() {
+        return true;
+    }
+    let mut cache = CACHE.lock().unwrap();
+    let now = Instant::now();
+    if cache
+        .as_ref()
+        .is_none_or(|(at, _)| now.duration_since(*at) >= LOCAL_IPS_TTL)
+    {
+        let ips = local_ip_address::list_afinet_netifas()
+            .map(|interfaces| interfaces.into_iter().map(|(_, ip)| ip).collect())
+            .unwrap_or_default();
+        *cache = Some((now, ips));
+    }
+    cache.as_ref().is_some_and(|(_, ips)| ips.contains(&ip))
+}
+
+fn sorted(mut addresses: Vec<InterfaceAddress>) -> Vec<InterfaceAddress> {
+    // 物理网卡优先于 VPN 和虚拟网卡；链路本地地址不论网卡都放到最后
+    addresses.sort_by_key(|a| {
+        let preference = preference(a.ip);
+        (preference == 2, a.kind, preference)
+    });
+    addresses.dedup_by_key(|a| a.ip);
+    addresses
+}
+
+/// 监听 `addr`。`[::]` 时关闭 `IPV6_V6ONLY` 同时接受 IPv4 连接（Windows 默认只收 IPv6），
+/// 系统不支持 IPv6 时退回 `0.0.0.0`。
+pub fn listen(addr: SocketAddr) -> io::Result<TcpListener> {
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        match listen_socket(addr, true) {
+            Err(e) if e.kind() != io::ErrorKind::AddrInUse => {
+                log::warn!("IPv6 unavailable ({}), listening on IPv4 only", e);
+                let v4 = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), addr.port());
+                listen_socket(v4, false)
+            }
+            result => result,
+        }
+    } else {
+        listen_socket(addr, false)
+    }
+}
+
+fn listen_socket(addr: SocketAddr, dual_stack: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if dual_stack {
+        socket.set_only_v6(false)?;
+    }
+    // 与 tokio 的 TcpListener::bind 一致：Unix 上允许重启后立即重用 TIME_WAIT 中的端口
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interface_kind() {
+        assert_eq!(InterfaceKind::from_name("eth0"), InterfaceKind::Ethernet);
+        assert_eq!(InterfaceKind::from_name("enp3s0"), InterfaceKind::Ethernet);
+        assert_eq!(InterfaceKind::from_name("以太网"), InterfaceKind::Ethernet);
+        assert_eq!(InterfaceKind::from_name("wlp2s0"), InterfaceKind::Wifi);
+        assert_eq!(InterfaceKind::from_name("Wi-Fi"), InterfaceKind::Wifi);
+        assert_eq!(InterfaceKind::from_name("tailscale0"), InterfaceKind::Vpn);
+        assert_eq!(InterfaceKind::from_name("utun3"), InterfaceKind::Vpn);
+        assert_eq!(InterfaceKind::from_name("docker0"), InterfaceKind::Virtual);
+        assert_eq!(
+            InterfaceKind::from_name("vEthernet (WSL)"),
+            InterfaceKind::Virtual
+        );
+    }
+
+    #[test]
+    fn test_usable_and_order() {
+        assert!(!is_usable("127.0.0.1".parse().unwrap()));
+        assert!(!is_usable("::1".parse().unwrap()));
+        assert!(!is_usable("fe80::1".parse().unwrap()));
+        assert!(is_usable("fd00::5".parse().unwrap()));
+
+        let addresses = sorted(vec![
+            InterfaceAddress::new("tailscale0", "100.64.0.2".parse().unwrap()),
+            InterfaceAddress::new("wlan0", "2001:db8::5".parse().unwrap()),
+            InterfaceAddress::new("docker0", "172.17.0.1".parse().unwrap()),
+            InterfaceAddress::new("wlan0", "192.168.1.5".parse().unwrap()),
+            InterfaceAddress::new("eth0", "169.254.3.4".parse().unwrap()),
+        ]);
+        let ips: Vec<String> = addresses.iter().map(|a| a.ip.to_string()).collect();
+        assert_eq!(
+            ips,
+            [
+                "192.168.1.5",
+                "2001:db8::5",
+                "100.64.0.2",
+                "172.17.0.1",
+                "169.254.3.4"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dual_stack_accepts_ipv4() {
+        let listener = listen("[::]:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let v4 = SocketAddr::from((Ipv4Addr::LOCALHOST, addr.port()));
+        let (client, accepted) =
+            tokio::join!(tokio::net::TcpStream::connect(v4), listener.accept());
+        client.unwrap();
+        let (_, peer) = accepted.unwrap();
+        assert_eq!(peer.ip().to_canonical(), IpAddr::V4(Ipv4Addr::LOCALHOST));
+    }
+}
@@ -0,0 +1,265 @@
+//! HTTP `Range` / `If-Range` 解析（RFC 9110 §14），供 `download_file` 断点续传与拖动播放使用。
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::http::{header, HeaderMap};
+
+/// 单个请求超过这么多区间时直接按整文件返回，防止恶意的碎片化请求。
+const MAX_RANGES: usize = 64;
+
+/// 闭区间 `[start, end]`，单位字节。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// 没有 Range 头、格式无效或 If-Range 不匹配：返回完整文件 (200)。
+    Full,
+    /// 至少一个可满足的区间 (206)，已排序并合并重叠部分。
+    Partial(Vec<ByteRange>),
+    /// 所有区间都超出文件长度 (416)。
+    Unsatisfiable,
+}
+
+/// 文件的校验器：用于 `ETag` / `Last-Modified` 响应头以及 `If-Range` 比较。
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: Option<String>,
+    modified_secs: Option<u64>,
+}
+
+impl Validators {
+    pub fn new(len: u64, modified: Option<SystemTime>) -> Self {
+        let since_epoch = modified.and_then(|t| t.duration_since(UNIX_EPOCH).ok());
+        let etag = match since_epoch {
+            Some(d) => format!("\"{:x}-{:x}\"", len, d.as_nanos()),
+            None => format!("\"{:x}\"", len),
+        };
+        let last_modified = since_epoch
+            .and_then(|d| chrono::DateTime::from_timestamp(d.as_secs() as i64, 0))
+            .map(|t| t.format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+
+        Self {
+            etag,
+            last_modified,
+            modified_secs: since_epoch.map(|d| d.as_secs()),
+        }
+    }
+
+    /// `If-Range` 为强 ETag 时要求完全相等；为 HTTP 日期时要求与 `Last-Modified` 一致。
+    fn if_range_matches(&self, value: &str) -> bool {
+        let value = value.trim();
+        if value.starts_with("W/") {
+            return false;
+        }
+        if value.starts_with('"') {
+            return value == self.etag;
+        }
+        match (
+            chrono::DateTime::parse_from_rfc2822(value),
+            self.modified_secs,
+        ) {
+            (Ok(date), Some(secs)) => date.timestamp() == secs as i64,
+            _ => false,
+        }
+    }
+}
+
+/// 根据请求头决定如何响应。
+pub fn evaluate(headers: &HeaderMap, total: u64, validators: &Validators) -> RangeRequest {
+    let Some(range) = headers.get(header::RANGE).and_then(|v| v.to_str().ok()) else {
+        return RangeRequest::Full;
+    };
+
+    if let Some(if_range) = headers.get(header::IF_RANGE) {
+        match if_range.to_str() {
+            Ok(v) if validators.if_range_matches(v) => {}
+            _ => return RangeRequest::Full,
+        }
+    }
+
+    parse_range(range, total)
+}
+
+/// 解析 `bytes=0-499,1000-,-500` 形式的 Range 头。
+pub fn parse_range(value: &str, total: u64) -> RangeRequest {
+    let Some(specs) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    let mut ranges = Vec::new();
+    let mut any_valid = false;
+
+    for spec in specs.split(',') {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            continue;
+        }
+        let Some((first, last)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+
+        let range = if first.is_empty() {
+            // 后缀区间：最后 N 个字节
+            let Ok(suffix) = last.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            any_valid = true;
+            if suffix == 0 || total == 0 {
+                continue;
+            }
+            ByteRange {
+                start: total.saturating_sub(suffix),
+                end: total - 1,
+            }
+        } else {
+            let Ok(start) = first.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if last.is_empty() {
+                u64::MAX
+            } else {
+                match last.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return RangeRequest::Full,
+                }
+            };
+            any_valid = true;
+            if start >= total {
+                continue;
+            }
+            ByteRange {
+                start,
+                end: end.min(total - 1),
+            }
+        };
+        ranges.push(range);
+    }
+
+    if !any_valid {
+        return RangeRequest::Full;
+    }
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+    if ranges.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+
+    RangeRequest::Partial(coalesce(ranges))
+}
+
+/// 排序并合并重叠或相邻的区间。
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(prev) if range.start <= prev.end.saturating_add(1) => {
+                prev.end = prev.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn r(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn test_parse_single_ranges() {
+        assert_eq!(
+            parse_range("bytes=0-499", 1000),
+            RangeRequest::Partial(vec![r(0, 499)])
+        );
+        assert_eq!(
+            parse_range("bytes=500-", 1000),
+            RangeRequest::Partial(vec![r(500, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=-200", 1000),
+            RangeRequest::Partial(vec![r(800, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=900-5000", 1000),
+            RangeRequest::Partial(vec![r(900, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            RangeRequest::Partial(vec![r(0, 999)])
+        );
+    }
+
+    #[test]
+    fn test_parse_multi_ranges_coalesced() {
+        assert_eq!(
+            parse_range("bytes=500-599, 0-99, 50-149, 150-199", 1000),
+            RangeRequest::Partial(vec![r(0, 199), r(500, 599)])
+        );
+    }
+
+    #[test]
+    fn test_parse_unsatisfiable_and_invalid() {
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=0-10", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("items=0-10", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=10-5", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=abc", 1000), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_if_range_mismatch_serves_full() {
+        let validators = Validators::new(1000, Some(SystemTime::now()));
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=0-9"));
+
+        assert_eq!(
+            evaluate(&headers, 1000, &validators),
+            RangeRequest::Partial(vec![r(0, 9)])
+        );
+
+        headers.insert(header::IF_RANGE, HeaderValue::from_static("\"stale\""));
+        assert_eq!(evaluate(&headers, 1000, &validators), RangeRequest::Full);
+
+        headers.insert(
+            header::IF_RANGE,
+            HeaderValue::from_str(&validators.etag).unwrap(),
+        );
+        assert_eq!(
+            evaluate(&headers, 1000, &validators),
+            RangeRequest::Partial(vec![r(0, 9)])
+        );
+
+        let date = validators.last_modified.clone().unwrap();
+        headers.insert(header::IF_RANGE, HeaderValue::from_str(&date).unwrap());
+        assert_eq!(
+            evaluate(&headers, 1000, &validators),
+            RangeRequest::Partial(vec![r(0, 9)])
+        );
+    }
+}
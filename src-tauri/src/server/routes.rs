@@ -1,25 +1,73 @@
 use axum::extract::DefaultBodyLimit;
-use axum::routing::{get, post, put};
+use axum::middleware;
+use axum::routing::{delete, get, post, put};
 use axum::Router;
 
 use super::handlers;
-use super::AppState;
+use super::{auth, AppState};
 
-pub fn api_routes() -> Router<AppState> {
-    Router::new()
+/// 不需要令牌的配对接口的请求体上限，防止未配对的主机让服务端缓冲任意大的请求。
+const PAIRING_BODY_LIMIT: usize = 4 * 1024;
+
+pub fn api_routes(state: AppState) -> Router<AppState> {
+    // 配对接口本身不需要令牌
+    let public = Router::new()
+        .route("/pair/request", post(handlers::request_pairing))
+        .route("/pair/confirm", post(handlers::confirm_pairing))
+        .layer(DefaultBodyLimit::max(PAIRING_BODY_LIMIT));
+
+    let protected = Router::new()
+        .route("/pair/devices", get(handlers::list_paired_devices))
+        .route("/pair/devices/{id}", delete(handlers::revoke_device))
         .route("/device/info", get(handlers::device_info))
+        .route("/devices", get(handlers::list_devices))
+        .route("/roots", get(handlers::list_roots))
         .route(
             "/files",
             get(handlers::list_files).delete(handlers::delete_file),
         )
         .route("/files/download", get(handlers::download_file))
+        .route("/files/hash", get(handlers::file_hash))
+        .route(
+            "/files/archive",
+            get(handlers::download_archive).post(handlers::download_selection),
+        )
         .route("/files/upload", post(handlers::upload_file))
         .route("/files/rename", put(handlers::rename_file))
         .route("/files/mkdir", post(handlers::create_directory))
+        .route("/uploads", post(handlers::create_upload))
+        .route(
+            "/uploads/{id}",
+            get(handlers::upload_status)
+                .put(handlers::upload_chunk)
+                .delete(handlers::abort_upload),
+        )
+        .route("/uploads/{id}/finalize", post(handlers::finalize_upload))
+        .route(
+            "/transfers",
+            get(handlers::list_transfers).delete(handlers::clear_transfers),
+        )
+        .route("/transfers/{id}", get(handlers::transfer_detail))
+        .route("/transfers/{id}/pause", post(handlers::pause_transfer))
+        .route("/transfers/{id}/resume", post(handlers::resume_transfer))
+        .route("/transfers/{id}/cancel", post(handlers::cancel_transfer))
+        .route("/events", get(handlers::event_stream))
+        .route(
+            "/settings",
+            get(handlers::get_settings).put(handlers::update_settings),
+        )
         .route(
             "/settings/throttle",
             get(handlers::get_throttle).put(handlers::set_throttle),
         )
+        .route(
+            "/settings/schedule",
+            get(handlers::get_schedule).put(handlers::set_schedule),
+        )
         .route("/logs", post(handlers::receive_logs))
-        .layer(DefaultBodyLimit::disable())
+        .route_layer(middleware::from_fn_with_state(state, auth::require_auth))
+        // 上传不限大小，鉴权通过后才会读取请求体
+        .layer(DefaultBodyLimit::disable());
+
+    public.merge(protected)
 }
@@ -0,0 +1,332 @@
+//! 共享目录沙箱：所有文件类 API 在访问磁盘前都要经过 `Sandbox::resolve`，
+//! 只允许落在已配置共享根目录内的路径，并按根目录的只读标志限制写操作。
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use super::error::{ApiError, ErrorCode};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SharedRoot {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+impl SharedRoot {
+    /// 默认共享用户主目录（可写）。
+    pub fn home() -> Self {
+        Self {
+            path: dirs::home_dir().unwrap_or_else(|| PathBuf::from("/")),
+            read_only: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    /// 在根目录内创建或修改
+    Write,
+    /// 删除或移动：除了可写外，还不能是共享根目录本身或它的上级
+    Remove,
+}
+
+#[derive(Debug)]
+pub enum SandboxError {
+    /// 相对路径、包含 `..` 等
+    InvalidPath,
+    OutsideRoots,
+    ReadOnly,
+    /// 删除或移动会带走某个共享根目录
+    RootItself,
+}
+
+impl std::fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SandboxError::InvalidPath => write!(f, "Invalid path"),
+            SandboxError::OutsideRoots => write!(f, "Path is outside shared directories"),
+            SandboxError::ReadOnly => write!(f, "Shared directory is read-only"),
+            SandboxError::RootItself => write!(f, "Cannot remove a shared directory or its parent"),
+        }
+    }
+}
+
+impl From<SandboxError> for ApiError {
+    fn from(e: SandboxError) -> Self {
+        let code = match e {
+            SandboxError::InvalidPath => ErrorCode::BadRequest,
+            SandboxError::ReadOnly => ErrorCode::ReadOnly,
+            SandboxError::OutsideRoots | SandboxError::RootItself => ErrorCode::Forbidden,
+        };
+        ApiError::new(code, e.to_string())
+    }
+}
+
+#[derive(Clone)]
+pub struct Sandbox {
+    /// 已规范化（绝对、无符号链接）的根目录
+    roots: Arc<RwLock<Vec<SharedRoot>>>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self::new(vec![SharedRoot::home()])
+    }
+}
+
+impl Sandbox {
+    pub fn new(roots: Vec<SharedRoot>) -> Self {
+        Self {
+            roots: Arc::new(RwLock::new(canonicalize_roots(roots))),
+        }
+    }
+
+    pub async fn roots(&self) -> Vec<SharedRoot> {
+        self.roots.read().await.clone()
+    }
+
+    pub async fn set_roots(&self, roots: Vec<SharedRoot>) {
+        *self.roots.write().await = canonicalize_roots(roots);
+    }
+
+    /// 校验并规范化客户端传来的路径，返回可以直接用于文件操作的真实路径。
+    /// 删除和移动作用于路径本身：最后一级是符号链接时返回链接，不解析到它指向的位置。
+    pub async fn resolve(&self, raw: &str, access: Access) -> Result<PathBuf, SandboxError> {
+        let requested = Path::new(raw);
+        if !requested.is_absolute()
+            || requested
+                .components()
+                .any(|c| matches!(c, Component::ParentDir))
+        {
+            return Err(SandboxError::InvalidPath);
+        }
+
+        let real = match access {
+            Access::Remove => {
+                let name = requested.file_name().ok_or(SandboxError::InvalidPath)?;
+                let parent = requested.parent().ok_or(SandboxError::InvalidPath)?;
+                canonicalize_lenient(parent).await?.join(name)
+            }
+            _ => canonicalize_lenient(requested).await?,
+        };
+
+        let roots = self.roots.read().await;
+        // 嵌套的根目录以最具体（最长）的那个为准
+        let root = roots
+            .iter()
+            .filter(|r| real.starts_with(&r.path))
+            .max_by_key(|r| r.path.components().count())
+            .ok_or(SandboxError::OutsideRoots)?;
+
+        if access != Access::Read && root.read_only {
+            return Err(SandboxError::ReadOnly);
+        }
+        // 删除上级目录会连带删掉嵌套在里面的根目录，哪怕那个根目录是只读的
+        if access == Access::Remove && roots.iter().any(|r| r.path.starts_with(&real)) {
+            return Err(SandboxError::RootItself);
+        }
+
+        Ok(real)
+    }
+}
+
+fn canonicalize_roots(roots: Vec<SharedRoot>) -> Vec<SharedRoot> {
+    roots
+        .into_iter()
+        .filter_map(|root| match std::fs::canonicalize(&root.path) {
+            Ok(path) => Some(SharedRoot { path, ..root }),
+            Err(e) => {
+                log::warn!("Ignoring shared directory {}: {}", root.path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// 规范化最长的已存在祖先（解析符号链接），再拼回尚不存在的部分。
+/// 目标不存在时（新建目录、上传、重命名目标）也能得到可比较的真实路径。
+async fn canonicalize_lenient(path: &Path) -> Result<PathBuf, SandboxError> {
+    let mut existing = path.to_path_buf();
+    let mut missing = Vec::new();
+
+    loop {
+        match tokio::fs::canonicalize(&existing).await {
+            Ok(mut real) => {
+                for name in missing.iter().rev() {
+                    real.push(name);
+                }
+                return Ok(real);
+            }
+            Err(_) => {
+                let name = existing
+                    .file_name()
+                    .ok_or(SandboxError::InvalidPath)?
+                    .to_os_string();
+                missing.push(name);
+                if !existing.pop() {
+                    return Err(SandboxError::InvalidPath);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn root(path: &Path, read_only: bool) -> SharedRoot {
+        SharedRoot {
+            path: path.to_path_buf(),
+            read_only,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_inside_and_outside_roots() {
+        let shared = tempdir().unwrap();
+        let other = tempdir().unwrap();
+        let sandbox = Sandbox::new(vec![root(shared.path(), false)]);
+        let shared_real = fs::canonicalize(shared.path()).unwrap();
+
+        let inside = shared.path().join("a.txt");
+        assert_eq!(
+            sandbox
+                .resolve(inside.to_str().unwrap(), Access::Write)
+                .await
+                .unwrap(),
+            shared_real.join("a.txt")
+        );
+
+        let outside = other.path().join("b.txt");
+        assert!(matches!(
+            sandbox
+                .resolve(outside.to_str().unwrap(), Access::Read)
+                .await,
+            Err(SandboxError::OutsideRoots)
+        ));
+
+        let traversal = format!("{}/../etc", shared.path().display());
+        assert!(matches!(
+            sandbox.resolve(&traversal, Access::Read).await,
+            Err(SandboxError::InvalidPath)
+        ));
+        assert!(matches!(
+            sandbox.resolve("relative/path", Access::Read).await,
+            Err(SandboxError::InvalidPath)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlink_escape_rejected() {
+        let shared = tempdir().unwrap();
+        let other = tempdir().unwrap();
+        std::os::unix::fs::symlink(other.path(), shared.path().join("link")).unwrap();
+        let sandbox = Sandbox::new(vec![root(shared.path(), false)]);
+
+        let escaped = shared.path().join("link").join("new.txt");
+        assert!(matches!(
+            sandbox
+                .resolve(escaped.to_str().unwrap(), Access::Write)
+                .await,
+            Err(SandboxError::OutsideRoots)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_remove_targets_symlink_itself() {
+        let shared = tempdir().unwrap();
+        let shared_real = fs::canonicalize(shared.path()).unwrap();
+        fs::create_dir(shared.path().join("photos")).unwrap();
+        std::os::unix::fs::symlink(shared.path().join("photos"), shared.path().join("link"))
+            .unwrap();
+        let sandbox = Sandbox::new(vec![root(shared.path(), false)]);
+
+        let link = shared.path().join("link");
+        assert_eq!(
+            sandbox
+                .resolve(link.to_str().unwrap(), Access::Remove)
+                .await
+                .unwrap(),
+            shared_real.join("link")
+        );
+        // 读写仍然跟随链接
+        assert_eq!(
+            sandbox
+                .resolve(link.to_str().unwrap(), Access::Read)
+                .await
+                .unwrap(),
+            shared_real.join("photos")
+        );
+
+        // 指向根目录的链接可以删除，删掉的只是链接
+        std::os::unix::fs::symlink(shared.path(), shared.path().join("home")).unwrap();
+        assert_eq!(
+            sandbox
+                .resolve(shared.path().join("home").to_str().unwrap(), Access::Remove)
+                .await
+                .unwrap(),
+            shared_real.join("home")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_only_and_root_removal() {
+        let shared = tempdir().unwrap();
+        fs::create_dir(shared.path().join("rw")).unwrap();
+        let sandbox = Sandbox::new(vec![
+            root(shared.path(), true),
+            root(&shared.path().join("rw"), false),
+        ]);
+
+        let top = shared.path().join("x.txt");
+        assert!(sandbox
+            .resolve(top.to_str().unwrap(), Access::Read)
+            .await
+            .is_ok());
+        assert!(matches!(
+            sandbox.resolve(top.to_str().unwrap(), Access::Write).await,
+            Err(SandboxError::ReadOnly)
+        ));
+
+        let nested = shared.path().join("rw").join("x.txt");
+        assert!(sandbox
+            .resolve(nested.to_str().unwrap(), Access::Remove)
+            .await
+            .is_ok());
+        assert!(matches!(
+            sandbox
+                .resolve(shared.path().join("rw").to_str().unwrap(), Access::Remove)
+                .await,
+            Err(SandboxError::RootItself)
+        ));
+
+        // 可写根目录下嵌套只读根目录：不能删除或移走只读根目录的上级
+        let data = tempdir().unwrap();
+        let parent = data.path().join("projects");
+        fs::create_dir_all(parent.join("archive")).unwrap();
+        let sandbox = Sandbox::new(vec![
+            root(data.path(), false),
+            root(&parent.join("archive"), true),
+        ]);
+        assert!(matches!(
+            sandbox
+                .resolve(parent.to_str().unwrap(), Access::Remove)
+                .await,
+            Err(SandboxError::RootItself)
+        ));
+        assert!(sandbox
+            .resolve(parent.join("other").to_str().unwrap(), Access::Remove)
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,339 @@
+//! 可选的 HTTPS。每次启动生成新的 TLS 密钥，设备身份的 Ed25519 密钥不参与握手，
+//! 只用来自签一张 CA 证书并签发 TLS 证书。CA 证书的公钥指纹就是设备指纹
+//! （`DeviceInfo::fingerprint`、mDNS 广播），客户端据此固定证书链，不依赖系统 CA 和主机名。
+//! 同一端口仍接受明文 HTTP，只用来把浏览器重定向到 HTTPS。
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::Request;
+use axum::http::header;
+use axum::response::{IntoResponse, Redirect, Response};
+use ed25519_dalek::pkcs8::{DecodePublicKey, EncodePrivateKey};
+use ed25519_dalek::VerifyingKey;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::verify_server_cert_signed_by_trust_anchor;
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::server::ParsedCertificate;
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+
+use super::error::{ApiError, ErrorCode};
+use crate::identity::{self, Identity};
+
+/// 连接建立后多久内必须完成握手（包括分辨 HTTP 和 TLS）。
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TLS 记录的第一个字节：握手
+const TLS_HANDSHAKE: u8 = 0x16;
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// 服务端配置：新生成的 TLS 密钥，证书链由设备密钥签发。
+pub fn server_config(identity: &Identity) -> io::Result<Arc<ServerConfig>> {
+    let (chain, key) = certificate(identity)?;
+    let mut config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(io::Error::other)?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// `[TLS 证书, 设备 CA 证书]` 和 TLS 私钥。
+fn certificate(
+    identity: &Identity,
+) -> io::Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let identity_der = identity
+        .signing_key()
+        .to_pkcs8_der()
+        .map_err(io::Error::other)?;
+    let identity_key =
+        rcgen::KeyPair::try_from(identity_der.as_bytes()).map_err(io::Error::other)?;
+    let mut ca = rcgen::CertificateParams::default();
+    ca.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Constrained(0));
+    ca.distinguished_name.push(
+        rcgen::DnType::CommonName,
+        format!("Transport {}", identity.id()),
+    );
+    let ca = ca.self_signed(&identity_key).map_err(io::Error::other)?;
+
+    let tls_key = rcgen::KeyPair::generate_for(&rcgen::PKCS_ED25519).map_err(io::Error::other)?;
+    let mut params = rcgen::CertificateParams::new(vec!["transport.local".to_string()])
+        .map_err(io::Error::other)?;
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, "transport.local");
+    params.extended_key_usages = vec![rcgen::ExtendedKeyUsagePurpose::ServerAuth];
+    let cert = params
+        .signed_by(&tls_key, &ca, &identity_key)
+        .map_err(io::Error::other)?;
+
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(tls_key.serialize_der()));
+    Ok((vec![cert.der().clone(), ca.der().clone()], key))
+}
+
+/// 固定对端证书的客户端配置：TLS 证书必须由指纹等于 `fingerprint` 的设备密钥签发，
+/// 不检查系统 CA 和主机名。
+pub fn client_config(fingerprint: &str) -> ClientConfig {
+    let provider = provider();
+    ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier {
+            fingerprint: fingerprint.trim().to_lowercase(),
+            provider,
+        }))
+        .with_no_client_auth()
+}
+
+/// 证书公钥的指纹，算法与 [`identity::fingerprint`] 相同；不是 Ed25519 证书时为 `None`。
+pub fn cert_fingerprint(cert: &CertificateDer<'_>) -> Option<String> {
+    let parsed = ParsedCertificate::try_from(cert).ok()?;
+    let key = VerifyingKey::from_public_key_der(&parsed.subject_public_key_info()).ok()?;
+    Some(identity::fingerprint(key.as_bytes()))
+}
+
+/// 签发了 `end_entity` 的设备 CA 证书的指纹，也就是对端的设备指纹；
+/// `intermediates` 里没有签发它的证书时为 `None`。
+pub fn chain_fingerprint(
+    end_entity: &CertificateDer<'_>,
+    intermediates: &[CertificateDer<'_>],
+    now: UnixTime,
+) -> Option<String> {
+    let parsed = ParsedCertificate::try_from(end_entity).ok()?;
+    let algorithms = provider().signature_verification_algorithms.all;
+    intermediates.iter().find_map(|ca| {
+        let fingerprint = cert_fingerprint(ca)?;
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.clone().into_owned()).ok()?;
+        verify_server_cert_signed_by_trust_anchor(&parsed, &roots, &[], now, algorithms)
+            .ok()
+            .map(|()| fingerprint)
+    })
+}
+
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    fingerprint: String,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match chain_fingerprint(end_entity, intermediates, now) {
+            Some(fingerprint) if fingerprint == self.fingerprint => {
+                Ok(ServerCertVerified::assertion())
+            }
+            _ => Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            )),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// 分好流（TLS 已握手）的连接，交给 `axum::serve`。
+pub struct Incoming<S> {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(S, SocketAddr)>,
+}
+
+impl<S> axum::serve::Listener for Incoming<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Io = S;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (S, SocketAddr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // 已经停止接受连接，axum 的优雅停止会结束服务
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+}
+
+enum Connection {
+    Tls(Box<TlsStream<TcpStream>>),
+    Plain(TcpStream),
+}
+
+/// 在同一端口上区分 TLS 和明文 HTTP：以握手记录开头的连接握手后进入第一个，其余进入第二个。
+/// 返回的 future 负责接受连接，`shutdown` 取消后结束并关闭监听套接字。
+pub fn accept(
+    listener: TcpListener,
+    config: Arc<ServerConfig>,
+    shutdown: CancellationToken,
+) -> (
+    Incoming<TlsStream<TcpStream>>,
+    Incoming<TcpStream>,
+    impl Future<Output = ()> + Send,
+) {
+    // 地址在 `listen` 时已经取到过，这里不会失败
+    let local_addr = listener.local_addr().expect("listener has a local address");
+    let (tls_tx, tls_rx) = mpsc::channel(64);
+    let (plain_tx, plain_rx) = mpsc::channel(64);
+    let acceptor = TlsAcceptor::from(config);
+
+    let dispatch = async move {
+        loop {
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        // 文件描述符用尽等错误：稍后重试，与 axum 的处理一致
+                        log::debug!("Failed to accept connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                },
+                _ = shutdown.cancelled() => break,
+            };
+            let acceptor = acceptor.clone();
+            let (tls_tx, plain_tx) = (tls_tx.clone(), plain_tx.clone());
+            // 握手可能很慢，不能挡住后面的连接
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, classify(stream, &acceptor)).await {
+                    Ok(Ok(Connection::Tls(stream))) => {
+                        let _ = tls_tx.send((*stream, peer)).await;
+                    }
+                    Ok(Ok(Connection::Plain(stream))) => {
+                        let _ = plain_tx.send((stream, peer)).await;
+                    }
+                    Ok(Err(e)) => log::debug!("TLS handshake with {} failed: {}", peer, e),
+                    Err(_) => log::debug!("TLS handshake with {} timed out", peer),
+                }
+            });
+        }
+    };
+
+    (
+        Incoming {
+            local_addr,
+            connections: tls_rx,
+        },
+        Incoming {
+            local_addr,
+            connections: plain_rx,
+        },
+        dispatch,
+    )
+}
+
+async fn classify(stream: TcpStream, acceptor: &TlsAcceptor) -> io::Result<Connection> {
+    let mut first = [0u8; 1];
+    if stream.peek(&mut first).await? == 1 && first[0] == TLS_HANDSHAKE {
+        Ok(Connection::Tls(Box::new(acceptor.accept(stream).await?)))
+    } else {
+        Ok(Connection::Plain(stream))
+    }
+}
+
+/// 明文 HTTP 上的请求：页面重定向到同一地址的 HTTPS；API 直接拒绝，
+/// 免得客户端在重定向前后都以明文发送令牌和文件内容。
+pub async fn redirect_to_https(req: Request) -> Response {
+    if req.uri().path().starts_with("/api") {
+        return ApiError::new(ErrorCode::HttpsRequired, "This device only accepts HTTPS")
+            .into_response();
+    }
+    let Some(host) = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+    else {
+        return ApiError::bad_request("Missing Host header").into_response();
+    };
+    let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    Redirect::permanent(&format!("https://{}{}", host, path)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_certificate_is_bound_to_identity() {
+        let now = UnixTime::now();
+        let identity = Identity::generate();
+        let (chain, _) = certificate(&identity).unwrap();
+        assert_eq!(
+            chain_fingerprint(&chain[0], &chain[1..], now),
+            Some(identity.fingerprint())
+        );
+        // TLS 证书用的不是设备密钥
+        assert_ne!(cert_fingerprint(&chain[0]), Some(identity.fingerprint()));
+
+        // 每次启动换一把 TLS 密钥，设备指纹不变
+        let (again, _) = certificate(&identity).unwrap();
+        assert_ne!(cert_fingerprint(&again[0]), cert_fingerprint(&chain[0]));
+        assert_eq!(
+            chain_fingerprint(&again[0], &again[1..], now),
+            Some(identity.fingerprint())
+        );
+
+        // 别的设备签发的 TLS 证书配上这台设备的 CA 证书也不行
+        let (other, _) = certificate(&Identity::generate()).unwrap();
+        assert_eq!(chain_fingerprint(&other[0], &chain[1..], now), None);
+        assert_eq!(chain_fingerprint(&chain[0], &[], now), None);
+    }
+}
@@ -0,0 +1,321 @@
+//! 持久化设置：保存在应用配置目录下的 `settings.json`，启动时读取，
+//! 通过 API 修改时写回；文件被外部修改后自动重新加载。
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::server::sandbox::SharedRoot;
+use crate::transfer::limiter::PeerRate;
+use crate::transfer::schedule::ScheduleRule;
+
+pub const DEFAULT_PORT: u16 = 8090;
+
+/// 检查配置文件是否被外部修改的间隔。
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 缺省的字段取默认值，旧版本的配置文件可以直接读取。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// 修改后重启服务生效
+    pub port: u16,
+    /// 启用 HTTPS（用设备密钥自签的证书），修改后重启服务生效
+    pub https: bool,
+    /// 对外显示的设备名，为空时使用主机名
+    pub device_name: Option<String>,
+    pub shared_roots: Vec<SharedRoot>,
+    /// 基础速率，0 表示不限
+    pub upload_bytes_per_sec: u64,
+    pub download_bytes_per_sec: u64,
+    pub upload_peers: Vec<PeerRate>,
+    pub download_peers: Vec<PeerRate>,
+    /// 单个传输的上限，0 表示不限
+    pub upload_transfer_bytes_per_sec: u64,
+    pub download_transfer_bytes_per_sec: u64,
+    pub schedule: Vec<ScheduleRule>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT,
+            https: false,
+            device_name: None,
+            shared_roots: vec![SharedRoot::home()],
+            upload_bytes_per_sec: 0,
+            download_bytes_per_sec: 0,
+            upload_peers: Vec::new(),
+            download_peers: Vec::new(),
+            upload_transfer_bytes_per_sec: 0,
+            download_transfer_bytes_per_sec: 0,
+            schedule: Vec::new(),
+        }
+    }
+}
+
+/// 命令行或环境变量指定的值，优先于配置文件，不会写回文件。
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    pub port: Option<u16>,
+    pub shared_roots: Option<Vec<SharedRoot>>,
+    /// 所有共享目录都只读
+    pub read_only: bool,
+    /// 强制启用 HTTPS
+    pub https: bool,
+    /// 同时作为上传和下载的基础速率
+    pub bytes_per_sec: Option<u64>,
+}
+
+impl Overrides {
+    fn apply(&self, settings: &mut Settings) {
+        if let Some(port) = self.port {
+            settings.port = port;
+        }
+        if let Some(roots) = &self.shared_roots {
+            settings.shared_roots = roots.clone();
+        }
+        if self.https {
+            settings.https = true;
+        }
+        if self.read_only {
+            for root in &mut settings.shared_roots {
+                root.read_only = true;
+            }
+        }
+        if let Some(rate) = self.bytes_per_sec {
+            settings.upload_bytes_per_sec = rate;
+            settings.download_bytes_per_sec = rate;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SettingsStore {
+    path: PathBuf,
+    /// 文件中保存的设置，不含 `overrides`
+    tx: Arc<watch::Sender<Settings>>,
+    overrides: Arc<Overrides>,
+    /// 最近一次读写时文件的修改时间，用来识别外部修改
+    modified: Arc<RwLock<Option<SystemTime>>>,
+}
+
+impl SettingsStore {
+    /// 与 Tauri 的 app_config_dir 一致：`<配置目录>/com.transport.app/settings.json`
+    pub fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("com.transport.app")
+            .join("settings.json")
+    }
+
+    /// 文件不存在时使用默认值；内容无法解析时打印警告并使用默认值，不覆盖原文件。
+    pub fn load(path: PathBuf) -> Self {
+        let (settings, modified) = match read(&path) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to load settings from {}: {}", path.display(), e);
+                }
+                (Settings::default(), None)
+            }
+        };
+        let store = Self::new(path, settings);
+        *store.modified.write().unwrap() = modified;
+        store
+    }
+
+    /// 以给定的设置开始，不读取文件，第一次修改时才写入。
+    pub fn new(path: PathBuf, settings: Settings) -> Self {
+        Self {
+            path,
+            tx: Arc::new(watch::channel(settings).0),
+            overrides: Arc::default(),
+            modified: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    pub fn with_overrides(mut self, overrides: Overrides) -> Self {
+        self.overrides = Arc::new(overrides);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 实际生效的设置。
+    pub fn get(&self) -> Settings {
+        let mut settings = self.saved();
+        self.overrides.apply(&mut settings);
+        settings
+    }
+
+    /// 文件中保存的设置，修改时应以此为基础，避免把命令行参数写进文件。
+    pub fn saved(&self) -> Settings {
+        self.tx.borrow().clone()
+    }
+
+    /// 每次设置变化（API 修改或文件被外部修改）都会收到通知，
+    /// 收到的是文件中的值，生效的设置用 `get` 读取。
+    pub fn subscribe(&self) -> watch::Receiver<Settings> {
+        self.tx.subscribe()
+    }
+
+    /// 修改并写回文件，返回生效的设置。
+    pub async fn update(&self, f: impl FnOnce(&mut Settings)) -> std::io::Result<Settings> {
+        let mut settings = self.saved();
+        f(&mut settings);
+        self.replace(settings).await
+    }
+
+    /// 整体替换文件中的设置，返回生效的设置。
+    pub async fn replace(&self, settings: Settings) -> std::io::Result<Settings> {
+        let json = serde_json::to_vec_pretty(&settings).map_err(std::io::Error::other)?;
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        // 先写临时文件再改名，读取方不会看到写了一半的内容
+        let temp = self.path.with_extension("json.tmp");
+        tokio::fs::write(&temp, json).await?;
+        tokio::fs::rename(&temp, &self.path).await?;
+
+        *self.modified.write().unwrap() = mtime(&self.path);
+        self.tx.send_replace(settings);
+        Ok(self.get())
+    }
+
+    /// 文件修改时间变化且内容不同时重新加载，随服务一起运行。
+    pub async fn watch_file(self) {
+        loop {
+            tokio::time::sleep(RELOAD_INTERVAL).await;
+            let current = mtime(&self.path);
+            if current == *self.modified.read().unwrap() {
+                continue;
+            }
+            match read(&self.path) {
+                Ok((settings, modified)) => {
+                    *self.modified.write().unwrap() = modified;
+                    self.tx.send_if_modified(|old| {
+                        let changed = *old != settings;
+                        *old = settings;
+                        changed
+                    });
+                }
+                // 编辑器保存到一半或写错了：保留当前设置，等下次修改
+                Err(e) => {
+                    *self.modified.write().unwrap() = current;
+                    log::warn!(
+                        "Ignoring invalid settings file {}: {}",
+                        self.path.display(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn read(path: &Path) -> std::io::Result<(Settings, Option<SystemTime>)> {
+    let modified = mtime(path);
+    let json = std::fs::read(path)?;
+    let settings = serde_json::from_slice(&json).map_err(std::io::Error::other)?;
+    Ok((settings, modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_persist_and_reload() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config").join("settings.json");
+        let store = SettingsStore::load(path.clone());
+        assert_eq!(store.get(), Settings::default());
+
+        let mut changes = store.subscribe();
+        store
+            .update(|s| {
+                s.port = 9000;
+                s.download_bytes_per_sec = 1024;
+            })
+            .await
+            .unwrap();
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(changes.borrow_and_update().port, 9000);
+
+        // 重新启动后读回
+        let reloaded = SettingsStore::load(path.clone());
+        assert_eq!(reloaded.get().download_bytes_per_sec, 1024);
+
+        // 缺省字段取默认值
+        std::fs::write(&path, r#"{"device_name": "desk"}"#).unwrap();
+        let partial = SettingsStore::load(path.clone());
+        assert_eq!(partial.get().device_name.as_deref(), Some("desk"));
+        assert_eq!(partial.get().port, DEFAULT_PORT);
+
+        // 损坏的文件不影响启动
+        std::fs::write(&path, "{").unwrap();
+        assert_eq!(SettingsStore::load(path).get(), Settings::default());
+    }
+
+    #[tokio::test]
+    async fn test_overrides_are_not_saved() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let store = SettingsStore::load(path.clone()).with_overrides(Overrides {
+            port: Some(9001),
+            read_only: true,
+            bytes_per_sec: Some(500),
+            ..Overrides::default()
+        });
+        let effective = store.update(|s| s.port = 9000).await.unwrap();
+        assert_eq!(effective.port, 9001);
+        assert!(effective.shared_roots.iter().all(|r| r.read_only));
+        assert_eq!(effective.download_bytes_per_sec, 500);
+
+        let saved = SettingsStore::load(path).get();
+        assert_eq!(saved.port, 9000);
+        assert_eq!(saved.download_bytes_per_sec, 0);
+        assert!(saved.shared_roots.iter().all(|r| !r.read_only));
+    }
+
+    #[tokio::test]
+    async fn test_external_edit_is_picked_up() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let store = SettingsStore::load(path.clone());
+        store.replace(Settings::default()).await.unwrap();
+        let mut changes = store.subscribe();
+        tokio::spawn(store.clone().watch_file());
+
+        let edited = Settings {
+            upload_bytes_per_sec: 42,
+            ..Settings::default()
+        };
+        std::fs::write(&path, serde_json::to_vec(&edited).unwrap()).unwrap();
+        // 保证修改时间不同
+        let later = SystemTime::now() + Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+
+        tokio::time::timeout(RELOAD_INTERVAL * 3, changes.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(changes.borrow().upload_bytes_per_sec, 42);
+    }
+}
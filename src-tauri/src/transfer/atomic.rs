@@ -0,0 +1,259 @@
+//! 原子落盘：上传内容先写到目标目录下的隐藏临时文件，收完后再按冲突策略
+//! 改名到最终位置，其他客户端不会看到写了一半的文件。
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+const TEMP_SUFFIX: &str = ".partial";
+
+/// 自动改名时最多尝试的序号。
+const MAX_RENAME_ATTEMPTS: u32 = 10_000;
+
+/// 目标文件已存在时的处理方式。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    #[default]
+    Overwrite,
+    /// 改存为 `name (1).ext`、`name (2).ext`……
+    Rename,
+    /// 保留已有文件，丢弃本次上传
+    Skip,
+    /// 报错
+    Fail,
+}
+
+impl ConflictPolicy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "overwrite" => Some(ConflictPolicy::Overwrite),
+            "rename" => Some(ConflictPolicy::Rename),
+            "skip" => Some(ConflictPolicy::Skip),
+            "fail" => Some(ConflictPolicy::Fail),
+            _ => None,
+        }
+    }
+}
+
+/// 上传的最终结果，随 JSON 响应返回。
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Created,
+    Overwritten,
+    Renamed,
+    Skipped,
+}
+
+/// 与 `dest` 同目录的隐藏临时文件，保证最后的改名不跨文件系统。
+pub fn temp_path(dest: &Path) -> PathBuf {
+    let name = dest
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    dest.with_file_name(format!(
+        ".{}.{}{}",
+        name,
+        &uuid::Uuid::new_v4().simple().to_string()[..8],
+        TEMP_SUFFIX
+    ))
+}
+
+/// 是否是 `temp_path` 生成的文件名，文件列表中应隐藏。
+pub fn is_temp_name(name: &str) -> bool {
+    name.starts_with('.') && name.ends_with(TEMP_SUFFIX)
+}
+
+/// 接收数据之前的检查：`Fail` 且目标已存在时报 `AlreadyExists`，
+/// `Skip` 且目标已存在时返回 `true`，调用方可以不再接收内容。
+pub fn precheck(dest: &Path, policy: ConflictPolicy) -> io::Result<bool> {
+    let exists = dest.symlink_metadata().is_ok();
+    match policy {
+        ConflictPolicy::Fail if exists => Err(already_exists(dest)),
+        ConflictPolicy::Skip => Ok(exists),
+        _ => Ok(false),
+    }
+}
+
+/// 把写好的临时文件按策略放到 `dest`，返回实际路径和结果。
+/// 除 `Overwrite` 外都不会覆盖在此期间出现的同名文件；失败或跳过时临时文件会被删除。
+pub async fn persist(
+    temp: &Path,
+    dest: &Path,
+    policy: ConflictPolicy,
+) -> io::Result<(PathBuf, Outcome)> {
+    let temp = temp.to_path_buf();
+    let dest = dest.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let result = persist_blocking(&temp, &dest, policy);
+        if !matches!(result, Ok((_, outcome)) if outcome != Outcome::Skipped) {
+            let _ = std::fs::remove_file(&temp);
+        }
+        result
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+fn persist_blocking(
+    temp: &Path,
+    dest: &Path,
+    policy: ConflictPolicy,
+) -> io::Result<(PathBuf, Outcome)> {
+    match policy {
+        ConflictPolicy::Overwrite => {
+            let existed = dest.symlink_metadata().is_ok();
+            std::fs::rename(temp, dest)?;
+            let outcome = if existed {
+                Outcome::Overwritten
+            } else {
+                Outcome::Created
+            };
+            Ok((dest.to_path_buf(), outcome))
+        }
+        ConflictPolicy::Rename => {
+            for n in 0..MAX_RENAME_ATTEMPTS {
+                let candidate = numbered(dest, n);
+                if place_new(temp, &candidate)? {
+                    let outcome = if n == 0 {
+                        Outcome::Created
+                    } else {
+                        Outcome::Renamed
+                    };
+                    return Ok((candidate, outcome));
+                }
+            }
+            Err(already_exists(dest))
+        }
+        ConflictPolicy::Skip => {
+            if place_new(temp, dest)? {
+                Ok((dest.to_path_buf(), Outcome::Created))
+            } else {
+                Ok((dest.to_path_buf(), Outcome::Skipped))
+            }
+        }
+        ConflictPolicy::Fail => {
+            if place_new(temp, dest)? {
+                Ok((dest.to_path_buf(), Outcome::Created))
+            } else {
+                Err(already_exists(dest))
+            }
+        }
+    }
+}
+
+/// 不覆盖地把 `temp` 放到 `dest`，`dest` 已存在时返回 `false`。
+fn place_new(temp: &Path, dest: &Path) -> io::Result<bool> {
+    // 硬链接在目标已存在时必定失败，没有先检查后改名的竞态
+    match std::fs::hard_link(temp, dest) {
+        Ok(()) => {
+            std::fs::remove_file(temp)?;
+            Ok(true)
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(_) => {
+            // FAT / exFAT 等不支持硬链接的文件系统，退回先检查再改名
+            if dest.symlink_metadata().is_ok() {
+                return Ok(false);
+            }
+            std::fs::rename(temp, dest)?;
+            Ok(true)
+        }
+    }
+}
+
+/// `report.pdf` -> `report (n).pdf`，`n == 0` 时原样返回。
+fn numbered(dest: &Path, n: u32) -> PathBuf {
+    if n == 0 {
+        return dest.to_path_buf();
+    }
+    let stem = dest
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match dest.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    dest.with_file_name(name)
+}
+
+fn already_exists(dest: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("File already exists: {}", dest.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn upload(dest: &Path, content: &str, policy: ConflictPolicy) -> (PathBuf, Outcome) {
+        let temp = temp_path(dest);
+        assert!(is_temp_name(&temp.file_name().unwrap().to_string_lossy()));
+        fs::write(&temp, content).unwrap();
+        let result = persist(&temp, dest, policy).await.unwrap();
+        assert!(!temp.exists());
+        result
+    }
+
+    #[tokio::test]
+    async fn test_conflict_policies() {
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("report.pdf");
+
+        let (path, outcome) = upload(&dest, "a", ConflictPolicy::Fail).await;
+        assert_eq!(
+            (path.as_path(), outcome),
+            (dest.as_path(), Outcome::Created)
+        );
+
+        let (_, outcome) = upload(&dest, "b", ConflictPolicy::Overwrite).await;
+        assert_eq!(outcome, Outcome::Overwritten);
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "b");
+
+        let (path, outcome) = upload(&dest, "c", ConflictPolicy::Rename).await;
+        assert_eq!(outcome, Outcome::Renamed);
+        assert_eq!(path, dir.path().join("report (1).pdf"));
+        let (path, _) = upload(&dest, "d", ConflictPolicy::Rename).await;
+        assert_eq!(path, dir.path().join("report (2).pdf"));
+
+        let (_, outcome) = upload(&dest, "e", ConflictPolicy::Skip).await;
+        assert_eq!(outcome, Outcome::Skipped);
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "b");
+
+        let temp = temp_path(&dest);
+        fs::write(&temp, "f").unwrap();
+        let err = persist(&temp, &dest, ConflictPolicy::Fail)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(!temp.exists());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_precheck() {
+        let dir = tempdir().unwrap();
+        let dest = dir.path().join("a.txt");
+        assert!(!precheck(&dest, ConflictPolicy::Skip).unwrap());
+        assert!(precheck(&dest, ConflictPolicy::Fail).is_ok());
+
+        fs::write(&dest, "x").unwrap();
+        assert!(precheck(&dest, ConflictPolicy::Skip).unwrap());
+        assert!(!precheck(&dest, ConflictPolicy::Overwrite).unwrap());
+        assert_eq!(
+            precheck(&dest, ConflictPolicy::Fail).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(
+            numbered(Path::new("/x/.bashrc"), 1),
+            Path::new("/x/.bashrc (1)")
+        );
+    }
+}
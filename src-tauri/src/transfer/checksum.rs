@@ -0,0 +1,313 @@
+//! 文件校验和（BLAKE3 / SHA-256）。
+//!
+//! 客户端用 `"<算法>:<十六进制>"` 表示期望的摘要；HTTP 头和 trailer 使用
+//! RFC 9530 的 `Repr-Digest` 格式。计算结果按路径缓存，长度或修改时间变化后失效。
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+/// 缓存条目上限，超出后整体清空。
+const CACHE_CAPACITY: usize = 4096;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    Blake3,
+    Sha256,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 2] = [Algorithm::Blake3, Algorithm::Sha256];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// RFC 9530 摘要算法名
+    pub fn http_name(self) -> &'static str {
+        match self {
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Sha256 => "sha-256",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "blake3" => Some(Algorithm::Blake3),
+            "sha256" | "sha-256" => Some(Algorithm::Sha256),
+            _ => None,
+        }
+    }
+}
+
+pub enum Hasher {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(sha2::Sha256),
+}
+
+impl Hasher {
+    pub fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Blake3 => Hasher::Blake3(Box::default()),
+            Algorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+            Hasher::Sha256(h) => h.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> Checksum {
+        match self {
+            Hasher::Blake3(h) => Checksum {
+                algorithm: Algorithm::Blake3,
+                digest: h.finalize().as_bytes().to_vec(),
+            },
+            Hasher::Sha256(h) => Checksum {
+                algorithm: Algorithm::Sha256,
+                digest: h.finalize().to_vec(),
+            },
+        }
+    }
+}
+
+/// 带算法的摘要，文本形式为 `"<算法>:<十六进制>"`。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: Algorithm,
+    pub digest: Vec<u8>,
+}
+
+impl Checksum {
+    pub fn parse(s: &str) -> Option<Self> {
+        let (algorithm, hex) = s.split_once(':')?;
+        let algorithm = Algorithm::parse(algorithm)?;
+        let hex = hex.trim();
+        if hex.len() != 64 || !hex.is_ascii() {
+            return None;
+        }
+        let digest = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        Some(Self { algorithm, digest })
+    }
+
+    pub fn hex(&self) -> String {
+        self.digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl std::fmt::Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.hex())
+    }
+}
+
+impl Serialize for Checksum {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Checksum {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Checksum::parse(&s).ok_or_else(|| {
+            serde::de::Error::custom("expected \"blake3:<hex>\" or \"sha256:<hex>\"")
+        })
+    }
+}
+
+/// `Repr-Digest` 头的值，例如 `sha-256=:base64:, blake3=:base64:`。
+pub fn repr_digest(checksums: &[Checksum]) -> String {
+    checksums
+        .iter()
+        .map(|c| {
+            format!(
+                "{}=:{}:",
+                c.algorithm.http_name(),
+                base64::engine::general_purpose::STANDARD.encode(&c.digest)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 解析 `Want-Repr-Digest`（如 `sha-256=5, blake3=10`），按偏好从高到低返回支持的算法；
+/// 偏好为 0 表示不要。
+pub fn parse_want_digest(value: &str) -> Vec<Algorithm> {
+    let mut wanted: Vec<(u8, Algorithm)> = value
+        .split(',')
+        .filter_map(|item| {
+            let (name, weight) = item.split_once('=').unwrap_or((item, "1"));
+            let algorithm = Algorithm::parse(name)?;
+            let weight: u8 = weight.trim().parse().ok()?;
+            (weight > 0).then_some((weight, algorithm))
+        })
+        .collect();
+    wanted.sort_by_key(|(weight, _)| std::cmp::Reverse(*weight));
+    wanted.into_iter().map(|(_, a)| a).collect()
+}
+
+/// 一次读完文件，同时计算多种摘要。
+pub async fn hash_file(path: &Path, algorithms: &[Algorithm]) -> std::io::Result<Vec<Checksum>> {
+    let path = path.to_path_buf();
+    let algorithms = algorithms.to_vec();
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path)?;
+        let mut hashers: Vec<Hasher> = algorithms.into_iter().map(Hasher::new).collect();
+        let mut buf = vec![0u8; 1024 * 1024];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            for hasher in &mut hashers {
+                hasher.update(&buf[..n]);
+            }
+        }
+        Ok(hashers.into_iter().map(Hasher::finalize).collect())
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+struct CachedDigest {
+    len: u64,
+    modified: Option<SystemTime>,
+    checksum: Checksum,
+}
+
+/// 已知文件摘要的缓存；下载时据此直接给出 `Repr-Digest` 头。
+#[derive(Clone, Default)]
+pub struct ChecksumCache {
+    entries: Arc<Mutex<HashMap<(PathBuf, Algorithm), CachedDigest>>>,
+}
+
+impl ChecksumCache {
+    /// 文件当前版本已知的全部摘要。
+    pub fn lookup(&self, path: &Path, len: u64, modified: Option<SystemTime>) -> Vec<Checksum> {
+        let entries = self.entries.lock().unwrap();
+        Algorithm::ALL
+            .iter()
+            .filter_map(|&algorithm| entries.get(&(path.to_path_buf(), algorithm)))
+            .filter(|c| c.len == len && c.modified == modified)
+            .map(|c| c.checksum.clone())
+            .collect()
+    }
+
+    pub fn insert(&self, path: &Path, len: u64, modified: Option<SystemTime>, checksum: Checksum) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= CACHE_CAPACITY {
+            entries.clear();
+        }
+        entries.insert(
+            (path.to_path_buf(), checksum.algorithm),
+            CachedDigest {
+                len,
+                modified,
+                checksum,
+            },
+        );
+    }
+
+    /// 返回请求的摘要，缓存中没有的现场计算并记下。
+    pub async fn compute(
+        &self,
+        path: &Path,
+        algorithms: &[Algorithm],
+    ) -> std::io::Result<Vec<Checksum>> {
+        let metadata = tokio::fs::metadata(path).await?;
+        let (len, modified) = (metadata.len(), metadata.modified().ok());
+        let known = self.lookup(path, len, modified);
+
+        let missing: Vec<Algorithm> = algorithms
+            .iter()
+            .copied()
+            .filter(|a| !known.iter().any(|c| c.algorithm == *a))
+            .collect();
+        let computed = if missing.is_empty() {
+            Vec::new()
+        } else {
+            hash_file(path, &missing).await?
+        };
+        for checksum in &computed {
+            self.insert(path, len, modified, checksum.clone());
+        }
+
+        Ok(algorithms
+            .iter()
+            .filter_map(|a| {
+                known
+                    .iter()
+                    .chain(&computed)
+                    .find(|c| c.algorithm == *a)
+                    .cloned()
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    // 空字符串的 SHA-256
+    const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn test_parse_and_format() {
+        let checksum = Checksum::parse(&format!("SHA-256:{}", EMPTY_SHA256)).unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha256);
+        assert_eq!(checksum.to_string(), format!("sha256:{}", EMPTY_SHA256));
+        assert_eq!(
+            repr_digest(&[checksum]),
+            "sha-256=:47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=:"
+        );
+
+        assert!(Checksum::parse("md5:abcd").is_none());
+        assert!(Checksum::parse("sha256:xyz").is_none());
+        assert_eq!(
+            parse_want_digest("sha-256=3, blake3=9, md5=10, sha-512=0"),
+            vec![Algorithm::Blake3, Algorithm::Sha256]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_invalidates_on_change() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "").unwrap();
+        let cache = ChecksumCache::default();
+
+        let sums = cache.compute(&file, &Algorithm::ALL).await.unwrap();
+        assert_eq!(sums[1].hex(), EMPTY_SHA256);
+        assert_eq!(sums[0].hex(), blake3::hash(b"").to_hex().as_str());
+
+        let meta = std::fs::metadata(&file).unwrap();
+        assert_eq!(cache.lookup(&file, 0, meta.modified().ok()).len(), 2);
+
+        std::fs::write(&file, "changed").unwrap();
+        let meta = std::fs::metadata(&file).unwrap();
+        assert!(cache
+            .lookup(&file, meta.len(), meta.modified().ok())
+            .is_empty());
+    }
+}
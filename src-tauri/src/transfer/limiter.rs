@@ -0,0 +1,290 @@
+//! 分层限速：单个传输上限 → 对端上限（按设备 id 或 IP）→ 全局上限。
+//!
+//! 每次只按 `QUANTUM` 大小向各层预留额度。预留是原子的，先预留的先轮到，
+//! 等待在预留之后、不持有任何锁，于是并发的传输一块一块轮流前进，
+//! 大块读写的下载也没法独占带宽，慢的等待者也不会挡住别人。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use super::manager::Peer;
+use super::throttle::Throttle;
+
+/// 每轮取的字节数，越小越公平、开销越大。
+const QUANTUM: usize = 64 * 1024;
+
+/// 单个对端的限速设置，`peer` 是设备 id 或 IP。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PeerRate {
+    pub peer: String,
+    pub bytes_per_sec: u64,
+}
+
+/// 一个方向（上传或下载）的分层限速器，克隆后共享状态。
+#[derive(Clone)]
+pub struct Limiter {
+    global: Throttle,
+    peers: Arc<Mutex<HashMap<String, Throttle>>>,
+    /// 新建传输的单个上限，0 表示不限
+    transfer_rate: Arc<AtomicU64>,
+}
+
+impl Limiter {
+    /// `bytes_per_sec` 为全局上限，0 表示不限。
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            global: Throttle::new(bytes_per_sec),
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            transfer_rate: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// 全局限速器。
+    pub fn global(&self) -> &Throttle {
+        &self.global
+    }
+
+    /// 设置单个传输的上限，0 表示不限；对之后开始的传输生效。
+    pub fn set_transfer_rate(&self, bytes_per_sec: u64) {
+        self.transfer_rate.store(bytes_per_sec, Ordering::Relaxed);
+    }
+
+    pub fn transfer_rate(&self) -> u64 {
+        self.transfer_rate.load(Ordering::Relaxed)
+    }
+
+    /// 设置对端上限，0 表示取消。
+    pub async fn set_peer_rate(&self, peer: &str, bytes_per_sec: u64) {
+        if bytes_per_sec == 0 {
+            self.peers.lock().unwrap().remove(peer);
+            return;
+        }
+        let existing = self.peers.lock().unwrap().get(peer).cloned();
+        match existing {
+            Some(throttle) => throttle.set_rate(bytes_per_sec).await,
+            None => {
+                self.peers
+                    .lock()
+                    .unwrap()
+                    .insert(peer.to_string(), Throttle::new(bytes_per_sec));
+            }
+        }
+    }
+
+    /// 用 `rates` 整体替换对端上限，已有的限速器保留，没列出的取消。
+    pub async fn replace_peer_rates(&self, rates: &[PeerRate]) {
+        let keep: Vec<&str> = rates
+            .iter()
+            .filter(|r| r.bytes_per_sec > 0)
+            .map(|r| r.peer.as_str())
+            .collect();
+        self.peers
+            .lock()
+            .unwrap()
+            .retain(|peer, _| keep.contains(&peer.as_str()));
+        for rate in rates {
+            self.set_peer_rate(&rate.peer, rate.bytes_per_sec).await;
+        }
+    }
+
+    pub async fn peer_rates(&self) -> Vec<PeerRate> {
+        let throttles: Vec<(String, Throttle)> = self
+            .peers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        let mut rates = Vec::with_capacity(throttles.len());
+        for (peer, throttle) in throttles {
+            rates.push(PeerRate {
+                peer,
+                bytes_per_sec: throttle.get_rate().await,
+            });
+        }
+        rates.sort_by(|a, b| a.peer.cmp(&b.peer));
+        rates
+    }
+
+    /// 设备 id 的设置优先于 IP。
+    fn peer_throttle(&self, peer: &Peer) -> Option<Throttle> {
+        let peers = self.peers.lock().unwrap();
+        peer.device_id
+            .as_ref()
+            .and_then(|id| peers.get(id))
+            .or_else(|| peers.get(&peer.addr))
+            .cloned()
+    }
+
+    /// 为一个传输创建限速器，供读写循环逐块调用；克隆后属于同一个传输。
+    pub fn pacer(&self, peer: Peer) -> Pacer {
+        let rate = self.transfer_rate();
+        Pacer {
+            limiter: self.clone(),
+            transfer: (rate > 0).then(|| Throttle::new(rate)),
+            peer,
+        }
+    }
+}
+
+/// 一个传输的限速器：绑定了对端，带有这个传输自己的上限。
+#[derive(Clone)]
+pub struct Pacer {
+    limiter: Limiter,
+    transfer: Option<Throttle>,
+    peer: Peer,
+}
+
+impl Pacer {
+    pub async fn consume(&self, bytes: usize) {
+        let peer = self.limiter.peer_throttle(&self.peer);
+        let mut remaining = bytes;
+        while remaining > 0 {
+            let n = remaining.min(QUANTUM);
+            if let Some(transfer) = &self.transfer {
+                transfer.consume(n).await;
+            }
+            if let Some(peer) = &peer {
+                peer.consume(n).await;
+            }
+            self.limiter.global.consume(n).await;
+            remaining -= n;
+        }
+    }
+}
+
+/// 上传和下载各自独立的限速器。
+#[derive(Clone)]
+pub struct Throttles {
+    pub upload: Limiter,
+    pub download: Limiter,
+}
+
+impl Throttles {
+    pub fn new(upload_bytes_per_sec: u64, download_bytes_per_sec: u64) -> Self {
+        Self {
+            upload: Limiter::new(upload_bytes_per_sec),
+            download: Limiter::new(download_bytes_per_sec),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{Duration, Instant};
+
+    fn peer(addr: &str, device_id: Option<&str>) -> Peer {
+        Peer {
+            addr: addr.to_string(),
+            device_id: device_id.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_peer_cap_applies_to_matching_peer_only() {
+        let limiter = Limiter::new(0);
+        limiter.set_peer_rate("10.0.0.2", 1000).await;
+        limiter.set_peer_rate("phone", 2000).await;
+        assert_eq!(
+            limiter.peer_rates().await,
+            vec![
+                PeerRate {
+                    peer: "10.0.0.2".to_string(),
+                    bytes_per_sec: 1000
+                },
+                PeerRate {
+                    peer: "phone".to_string(),
+                    bytes_per_sec: 2000
+                },
+            ]
+        );
+
+        let start = Instant::now();
+        limiter.pacer(peer("10.0.0.3", None)).consume(100_000).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        // 初始额度 1000，剩下 500 要等约 500ms
+        let start = Instant::now();
+        limiter.pacer(peer("10.0.0.2", None)).consume(1500).await;
+        assert!(start.elapsed() >= Duration::from_millis(400));
+
+        // 设备 id 优先：phone 的上限是 2000，不受 IP 上限影响
+        let start = Instant::now();
+        limiter
+            .pacer(peer("10.0.0.2", Some("phone")))
+            .consume(2000)
+            .await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        limiter.set_peer_rate("10.0.0.2", 0).await;
+        assert_eq!(limiter.peer_rates().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_transfers_share_fairly() {
+        let limiter = Limiter::new(1_000_000);
+        // 先用掉初始额度
+        limiter.pacer(Peer::default()).consume(1_000_000).await;
+
+        let greedy = tokio::spawn({
+            let limiter = limiter.clone();
+            async move {
+                limiter.pacer(Peer::default()).consume(1_000_000).await;
+                Instant::now()
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let start = Instant::now();
+        limiter.pacer(Peer::default()).consume(128 * 1024).await;
+        let small_done = Instant::now();
+        // 轮流取额度：小传输不必等大传输结束
+        assert!(small_done - start < Duration::from_millis(600));
+        assert!(greedy.await.unwrap() > small_done);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_cap_is_per_transfer() {
+        let limiter = Limiter::new(0);
+        limiter.set_transfer_rate(1000);
+
+        // 同一个传输：初始额度 1000，剩下 500 要等约 500ms
+        let pacer = limiter.pacer(Peer::default());
+        let start = Instant::now();
+        pacer.consume(1000).await;
+        pacer.consume(500).await;
+        assert!(start.elapsed() >= Duration::from_millis(400));
+
+        // 另一个传输有自己的额度
+        let start = Instant::now();
+        limiter.pacer(Peer::default()).consume(1000).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_waiting_transfer_does_not_block_others() {
+        let limiter = Limiter::new(0);
+        limiter.set_peer_rate("slow", 1000).await;
+        let slow = limiter.pacer(peer("10.0.0.2", Some("slow")));
+        slow.consume(1000).await;
+
+        // 慢对端等待额度期间，其他对端照常通过
+        let waiting = tokio::spawn(async move { slow.consume(1000).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let start = Instant::now();
+        limiter
+            .pacer(peer("10.0.0.3", None))
+            .consume(1_000_000)
+            .await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+        waiting.await.unwrap();
+    }
+}
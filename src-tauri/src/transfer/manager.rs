@@ -0,0 +1,591 @@
+//! 服务端传输队列：记录每个进行中的上传/下载（对端、路径、进度、速率、状态），
+//! 支持暂停、继续和取消，所有客户端看到的是同一份队列。
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+
+use crate::events::{Event, EventBus};
+
+/// 已结束的传输最多保留多少条。
+const MAX_FINISHED: usize = 100;
+/// 速率按至少这么长的窗口统计。
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+/// 同一传输两次进度事件之间的最短间隔。
+const PROGRESS_EVENT_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferStatus {
+    /// 分块上传的两个分块之间
+    Queued,
+    Transferring,
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl TransferStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            TransferStatus::Completed | TransferStatus::Failed | TransferStatus::Cancelled
+        )
+    }
+}
+
+/// 传输的另一端。
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Peer {
+    pub addr: String,
+    /// 已配对设备的 id；本机或未知时为空
+    pub device_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TransferInfo {
+    pub id: String,
+    pub direction: Direction,
+    pub peer: Peer,
+    pub path: String,
+    /// 总字节数；multipart 上传事先不知道
+    pub total: Option<u64>,
+    pub transferred: u64,
+    /// 字节/秒
+    pub rate: u64,
+    pub status: TransferStatus,
+    pub error: Option<String>,
+    /// Unix 毫秒
+    pub started_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug)]
+pub enum TransferError {
+    NotFound,
+    Finished,
+}
+
+impl std::fmt::Display for TransferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferError::NotFound => write!(f, "Transfer not found"),
+            TransferError::Finished => write!(f, "Transfer already finished"),
+        }
+    }
+}
+
+/// 传输被取消时 `TransferHandle::advance` 返回的错误。
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Transfer cancelled")
+    }
+}
+
+impl From<Cancelled> for std::io::Error {
+    fn from(e: Cancelled) -> Self {
+        std::io::Error::other(e.to_string())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Control {
+    Run,
+    Pause,
+    Cancel,
+}
+
+struct Entry {
+    info: TransferInfo,
+    control: watch::Sender<Control>,
+    /// 是否有请求正在读写这个传输
+    active: bool,
+    window_start: Instant,
+    window_bytes: u64,
+    last_progress_event: Instant,
+}
+
+/// 状态变化时要发布的事件。
+type EventKind = fn(TransferInfo) -> Event;
+
+#[derive(Clone, Default)]
+pub struct TransferManager {
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+    events: EventBus,
+}
+
+impl TransferManager {
+    pub fn new(events: EventBus) -> Self {
+        Self {
+            entries: Arc::default(),
+            events,
+        }
+    }
+
+    /// 登记一个新的传输。
+    pub fn begin(
+        &self,
+        direction: Direction,
+        peer: Peer,
+        path: String,
+        total: Option<u64>,
+    ) -> TransferHandle {
+        let id = uuid::Uuid::new_v4().to_string();
+        self.begin_with_id(id, direction, peer, path, total, 0)
+    }
+
+    /// 以指定 id 登记或接续一个传输（分块上传的每个分块都用会话 id）。
+    /// 接续时保留开始时间和暂停状态。
+    pub fn begin_with_id(
+        &self,
+        id: String,
+        direction: Direction,
+        peer: Peer,
+        path: String,
+        total: Option<u64>,
+        transferred: u64,
+    ) -> TransferHandle {
+        let now = now_millis();
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(id.clone()).or_insert_with(|| Entry {
+            info: TransferInfo {
+                id: id.clone(),
+                direction,
+                peer: peer.clone(),
+                path: path.clone(),
+                total,
+                transferred,
+                rate: 0,
+                status: TransferStatus::Transferring,
+                error: None,
+                started_at: now,
+                updated_at: now,
+            },
+            control: watch::channel(Control::Run).0,
+            active: false,
+            window_start: Instant::now(),
+            window_bytes: 0,
+            last_progress_event: Instant::now(),
+        });
+
+        if *entry.control.borrow() == Control::Cancel {
+            entry.control.send_replace(Control::Run);
+        }
+        let paused = *entry.control.borrow() == Control::Pause;
+        entry.info.peer = peer;
+        entry.info.path = path;
+        entry.info.total = total;
+        entry.info.transferred = transferred;
+        entry.info.rate = 0;
+        entry.info.error = None;
+        entry.info.updated_at = now;
+        entry.info.status = if paused {
+            TransferStatus::Paused
+        } else {
+            TransferStatus::Transferring
+        };
+        entry.active = true;
+        entry.window_start = Instant::now();
+        entry.window_bytes = 0;
+
+        let control = entry.control.subscribe();
+        let info = entry.info.clone();
+        drop(entries);
+        self.events.publish(Event::TransferStarted(info));
+
+        TransferHandle {
+            id,
+            manager: self.clone(),
+            control,
+            finished: false,
+            resumable: false,
+        }
+    }
+
+    /// 所有传输，最新的在前。
+    pub fn list(&self) -> Vec<TransferInfo> {
+        let entries = self.entries.lock().unwrap();
+        let mut list: Vec<_> = entries.values().map(|e| e.info.clone()).collect();
+        list.sort_by_key(|t| std::cmp::Reverse(t.started_at));
+        list
+    }
+
+    pub fn get(&self, id: &str) -> Option<TransferInfo> {
+        self.entries.lock().unwrap().get(id).map(|e| e.info.clone())
+    }
+
+    pub fn pause(&self, id: &str) -> Result<TransferInfo, TransferError> {
+        self.control(id, Control::Pause, Event::TransferUpdated, |_| {
+            TransferStatus::Paused
+        })
+    }
+
+    pub fn resume(&self, id: &str) -> Result<TransferInfo, TransferError> {
+        self.control(id, Control::Run, Event::TransferUpdated, |active| {
+            if active {
+                TransferStatus::Transferring
+            } else {
+                TransferStatus::Queued
+            }
+        })
+    }
+
+    /// 取消后正在进行的读写会在下一个分块处中断。
+    pub fn cancel(&self, id: &str) -> Result<TransferInfo, TransferError> {
+        self.control(id, Control::Cancel, Event::TransferFailed, |_| {
+            TransferStatus::Cancelled
+        })
+    }
+
+    /// 清除所有已结束的记录。
+    pub fn clear_finished(&self) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, e| !e.info.status.is_finished());
+    }
+
+    fn control(
+        &self,
+        id: &str,
+        control: Control,
+        event: EventKind,
+        status: impl FnOnce(bool) -> TransferStatus,
+    ) -> Result<TransferInfo, TransferError> {
+        let info = {
+            let mut entries = self.entries.lock().unwrap();
+            let entry = entries.get_mut(id).ok_or(TransferError::NotFound)?;
+            if entry.info.status.is_finished() {
+                return Err(TransferError::Finished);
+            }
+            entry.control.send_replace(control);
+            entry.info.status = status(entry.active);
+            entry.info.rate = 0;
+            entry.info.updated_at = now_millis();
+            entry.info.clone()
+        };
+        self.events.publish(event(info.clone()));
+        Ok(info)
+    }
+
+    /// 修改一项记录；`f` 返回事件类型时在释放锁之后发布。
+    fn update(&self, id: &str, f: impl FnOnce(&mut Entry) -> Option<EventKind>) {
+        let event = {
+            let mut entries = self.entries.lock().unwrap();
+            let Some(entry) = entries.get_mut(id) else {
+                return;
+            };
+            let kind = f(entry);
+            entry.info.updated_at = now_millis();
+            kind.map(|kind| kind(entry.info.clone()))
+        };
+        if let Some(event) = event {
+            self.events.publish(event);
+        }
+    }
+
+    /// 已结束的记录超过上限时丢掉最早的。
+    fn trim_finished(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        let mut finished: Vec<_> = entries
+            .values()
+            .filter(|e| e.info.status.is_finished())
+            .map(|e| (e.info.updated_at, e.info.id.clone()))
+            .collect();
+        if finished.len() <= MAX_FINISHED {
+            return;
+        }
+        finished.sort();
+        let excess = finished.len() - MAX_FINISHED;
+        for (_, id) in finished.into_iter().take(excess) {
+            entries.remove(&id);
+        }
+    }
+}
+
+/// 正在进行的一次读写；结束时调用 `complete` / `suspend` / `fail`，
+/// 未调用就被丢弃（例如客户端断开）视为失败，可续传的传输视为等待下一块。
+pub struct TransferHandle {
+    id: String,
+    manager: TransferManager,
+    control: watch::Receiver<Control>,
+    finished: bool,
+    resumable: bool,
+}
+
+impl TransferHandle {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// 记录又传输了 `bytes` 字节；暂停时在这里等待继续，取消时返回错误。
+    pub async fn advance(&mut self, bytes: usize) -> Result<(), Cancelled> {
+        self.manager.update(&self.id, |entry| {
+            entry.info.transferred += bytes as u64;
+            entry.window_bytes += bytes as u64;
+            let elapsed = entry.window_start.elapsed();
+            if elapsed >= RATE_WINDOW {
+                entry.info.rate = (entry.window_bytes as f64 / elapsed.as_secs_f64()) as u64;
+                entry.window_start = Instant::now();
+                entry.window_bytes = 0;
+            }
+            if entry.last_progress_event.elapsed() < PROGRESS_EVENT_INTERVAL {
+                return None;
+            }
+            entry.last_progress_event = Instant::now();
+            Some(Event::TransferProgress)
+        });
+
+        loop {
+            let control = *self.control.borrow_and_update();
+            match control {
+                Control::Run => return Ok(()),
+                Control::Cancel => return Err(Cancelled),
+                Control::Pause => {
+                    if self.control.changed().await.is_err() {
+                        return Err(Cancelled);
+                    }
+                    // 暂停期间的时间不计入速率
+                    self.manager.update(&self.id, |entry| {
+                        entry.window_start = Instant::now();
+                        entry.window_bytes = 0;
+                        None
+                    });
+                }
+            }
+        }
+    }
+
+    /// 标记为可续传（分块上传）：已收到的数据保存在会话里，断开后客户端还能接着传。
+    pub fn resumable(mut self) -> Self {
+        self.resumable = true;
+        self
+    }
+
+    pub fn complete(mut self) {
+        self.finish(TransferStatus::Completed, None);
+    }
+
+    pub fn fail(mut self, error: impl ToString) {
+        self.abort(error.to_string());
+    }
+
+    /// 本次请求结束但传输尚未完成（分块上传等待下一个分块）。
+    pub fn suspend(mut self) {
+        self.wait_for_next();
+    }
+
+    fn wait_for_next(&mut self) {
+        self.finished = true;
+        self.manager.update(&self.id, |entry| {
+            entry.active = false;
+            entry.info.rate = 0;
+            if entry.info.status == TransferStatus::Transferring {
+                entry.info.status = TransferStatus::Queued;
+            }
+            Some(Event::TransferUpdated)
+        });
+    }
+
+    /// 被取消的记为已取消，其余记为失败。
+    fn abort(&mut self, error: String) {
+        let status = if *self.control.borrow() == Control::Cancel {
+            TransferStatus::Cancelled
+        } else {
+            TransferStatus::Failed
+        };
+        self.finish(status, Some(error));
+    }
+
+    fn finish(&mut self, status: TransferStatus, error: Option<String>) {
+        self.finished = true;
+        self.manager.update(&self.id, |entry| {
+            entry.active = false;
+            entry.info.rate = 0;
+            entry.info.status = status;
+            entry.info.error = error;
+            Some(if status == TransferStatus::Completed {
+                Event::TransferCompleted
+            } else {
+                Event::TransferFailed
+            })
+        });
+        self.manager.trim_finished();
+    }
+}
+
+impl Drop for TransferHandle {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if self.resumable && *self.control.borrow() != Control::Cancel {
+            self.wait_for_next();
+        } else {
+            self.abort("Connection interrupted".to_string());
+        }
+    }
+}
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer() -> Peer {
+        Peer {
+            addr: "192.168.1.5".to_string(),
+            device_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_progress_and_completion() {
+        let manager = TransferManager::default();
+        let mut handle = manager.begin(Direction::Download, peer(), "/a.bin".into(), Some(10));
+        let id = handle.id().to_string();
+
+        handle.advance(4).await.unwrap();
+        handle.advance(6).await.unwrap();
+        let info = manager.get(&id).unwrap();
+        assert_eq!(info.transferred, 10);
+        assert_eq!(info.status, TransferStatus::Transferring);
+
+        handle.complete();
+        assert_eq!(manager.get(&id).unwrap().status, TransferStatus::Completed);
+        assert!(matches!(manager.pause(&id), Err(TransferError::Finished)));
+
+        manager.clear_finished();
+        assert!(manager.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pause_blocks_until_resumed() {
+        let manager = TransferManager::default();
+        let mut handle = manager.begin(Direction::Upload, peer(), "/b.bin".into(), None);
+        let id = handle.id().to_string();
+
+        manager.pause(&id).unwrap();
+        let task = tokio::spawn(async move {
+            handle.advance(1).await.unwrap();
+            handle
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!task.is_finished());
+        assert_eq!(manager.get(&id).unwrap().status, TransferStatus::Paused);
+
+        manager.resume(&id).unwrap();
+        let handle = task.await.unwrap();
+        assert_eq!(manager.get(&id).unwrap().transferred, 1);
+        handle.complete();
+    }
+
+    #[tokio::test]
+    async fn test_cancel_and_dropped_handles() {
+        let manager = TransferManager::default();
+        let mut handle = manager.begin(Direction::Download, peer(), "/c.bin".into(), Some(5));
+        let id = handle.id().to_string();
+        manager.cancel(&id).unwrap();
+        assert!(handle.advance(1).await.is_err());
+        drop(handle);
+        assert_eq!(manager.get(&id).unwrap().status, TransferStatus::Cancelled);
+
+        // 客户端断开：未正常结束的句柄记为失败
+        let handle = manager.begin(Direction::Download, peer(), "/d.bin".into(), Some(5));
+        let id = handle.id().to_string();
+        drop(handle);
+        assert_eq!(manager.get(&id).unwrap().status, TransferStatus::Failed);
+
+        // 可续传的传输断开后等待下一块，被取消的仍记为已取消
+        let handle = manager
+            .begin(Direction::Upload, peer(), "/e.bin".into(), Some(5))
+            .resumable();
+        let id = handle.id().to_string();
+        drop(handle);
+        assert_eq!(manager.get(&id).unwrap().status, TransferStatus::Queued);
+
+        let handle = manager
+            .begin(Direction::Upload, peer(), "/f.bin".into(), Some(5))
+            .resumable();
+        let id = handle.id().to_string();
+        manager.cancel(&id).unwrap();
+        drop(handle);
+        assert_eq!(manager.get(&id).unwrap().status, TransferStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_chunked_upload_reuses_id() {
+        let manager = TransferManager::default();
+        let mut first = manager.begin_with_id(
+            "s1".into(),
+            Direction::Upload,
+            peer(),
+            "/e".into(),
+            Some(8),
+            0,
+        );
+        first.advance(4).await.unwrap();
+        first.suspend();
+        assert_eq!(manager.get("s1").unwrap().status, TransferStatus::Queued);
+
+        let mut second = manager.begin_with_id(
+            "s1".into(),
+            Direction::Upload,
+            peer(),
+            "/e".into(),
+            Some(8),
+            4,
+        );
+        second.advance(4).await.unwrap();
+        second.complete();
+        let info = manager.get("s1").unwrap();
+        assert_eq!(info.transferred, 8);
+        assert_eq!(info.status, TransferStatus::Completed);
+        assert_eq!(manager.list().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_events_are_published() {
+        let events = EventBus::new();
+        let mut receiver = events.subscribe();
+        let manager = TransferManager::new(events);
+
+        let mut handle = manager.begin(Direction::Download, peer(), "/f.bin".into(), Some(3));
+        handle.advance(3).await.unwrap();
+        manager.pause(handle.id()).unwrap();
+        manager.resume(handle.id()).unwrap();
+        handle.complete();
+
+        let mut names = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            names.push(event.name());
+        }
+        // 第一次进度事件落在节流间隔内，被合并掉
+        assert_eq!(
+            names,
+            vec![
+                "transfer-started",
+                "transfer-updated",
+                "transfer-updated",
+                "transfer-completed"
+            ]
+        );
+    }
+}
@@ -1 +1,7 @@
+pub mod atomic;
+pub mod checksum;
+pub mod limiter;
+pub mod manager;
+pub mod schedule;
+pub mod session;
 pub mod throttle;
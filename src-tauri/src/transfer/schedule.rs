@@ -0,0 +1,275 @@
+//! 按时段限速：规则是"星期 + 时间段 → 速率"，第一条命中的规则生效，
+//! 都不命中时用 `set_throttle` 设置的基础速率。后台任务每分钟把结果写入全局限速器。
+
+use std::sync::{Arc, Mutex};
+
+use chrono::{Datelike, Timelike};
+use serde::{Deserialize, Serialize};
+
+use super::limiter::Throttles;
+
+/// 一天中的时刻，文本形式 `"HH:MM"`。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay(u16);
+
+impl TimeOfDay {
+    pub fn parse(s: &str) -> Option<Self> {
+        let (h, m) = s.trim().split_once(':')?;
+        let (h, m): (u16, u16) = (h.parse().ok()?, m.parse().ok()?);
+        // 允许 24:00 表示一天结束
+        if m >= 60 || h > 24 || (h == 24 && m != 0) {
+            return None;
+        }
+        Some(Self(h * 60 + m))
+    }
+
+    pub fn minutes(self) -> u16 {
+        self.0
+    }
+}
+
+impl std::fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+impl Serialize for TimeOfDay {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        TimeOfDay::parse(&s).ok_or_else(|| serde::de::Error::custom("expected \"HH:MM\""))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScheduleRule {
+    /// 星期几，1 = 周一 … 7 = 周日；为空表示每天
+    #[serde(default)]
+    pub days: Vec<u8>,
+    pub start: TimeOfDay,
+    /// 早于 `start` 时跨过午夜；等于 `start` 表示全天
+    pub end: TimeOfDay,
+    /// 不填则该方向沿用基础速率；0 表示不限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_bytes_per_sec: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_bytes_per_sec: Option<u64>,
+}
+
+impl ScheduleRule {
+    fn on_day(&self, weekday: u8) -> bool {
+        self.days.is_empty() || self.days.contains(&weekday)
+    }
+
+    /// `weekday` 为 1..=7，`minute` 为当天第几分钟。
+    pub fn matches(&self, weekday: u8, minute: u16) -> bool {
+        let (start, end) = (self.start.minutes(), self.end.minutes());
+        if start < end {
+            return self.on_day(weekday) && (start..end).contains(&minute);
+        }
+        if start == end {
+            return self.on_day(weekday);
+        }
+        // 跨午夜：凌晨那段属于前一天的规则
+        let yesterday = if weekday == 1 { 7 } else { weekday - 1 };
+        (minute >= start && self.on_day(weekday)) || (minute < end && self.on_day(yesterday))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(day) = self.days.iter().find(|d| !(1..=7).contains(*d)) {
+            return Err(format!("Invalid weekday {}, expected 1-7", day));
+        }
+        Ok(())
+    }
+}
+
+pub fn validate_rules(rules: &[ScheduleRule]) -> Result<(), String> {
+    rules.iter().try_for_each(ScheduleRule::validate)
+}
+
+/// 当前生效的速率及其来源。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EffectiveRate {
+    /// 命中规则的下标，`None` 表示使用基础速率
+    pub rule: Option<usize>,
+    pub upload_bytes_per_sec: u64,
+    pub download_bytes_per_sec: u64,
+}
+
+struct Inner {
+    rules: Vec<ScheduleRule>,
+    base_upload: u64,
+    base_download: u64,
+}
+
+#[derive(Clone)]
+pub struct BandwidthSchedule {
+    inner: Arc<Mutex<Inner>>,
+    throttles: Throttles,
+}
+
+impl BandwidthSchedule {
+    /// 没有规则，基础速率为不限，需要时用 `set_base` 设置。
+    pub fn new(throttles: Throttles) -> Self {
+        let inner = Inner {
+            rules: Vec::new(),
+            base_upload: 0,
+            base_download: 0,
+        };
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+            throttles,
+        }
+    }
+
+    pub fn rules(&self) -> Vec<ScheduleRule> {
+        self.inner.lock().unwrap().rules.clone()
+    }
+
+    pub async fn set_rules(&self, rules: Vec<ScheduleRule>) -> Result<(), String> {
+        validate_rules(&rules)?;
+        self.inner.lock().unwrap().rules = rules;
+        self.apply().await;
+        Ok(())
+    }
+
+    /// 没有规则命中时使用的速率，`None` 表示不修改。
+    pub async fn set_base(&self, upload: Option<u64>, download: Option<u64>) {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(rate) = upload {
+                inner.base_upload = rate;
+            }
+            if let Some(rate) = download {
+                inner.base_download = rate;
+            }
+        }
+        self.apply().await;
+    }
+
+    /// `weekday` 为 1..=7（周一起），`minute` 为当天第几分钟。
+    pub fn effective_at(&self, weekday: u8, minute: u16) -> EffectiveRate {
+        let inner = self.inner.lock().unwrap();
+        let hit = inner
+            .rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| rule.matches(weekday, minute));
+        EffectiveRate {
+            rule: hit.map(|(i, _)| i),
+            upload_bytes_per_sec: hit
+                .and_then(|(_, r)| r.upload_bytes_per_sec)
+                .unwrap_or(inner.base_upload),
+            download_bytes_per_sec: hit
+                .and_then(|(_, r)| r.download_bytes_per_sec)
+                .unwrap_or(inner.base_download),
+        }
+    }
+
+    /// 按本地时间计算。
+    pub fn effective(&self) -> EffectiveRate {
+        let now = chrono::Local::now();
+        self.effective_at(
+            now.weekday().number_from_monday() as u8,
+            (now.hour() * 60 + now.minute()) as u16,
+        )
+    }
+
+    /// 把当前生效的速率写入全局限速器。
+    pub async fn apply(&self) -> EffectiveRate {
+        let effective = self.effective();
+        self.throttles
+            .upload
+            .global()
+            .set_rate(effective.upload_bytes_per_sec)
+            .await;
+        self.throttles
+            .download
+            .global()
+            .set_rate(effective.download_bytes_per_sec)
+            .await;
+        effective
+    }
+
+    /// 每到整分钟重新应用一次，随服务一起运行。
+    pub async fn run(self) {
+        loop {
+            self.apply().await;
+            let second = chrono::Local::now().second() as u64;
+            tokio::time::sleep(std::time::Duration::from_secs(60 - second.min(59))).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(days: &[u8], start: &str, end: &str, download: u64) -> ScheduleRule {
+        ScheduleRule {
+            days: days.to_vec(),
+            start: TimeOfDay::parse(start).unwrap(),
+            end: TimeOfDay::parse(end).unwrap(),
+            upload_bytes_per_sec: None,
+            download_bytes_per_sec: Some(download),
+        }
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let work = rule(&[1, 2, 3, 4, 5], "09:00", "18:00", 1);
+        assert!(work.matches(1, 9 * 60));
+        assert!(!work.matches(1, 18 * 60));
+        assert!(!work.matches(6, 10 * 60));
+
+        // 周五 22:00 到周六 06:00
+        let night = rule(&[5], "22:00", "06:00", 1);
+        assert!(night.matches(5, 23 * 60));
+        assert!(night.matches(6, 5 * 60));
+        assert!(!night.matches(5, 5 * 60));
+        assert!(!night.matches(6, 23 * 60));
+
+        assert!(rule(&[], "00:00", "00:00", 1).matches(7, 0));
+        assert!(TimeOfDay::parse("24:00").is_some());
+        assert!(TimeOfDay::parse("12:60").is_none());
+        assert!(rule(&[8], "00:00", "01:00", 1).validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_effective_rate_falls_back_to_base() {
+        let throttles = Throttles::unlimited();
+        let schedule = BandwidthSchedule::new(throttles.clone());
+        schedule.set_base(Some(100), Some(200)).await;
+        schedule
+            .set_rules(vec![
+                rule(&[1, 2, 3, 4, 5], "09:00", "18:00", 1000),
+                rule(&[], "00:00", "00:00", 5000),
+            ])
+            .await
+            .unwrap();
+
+        let effective = schedule.effective_at(2, 10 * 60);
+        assert_eq!(effective.rule, Some(0));
+        assert_eq!(effective.download_bytes_per_sec, 1000);
+        assert_eq!(effective.upload_bytes_per_sec, 100);
+        assert_eq!(schedule.effective_at(6, 10 * 60).rule, Some(1));
+
+        schedule.set_rules(Vec::new()).await.unwrap();
+        schedule.set_base(Some(300), None).await;
+        assert_eq!(
+            schedule.effective_at(2, 10 * 60),
+            EffectiveRate {
+                rule: None,
+                upload_bytes_per_sec: 300,
+                download_bytes_per_sec: 200,
+            }
+        );
+        assert_eq!(throttles.upload.global().get_rate().await, 300);
+    }
+}
@@ -0,0 +1,468 @@
+//! 可续传的分块上传会话。
+//!
+//! 每个会话在暂存目录下占用一个子目录：`meta.json` 记录目标位置和总大小，
+//! `data.part` 保存已收到的字节。已提交的偏移量就是 `data.part` 的长度，
+//! 因此服务重启或客户端休眠后都能从断点继续。
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use super::atomic::{self, ConflictPolicy, Outcome};
+use super::checksum::{self, Checksum};
+
+const META_FILE: &str = "meta.json";
+const DATA_FILE: &str = "data.part";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SessionMeta {
+    pub id: String,
+    /// 目标目录
+    pub path: String,
+    pub file_name: String,
+    pub total_size: u64,
+    pub created_at: u64,
+    /// 客户端声明的摘要，合并前校验
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<Checksum>,
+    /// 合并时目标文件已存在的处理方式
+    #[serde(default)]
+    pub on_conflict: ConflictPolicy,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionStatus {
+    #[serde(flatten)]
+    pub meta: SessionMeta,
+    pub offset: u64,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    NotFound,
+    /// 该会话正在被另一个请求写入
+    Busy,
+    /// 客户端给出的偏移量与已提交偏移量不一致
+    OffsetMismatch {
+        expected: u64,
+    },
+    /// 写入会超出声明的总大小
+    TooLarge,
+    /// 尚未收齐全部字节就请求合并
+    Incomplete {
+        offset: u64,
+    },
+    /// 收到的数据与声明的摘要不符，会话已被丢弃
+    ChecksumMismatch {
+        expected: Checksum,
+        actual: Checksum,
+    },
+    /// 目标文件已存在且冲突策略为 `Fail`
+    AlreadyExists,
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for SessionError {
+    fn from(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            SessionError::NotFound
+        } else {
+            SessionError::Io(e)
+        }
+    }
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::NotFound => write!(f, "Upload session not found"),
+            SessionError::Busy => write!(f, "Upload session is busy"),
+            SessionError::OffsetMismatch { expected } => {
+                write!(f, "Offset mismatch, committed offset is {}", expected)
+            }
+            SessionError::TooLarge => write!(f, "Chunk exceeds declared total size"),
+            SessionError::Incomplete { offset } => {
+                write!(f, "Upload incomplete, committed offset is {}", offset)
+            }
+            SessionError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch: expected {}, got {}",
+                expected, actual
+            ),
+            SessionError::AlreadyExists => write!(f, "Target file already exists"),
+            SessionError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct UploadSessions {
+    staging_dir: PathBuf,
+    active: Arc<Mutex<HashSet<String>>>,
+}
+
+/// 独占一个会话（写入、合并或放弃），释放时把会话标记为空闲。
+struct Claim {
+    id: String,
+    active: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        self.active.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// 会话写入期间持有，释放时把会话标记为空闲。
+pub struct SessionWriter {
+    _claim: Claim,
+    file: tokio::fs::File,
+    offset: u64,
+    total_size: u64,
+}
+
+impl UploadSessions {
+    pub fn new(staging_dir: PathBuf) -> Self {
+        Self {
+            staging_dir,
+            active: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// 默认暂存目录：`~/.transport/uploads/`
+    pub fn default_staging_dir() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".transport")
+            .join("uploads")
+    }
+
+    fn claim(&self, id: &str) -> Result<Claim, SessionError> {
+        if !self.active.lock().unwrap().insert(id.to_string()) {
+            return Err(SessionError::Busy);
+        }
+        Ok(Claim {
+            id: id.to_string(),
+            active: self.active.clone(),
+        })
+    }
+
+    fn session_dir(&self, id: &str) -> Result<PathBuf, SessionError> {
+        // 只接受 UUID，防止 id 中带路径分隔符逃出暂存目录
+        uuid::Uuid::parse_str(id).map_err(|_| SessionError::NotFound)?;
+        Ok(self.staging_dir.join(id))
+    }
+
+    pub async fn create(
+        &self,
+        path: String,
+        file_name: &str,
+        total_size: u64,
+        checksum: Option<Checksum>,
+        on_conflict: ConflictPolicy,
+    ) -> Result<SessionStatus, SessionError> {
+        let file_name = Path::new(file_name)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "unnamed".to_string());
+        // 能提前发现的冲突不必等到传完
+        check_conflict(&Path::new(&path).join(&file_name), on_conflict)?;
+
+        let meta = SessionMeta {
+            id: uuid::Uuid::new_v4().to_string(),
+            path,
+            file_name,
+            total_size,
+            created_at: chrono::Utc::now().timestamp() as u64,
+            checksum,
+            on_conflict,
+        };
+
+        let dir = self.session_dir(&meta.id)?;
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::File::create(dir.join(DATA_FILE)).await?;
+        let json = serde_json::to_vec_pretty(&meta).map_err(std::io::Error::other)?;
+        tokio::fs::write(dir.join(META_FILE), json).await?;
+
+        Ok(SessionStatus { meta, offset: 0 })
+    }
+
+    pub async fn status(&self, id: &str) -> Result<SessionStatus, SessionError> {
+        let dir = self.session_dir(id)?;
+        let json = tokio::fs::read(dir.join(META_FILE)).await?;
+        let meta: SessionMeta = serde_json::from_slice(&json)
+            .map_err(|e| SessionError::Io(std::io::Error::other(e)))?;
+        let offset = tokio::fs::metadata(dir.join(DATA_FILE)).await?.len();
+        Ok(SessionStatus { meta, offset })
+    }
+
+    /// 开始在 `offset` 处追加数据；`offset` 必须等于已提交偏移量。
+    pub async fn writer(&self, id: &str, offset: u64) -> Result<SessionWriter, SessionError> {
+        let dir = self.session_dir(id)?;
+        let claim = self.claim(id)?;
+
+        let status = self.status(id).await?;
+        if status.offset != offset {
+            return Err(SessionError::OffsetMismatch {
+                expected: status.offset,
+            });
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join(DATA_FILE))
+            .await?;
+        Ok(SessionWriter {
+            _claim: claim,
+            file,
+            offset,
+            total_size: status.meta.total_size,
+        })
+    }
+
+    /// 收齐后按冲突策略把数据移动到目标目录，返回最终路径和结果。
+    /// 合并期间独占会话，同时到达的写入或第二次合并得到 `Busy`。
+    pub async fn finalize(&self, id: &str) -> Result<(PathBuf, Outcome), SessionError> {
+        let dir = self.session_dir(id)?;
+        let _claim = self.claim(id)?;
+        let status = self.status(id).await?;
+        if status.offset != status.meta.total_size {
+            return Err(SessionError::Incomplete {
+                offset: status.offset,
+            });
+        }
+
+        let dest = Path::new(&status.meta.path).join(&status.meta.file_name);
+        let data = dir.join(DATA_FILE);
+
+        if let Some(expected) = status.meta.checksum {
+            let actual = checksum::hash_file(&data, &[expected.algorithm])
+                .await?
+                .remove(0);
+            if actual != expected {
+                tokio::fs::remove_dir_all(&dir).await?;
+                return Err(SessionError::ChecksumMismatch { expected, actual });
+            }
+        }
+        // 冲突时保留会话，客户端仍可放弃或稍后重试
+        check_conflict(&dest, status.meta.on_conflict)?;
+
+        let temp = atomic::temp_path(&dest);
+        if tokio::fs::rename(&data, &temp).await.is_err() {
+            // 暂存目录与目标可能不在同一个文件系统上，先复制到目标目录下的临时文件
+            if let Err(e) = tokio::fs::copy(&data, &temp).await {
+                let _ = tokio::fs::remove_file(&temp).await;
+                return Err(SessionError::Io(e));
+            }
+        }
+        let placed = atomic::persist(&temp, &dest, status.meta.on_conflict).await;
+        tokio::fs::remove_dir_all(&dir).await?;
+        placed.map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => SessionError::AlreadyExists,
+            _ => SessionError::Io(e),
+        })
+    }
+
+    pub async fn abort(&self, id: &str) -> Result<(), SessionError> {
+        let dir = self.session_dir(id)?;
+        let _claim = self.claim(id)?;
+        tokio::fs::remove_dir_all(dir).await?;
+        Ok(())
+    }
+
+    /// 删除超过 `max_age` 未更新的会话。
+    pub async fn prune(&self, max_age: Duration) {
+        let Ok(mut entries) = tokio::fs::read_dir(&self.staging_dir).await else {
+            return;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let stale = tokio::fs::metadata(entry.path().join(DATA_FILE))
+                .await
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.elapsed().ok())
+                .is_none_or(|age| age > max_age);
+            if stale {
+                let _ = tokio::fs::remove_dir_all(entry.path()).await;
+            }
+        }
+    }
+}
+
+impl SessionWriter {
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<(), SessionError> {
+        if self.offset + chunk.len() as u64 > self.total_size {
+            return Err(SessionError::TooLarge);
+        }
+        self.file.write_all(chunk).await?;
+        self.offset += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// 刷盘并返回新的已提交偏移量。
+    pub async fn commit(mut self) -> Result<u64, SessionError> {
+        self.file.flush().await?;
+        self.file.sync_data().await?;
+        Ok(self.offset)
+    }
+}
+
+fn check_conflict(dest: &Path, policy: ConflictPolicy) -> Result<(), SessionError> {
+    match atomic::precheck(dest, policy) {
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err(SessionError::AlreadyExists),
+        Err(e) => Err(SessionError::Io(e)),
+        Ok(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_resume_and_finalize() {
+        let staging = tempdir().unwrap();
+        let target = tempdir().unwrap();
+        let sessions = UploadSessions::new(staging.path().to_path_buf());
+
+        let created = sessions
+            .create(
+                target.path().to_string_lossy().to_string(),
+                "a.txt",
+                10,
+                None,
+                ConflictPolicy::Fail,
+            )
+            .await
+            .unwrap();
+        let id = created.meta.id;
+
+        let mut writer = sessions.writer(&id, 0).await.unwrap();
+        writer.write(b"hello").await.unwrap();
+        assert!(matches!(
+            sessions.writer(&id, 5).await,
+            Err(SessionError::Busy)
+        ));
+        assert_eq!(writer.commit().await.unwrap(), 5);
+
+        // 模拟断线重连：重新查询偏移量后继续
+        let resumed = UploadSessions::new(staging.path().to_path_buf());
+        assert_eq!(resumed.status(&id).await.unwrap().offset, 5);
+        assert!(matches!(
+            resumed.writer(&id, 0).await,
+            Err(SessionError::OffsetMismatch { expected: 5 })
+        ));
+        assert!(matches!(
+            resumed.finalize(&id).await,
+            Err(SessionError::Incomplete { offset: 5 })
+        ));
+
+        let mut writer = resumed.writer(&id, 5).await.unwrap();
+        assert!(matches!(
+            writer.write(b"world!").await,
+            Err(SessionError::TooLarge)
+        ));
+        writer.write(b"world").await.unwrap();
+        writer.commit().await.unwrap();
+
+        // 合并时会话不能同时被占用
+        let writer = resumed.writer(&id, 10).await.unwrap();
+        assert!(matches!(
+            resumed.finalize(&id).await,
+            Err(SessionError::Busy)
+        ));
+        drop(writer);
+
+        let (dest, outcome) = resumed.finalize(&id).await.unwrap();
+        assert_eq!(outcome, Outcome::Created);
+        assert_eq!(std::fs::read_to_string(dest).unwrap(), "helloworld");
+        assert!(matches!(
+            resumed.status(&id).await,
+            Err(SessionError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_non_uuid_id() {
+        let staging = tempdir().unwrap();
+        let sessions = UploadSessions::new(staging.path().to_path_buf());
+        assert!(matches!(
+            sessions.status("../../etc").await,
+            Err(SessionError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_finalize_rejects_checksum_mismatch() {
+        let staging = tempdir().unwrap();
+        let target = tempdir().unwrap();
+        let sessions = UploadSessions::new(staging.path().to_path_buf());
+        let mut expected = checksum::Hasher::new(checksum::Algorithm::Sha256);
+        expected.update(b"hello");
+        let expected = expected.finalize();
+
+        let created = sessions
+            .create(
+                target.path().to_string_lossy().to_string(),
+                "a.txt",
+                5,
+                Some(expected.clone()),
+                ConflictPolicy::default(),
+            )
+            .await
+            .unwrap();
+        let id = created.meta.id;
+        let mut writer = sessions.writer(&id, 0).await.unwrap();
+        writer.write(b"jello").await.unwrap();
+        writer.commit().await.unwrap();
+
+        match sessions.finalize(&id).await {
+            Err(SessionError::ChecksumMismatch { expected: e, .. }) => assert_eq!(e, expected),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        assert!(!target.path().join("a.txt").exists());
+        assert!(matches!(
+            sessions.status(&id).await,
+            Err(SessionError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_finalize_applies_conflict_policy() {
+        let staging = tempdir().unwrap();
+        let target = tempdir().unwrap();
+        std::fs::write(target.path().join("a.txt"), "old").unwrap();
+        let sessions = UploadSessions::new(staging.path().to_path_buf());
+        let dir = target.path().to_string_lossy().to_string();
+
+        assert!(matches!(
+            sessions
+                .create(dir.clone(), "a.txt", 3, None, ConflictPolicy::Fail)
+                .await,
+            Err(SessionError::AlreadyExists)
+        ));
+
+        let id = sessions
+            .create(dir, "a.txt", 3, None, ConflictPolicy::Rename)
+            .await
+            .unwrap()
+            .meta
+            .id;
+        let mut writer = sessions.writer(&id, 0).await.unwrap();
+        writer.write(b"new").await.unwrap();
+        writer.commit().await.unwrap();
+
+        let (dest, outcome) = sessions.finalize(&id).await.unwrap();
+        assert_eq!(outcome, Outcome::Renamed);
+        assert_eq!(dest, target.path().join("a (1).txt"));
+        assert_eq!(std::fs::read_to_string(dest).unwrap(), "new");
+        assert_eq!(
+            std::fs::read_to_string(target.path().join("a.txt")).unwrap(),
+            "old"
+        );
+    }
+}
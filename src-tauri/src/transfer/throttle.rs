@@ -1,66 +1,78 @@
+//! 令牌桶限速器（GCRA 形式）。
+//!
+//! 不存令牌数，只记一个"理论到达时间" `tat`：已放行的字节按速率折算成时间累加上去，
+//! 桶满即 `tat` 落后当前时间一秒。放行时用一次 CAS 推进 `tat`，需要等待的请求
+//! 直接睡到算好的截止时间，没有锁也没有轮询。
+
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use tokio::time::{sleep, Duration, Instant};
 
+/// 桶容量：一秒的额度。
+const BURST_NANOS: u64 = 1_000_000_000;
+
+struct State {
+    bytes_per_sec: AtomicU64,
+    /// `tat` 相对 `epoch` 的纳秒数再加上 `BURST_NANOS`，保证非负；0 表示创建时桶是满的
+    tat: AtomicU64,
+    epoch: Instant,
+}
+
+#[derive(Clone)]
 pub struct Throttle {
-    bytes_per_sec: Arc<Mutex<u64>>,
-    tokens: Arc<Mutex<f64>>,
-    last_refill: Arc<Mutex<Instant>>,
+    state: Arc<State>,
 }
 
 impl Throttle {
     /// Create a new throttle. 0 means unlimited.
     pub fn new(bytes_per_sec: u64) -> Self {
         Self {
-            bytes_per_sec: Arc::new(Mutex::new(bytes_per_sec)),
-            tokens: Arc::new(Mutex::new(bytes_per_sec as f64)),
-            last_refill: Arc::new(Mutex::new(Instant::now())),
+            state: Arc::new(State {
+                bytes_per_sec: AtomicU64::new(bytes_per_sec),
+                tat: AtomicU64::new(0),
+                epoch: Instant::now(),
+            }),
         }
     }
 
     pub async fn set_rate(&self, bytes_per_sec: u64) {
-        *self.bytes_per_sec.lock().await = bytes_per_sec;
+        self.state
+            .bytes_per_sec
+            .store(bytes_per_sec, Ordering::Relaxed);
     }
 
     pub async fn get_rate(&self) -> u64 {
-        *self.bytes_per_sec.lock().await
+        self.state.bytes_per_sec.load(Ordering::Relaxed)
     }
 
     pub async fn consume(&self, bytes: usize) {
-        let rate = *self.bytes_per_sec.lock().await;
-        if rate == 0 {
-            return; // unlimited
-        }
-
-        loop {
-            {
-                let mut tokens = self.tokens.lock().await;
-                let mut last = self.last_refill.lock().await;
-                let now = Instant::now();
-                let elapsed = now.duration_since(*last).as_secs_f64();
-                *tokens += elapsed * rate as f64;
-                if *tokens > rate as f64 {
-                    *tokens = rate as f64;
-                }
-                *last = now;
-
-                if *tokens >= bytes as f64 {
-                    *tokens -= bytes as f64;
-                    return;
-                }
-            }
-            sleep(Duration::from_millis(10)).await;
+        if let Some(wait) = self.reserve(bytes) {
+            sleep(wait).await;
         }
     }
-}
 
-impl Clone for Throttle {
-    fn clone(&self) -> Self {
-        Self {
-            bytes_per_sec: self.bytes_per_sec.clone(),
-            tokens: self.tokens.clone(),
-            last_refill: self.last_refill.clone(),
+    /// 预留 `bytes` 字节的额度，返回还要等多久才能发送；不限速或额度足够时为 `None`。
+    /// 预留只是一次 CAS，先预留的先轮到，调用方在任何锁之外等待即可。
+    pub fn reserve(&self, bytes: usize) -> Option<Duration> {
+        let rate = self.state.bytes_per_sec.load(Ordering::Relaxed);
+        if rate == 0 {
+            return None; // unlimited
         }
+
+        let elapsed = self.state.epoch.elapsed().as_nanos() as u64;
+        let cost = (bytes as u128 * BURST_NANOS as u128 / rate as u128) as u64;
+        // 桶里最多攒一秒的额度：tat 不早于 elapsed（即当前时间减一秒）
+        let previous = self
+            .state
+            .tat
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |tat| {
+                Some(tat.max(elapsed).saturating_add(cost))
+            })
+            .unwrap();
+        let deadline = previous.max(elapsed).saturating_add(cost);
+
+        let now = elapsed + BURST_NANOS;
+        (deadline > now).then(|| Duration::from_nanos(deadline - now))
     }
 }
 
@@ -87,6 +99,37 @@ mod tests {
         assert!(elapsed <= Duration::from_millis(700));
     }
 
+    #[tokio::test]
+    async fn test_throttle_splits_large_requests() {
+        let throttle = Throttle::new(1000);
+        let start = Instant::now();
+        // 超过桶容量：先用掉初始的 1000，剩下 500 等约 500ms
+        throttle.consume(1500).await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(400));
+        assert!(elapsed <= Duration::from_millis(700));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_consumers_share_rate() {
+        let throttle = Throttle::new(1000);
+        throttle.consume(1000).await;
+        let start = Instant::now();
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let throttle = throttle.clone();
+                tokio::spawn(async move { throttle.consume(250).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        // 4 × 250 字节合计一秒的额度
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(900));
+        assert!(elapsed <= Duration::from_millis(1200));
+    }
+
     #[tokio::test]
     async fn test_get_set_rate() {
         let throttle = Throttle::new(100);
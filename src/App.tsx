@@ -1,6 +1,7 @@
 import { BrowserRouter, Routes, Route, Navigate } from "react-router";
 import { isMobile } from "./lib/useIsMobile";
 import DebugConsole from "./components/DebugConsole";
+import PairingNotice from "./components/PairingNotice";
 import Layout from "./components/Layout";
 import MobileLayout from "./components/MobileLayout";
 import HomePage from "./pages/HomePage";
@@ -24,6 +25,7 @@ export default function App() {
           </Routes>
         </MobileLayout>
         <DebugConsole />
+        <PairingNotice />
       </BrowserRouter>
     );
   }
@@ -38,6 +40,7 @@ export default function App() {
         </Routes>
       </Layout>
       <DebugConsole />
+      <PairingNotice />
     </BrowserRouter>
   );
 }
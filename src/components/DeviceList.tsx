@@ -1,6 +1,8 @@
 import { useEffect } from "react";
 import { useDeviceStore } from "../stores/deviceStore";
 import { getDevices } from "../services/localApi";
+import { subscribeLocalEvents } from "../services/events";
+import { isTauri } from "../lib/env";
 import { Device } from "../types";
 
 const platformIcons: Record<string, string> = {
@@ -17,12 +19,20 @@ export default function DeviceList() {
 
   useEffect(() => {
     getDevices().then(setDevices);
-    const poll = setInterval(() => getDevices().then(setDevices), 3000);
-    return () => clearInterval(poll);
+    // 浏览器模式下列表只有当前主机，不需要订阅
+    if (!isTauri) return;
+    return subscribeLocalEvents(["peers-changed", "resync"], (event, payload) => {
+      if (event === "peers-changed") setDevices(payload as Device[]);
+      else getDevices().then(setDevices);
+    });
   }, [setDevices]);
 
   const renderDevice = (device: Device, isLocal: boolean) => {
-    const isSelected = selectedDevice?.ip === device.ip;
+    // 选中后 ip 可能被换成实际连通的地址
+    const isSelected =
+      !!selectedDevice &&
+      (selectedDevice.ip === device.ip ||
+        (device.addresses?.includes(selectedDevice.ip) ?? false));
     return (
       <button
         key={device.ip + (isLocal ? "-local" : "")}
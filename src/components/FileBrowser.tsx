@@ -3,6 +3,7 @@ import { useSearchParams } from "react-router";
 import { useDeviceStore } from "../stores/deviceStore";
 import { useTransferStore } from "../stores/transferStore";
 import { listFiles, downloadFile, uploadFile, deleteFile, createDirectory, renameFile } from "../services/remoteApi";
+import { subscribeDeviceEvents, FsChange } from "../services/events";
 import { isVR } from "../lib/useIsMobile";
 import { FileEntry } from "../types";
 import PathNav from "./PathNav";
@@ -60,6 +61,22 @@ export default function FileBrowser() {
     loadFiles();
   }, [loadFiles]);
 
+  // 其他客户端修改了当前目录时自动刷新
+  useEffect(() => {
+    if (!selectedDevice) return;
+    const parentOf = (p: string) => p.slice(0, Math.max(p.lastIndexOf("/"), p.lastIndexOf("\\"))) || "/";
+    return subscribeDeviceEvents(selectedDevice.ip, selectedDevice.port, (event, payload) => {
+      if (event === "resync") {
+        loadFiles();
+      } else if (event === "fs-changed") {
+        const change = payload as FsChange;
+        if ([change.path, change.from].some((p) => p && parentOf(p) === currentPath)) {
+          loadFiles();
+        }
+      }
+    });
+  }, [selectedDevice, currentPath, loadFiles]);
+
   if (!selectedDevice) {
     return (
       <div className="flex-1 flex items-center justify-center text-slate-500">
@@ -269,21 +286,22 @@ export default function FileBrowser() {
               label: "下载",
               onClick: () => {
                 const entry = contextMenu.entry;
-                if (entry.is_dir) return;
                 const filePath = currentPath === "/" ? `/${entry.name}` : `${currentPath}/${entry.name}`;
-                const downloadUrl = `/api/files/download?path=${encodeURIComponent(filePath)}`;
+                // 文件夹由服务端边打包边下载
+                const downloadUrl = entry.is_dir
+                  ? `/api/files/archive?path=${encodeURIComponent(filePath)}&format=zip`
+                  : `/api/files/download?path=${encodeURIComponent(filePath)}`;
                 if (isVR) {
                   window.open(downloadUrl, "_blank");
                 } else {
                   const a = document.createElement("a");
                   a.href = downloadUrl;
-                  a.download = entry.name;
+                  a.download = entry.is_dir ? `${entry.name}.zip` : entry.name;
                   document.body.appendChild(a);
                   a.click();
                   a.remove();
                 }
               },
-              disabled: contextMenu.entry.is_dir,
             },
             {
               label: "重命名",
@@ -0,0 +1,54 @@
+import { useEffect, useState } from "react";
+import { isTauri } from "../lib/env";
+
+interface PairingPrompt {
+  request_id: string;
+  device_name: string;
+  pin: string;
+}
+
+/** 原生应用中显示其他设备的配对请求 PIN（浏览器模式下由主机控制台显示） */
+export default function PairingNotice() {
+  const [prompts, setPrompts] = useState<PairingPrompt[]>([]);
+
+  useEffect(() => {
+    if (!isTauri) return;
+    let unlisten: (() => void) | undefined;
+    import("@tauri-apps/api/event").then(({ listen }) =>
+      listen<PairingPrompt>("pairing-request", (event) => {
+        const prompt = event.payload;
+        setPrompts((list) => [...list, prompt]);
+        // PIN 两分钟后失效
+        setTimeout(
+          () => setPrompts((list) => list.filter((p) => p.request_id !== prompt.request_id)),
+          120_000
+        );
+      }).then((fn) => (unlisten = fn))
+    );
+    return () => unlisten?.();
+  }, []);
+
+  if (prompts.length === 0) return null;
+
+  return (
+    <div className="fixed top-4 right-4 z-50 flex flex-col gap-2">
+      {prompts.map((p) => (
+        <div
+          key={p.request_id}
+          className="bg-slate-700 border border-slate-600 rounded-lg shadow-xl px-4 py-3 min-w-[220px]"
+        >
+          <div className="text-sm text-slate-300">
+            「{p.device_name}」请求配对
+          </div>
+          <div className="text-2xl font-mono tracking-widest text-white my-1">{p.pin}</div>
+          <button
+            onClick={() => setPrompts((list) => list.filter((x) => x.request_id !== p.request_id))}
+            className="text-xs text-slate-400 hover:text-slate-200"
+          >
+            关闭
+          </button>
+        </div>
+      ))}
+    </div>
+  );
+}
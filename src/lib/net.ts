@@ -0,0 +1,36 @@
+import { peerFetch } from "../services/peer";
+
+/** `host:port`，IPv6 地址加方括号 */
+export function hostPort(ip: string, port: number): string {
+  return ip.includes(":") ? `[${ip}]:${port}` : `${ip}:${port}`;
+}
+
+/**
+ * 同时探测设备的各个地址，返回最先响应的一个；任何 HTTP 响应（包括 401）都算连通。
+ * 设备启用了 HTTPS 时传入指纹，经由后端探测（见 peerFetch）。
+ * 都连不上时返回首选地址，让后续请求照常报错。
+ */
+export function pickAddress(
+  addresses: string[],
+  port: number,
+  fingerprint?: string,
+  timeoutMs = 2000,
+): Promise<string> {
+  if (addresses.length <= 1) return Promise.resolve(addresses[0]);
+  return new Promise((resolve) => {
+    let failed = 0;
+    for (const ip of addresses) {
+      const init = { signal: AbortSignal.timeout(timeoutMs) };
+      const probe = fingerprint
+        ? peerFetch(`https://${hostPort(ip, port)}/api/device/info`, fingerprint, init)
+        : fetch(`http://${hostPort(ip, port)}/api/device/info`, init);
+      probe.then(
+        () => resolve(ip),
+        () => {
+          failed += 1;
+          if (failed === addresses.length) resolve(addresses[0]);
+        },
+      );
+    }
+  });
+}
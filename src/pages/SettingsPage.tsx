@@ -42,6 +42,18 @@ export default function SettingsPage() {
             <span className="text-slate-400">服务端口</span>
             <span>{localDevice?.port || "-"}</span>
           </div>
+          <div className="flex justify-between">
+            <span className="text-slate-400">HTTPS</span>
+            <span>{localDevice?.https ? "已启用" : "未启用"}</span>
+          </div>
+          {localDevice?.fingerprint && (
+            <div className="flex justify-between gap-4">
+              <span className="text-slate-400 shrink-0">设备指纹</span>
+              <span className="font-mono text-xs break-all text-right">
+                {localDevice.fingerprint}
+              </span>
+            </div>
+          )}
         </div>
       </section>
 
@@ -81,7 +93,8 @@ export default function SettingsPage() {
             其他设备可以通过浏览器访问以下地址下载本应用：
           </p>
           <code className="text-blue-400">
-            http://{localDevice?.ip || "..."}:{localDevice?.port || "8090"}
+            {localDevice?.https ? "https" : "http"}://{localDevice?.ip || "..."}:
+            {localDevice?.port || "8090"}
           </code>
         </div>
       </section>
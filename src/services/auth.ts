@@ -0,0 +1,215 @@
+import { isTauri } from "../lib/env";
+import logger from "../lib/logger";
+import { hostPort } from "../lib/net";
+import { Device } from "../types";
+import { peerFetch } from "./peer";
+
+const log = logger.child({ module: "auth" });
+
+const TOKEN_PREFIX = "transport.token.";
+
+/** 服务端的错误响应，正文为 `{"code": "not_found", "message": "..."}` */
+export class ApiError extends Error {
+  status: number;
+  /** 机器可读的错误码，旧版本服务端返回纯文本时为空 */
+  code?: string;
+
+  constructor(status: number, message: string, code?: string) {
+    super(message);
+    this.name = "ApiError";
+    this.status = status;
+    this.code = code;
+  }
+}
+
+/** 读取非 2xx 响应的正文 */
+export async function apiError(res: Response): Promise<ApiError> {
+  const text = await res.text();
+  try {
+    const body = JSON.parse(text);
+    if (typeof body?.code === "string") {
+      return new ApiError(res.status, body.message ?? text, body.code);
+    }
+  } catch {
+    // 不是 JSON，按纯文本处理
+  }
+  return new ApiError(res.status, text || `HTTP ${res.status}`);
+}
+
+/** 同一设备并发请求遇到 401 时只弹一次配对框 */
+const pairing = new Map<string, Promise<void>>();
+
+/** `ip:port` → 设备 id；知道 id 时令牌按 id 保存，地址变了不用重新配对 */
+const deviceIds = new Map<string, string>();
+
+/** `ip:port` → 发现设备时得到的指纹，配对前用来核对主机身份，HTTPS 设备也用它固定证书 */
+const deviceFingerprints = new Map<string, string>();
+
+/** 启用了 HTTPS 的设备（`ip:port`） */
+const httpsDevices = new Set<string>();
+
+/** 记下设备各个地址对应的 id、指纹和是否启用 HTTPS，拿到设备信息时调用 */
+export function rememberDevice(device: Device): void {
+  for (const ip of [device.ip, ...(device.addresses ?? [])]) {
+    const key = hostPort(ip, device.port);
+    if (device.id) deviceIds.set(key, device.id);
+    if (device.fingerprint) deviceFingerprints.set(key, device.fingerprint);
+    if (device.https) httpsDevices.add(key);
+    else httpsDevices.delete(key);
+  }
+}
+
+/** 原生应用访问设备用的 `http(s)://host:port` */
+export function deviceOrigin(ip: string, port: number): string {
+  const key = hostPort(ip, port);
+  return `${httpsDevices.has(key) ? "https" : "http"}://${key}`;
+}
+
+/**
+ * 访问设备的 fetch：原生应用访问启用了 HTTPS 的设备时经由后端固定证书（见 peer.ts），
+ * 其他情况直接 fetch。
+ */
+export function deviceFetch(
+  ip: string,
+  port: number,
+  url: string,
+  init: RequestInit = {}
+): Promise<Response> {
+  const key = hostPort(ip, port);
+  const fingerprint = deviceFingerprints.get(key);
+  if (isTauri && httpsDevices.has(key) && fingerprint) {
+    return peerFetch(url, fingerprint, init);
+  }
+  return fetch(url, init);
+}
+
+function baseUrl(ip: string, port: number): string {
+  // 浏览器模式始终同源访问当前主机（见 remoteApi.deviceUrl）
+  return isTauri ? deviceOrigin(ip, port) : "";
+}
+
+function tokenKey(ip: string, port: number): string {
+  if (!isTauri) return `${TOKEN_PREFIX}self`;
+  const id = deviceIds.get(hostPort(ip, port));
+  return id ? `${TOKEN_PREFIX}${id}` : `${TOKEN_PREFIX}${ip}:${port}`;
+}
+
+interface PairingIdentity {
+  device_id: string;
+  public_key: string;
+}
+
+/**
+ * 原生应用配对时附上本机身份，签名由后端完成，私钥不进入前端。
+ * 不知道主机指纹时无法核对签名对象，按无身份配对。
+ */
+async function pairingIdentity(expectedFingerprint?: string): Promise<PairingIdentity | null> {
+  if (!isTauri || !expectedFingerprint) return null;
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<PairingIdentity>("pairing_identity");
+}
+
+/** `/api/pair/request` 的响应；`device_id`、`fingerprint` 是被配对主机的身份 */
+interface PairRequested {
+  request_id: string;
+  device_id?: string;
+  fingerprint?: string;
+}
+
+/**
+ * 签名绑定被配对主机的 id 和指纹，后端不会替前端签任意内容。
+ * 响应未经认证，后端核对其中的指纹与发现设备时得到的一致，不一致时拒绝签名。
+ */
+async function signPairing(
+  requested: PairRequested,
+  expectedFingerprint: string,
+): Promise<string> {
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<string>("sign_pairing", {
+    hostId: requested.device_id ?? "",
+    hostFingerprint: requested.fingerprint ?? "",
+    expectedFingerprint,
+    requestId: requested.request_id,
+  });
+}
+
+function clientName(): string {
+  const ua = navigator.userAgent;
+  if (/Android/i.test(ua)) return "Android";
+  if (/iPhone|iPad/i.test(ua)) return "iOS";
+  if (/Mac/i.test(ua)) return "Mac";
+  if (/Windows/i.test(ua)) return "Windows";
+  return "Browser";
+}
+
+async function pair(ip: string, port: number): Promise<void> {
+  const base = baseUrl(ip, port);
+  const expectedFingerprint = deviceFingerprints.get(hostPort(ip, port));
+  const identity = await pairingIdentity(expectedFingerprint);
+  const res = await deviceFetch(ip, port, `${base}/api/pair/request`, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ device_name: clientName(), ...identity }),
+  });
+  if (!res.ok) throw await apiError(res);
+  const requested: PairRequested = await res.json();
+  const { request_id } = requested;
+  const signature =
+    identity && expectedFingerprint ? await signPairing(requested, expectedFingerprint) : undefined;
+  log.info({ ip, port }, "pairing requested");
+
+  let message = "请输入对方设备上显示的 6 位配对码";
+  for (;;) {
+    const pin = window.prompt(message);
+    if (pin === null) throw new Error("Pairing cancelled");
+
+    const confirm = await deviceFetch(ip, port, `${base}/api/pair/confirm`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({ request_id, pin, signature }),
+    });
+    if (confirm.ok) {
+      // 浏览器模式下服务端同时下发了 HttpOnly cookie，这里的令牌给 Tauri 使用
+      const { token } = await confirm.json();
+      localStorage.setItem(tokenKey(ip, port), token);
+      log.info({ ip, port }, "pairing complete");
+      return;
+    }
+    // 403 = PIN 错误，还有重试次数；其它状态（过期、次数用尽）直接失败
+    const err = await apiError(confirm);
+    if (confirm.status !== 403) throw err;
+    message = `${err.message}\n请重新输入配对码`;
+  }
+}
+
+function ensurePaired(ip: string, port: number): Promise<void> {
+  const key = tokenKey(ip, port);
+  let pending = pairing.get(key);
+  if (!pending) {
+    pending = pair(ip, port).finally(() => pairing.delete(key));
+    pairing.set(key, pending);
+  }
+  return pending;
+}
+
+/** 带设备令牌的 fetch；未配对（401）时先走配对流程再重试一次 */
+export async function authFetch(
+  ip: string,
+  port: number,
+  url: string,
+  init: RequestInit = {}
+): Promise<Response> {
+  const send = () => {
+    const headers = new Headers(init.headers);
+    const token = localStorage.getItem(tokenKey(ip, port));
+    if (token) headers.set("Authorization", `Bearer ${token}`);
+    return deviceFetch(ip, port, url, { ...init, headers });
+  };
+
+  const res = await send();
+  if (res.status !== 401) return res;
+
+  localStorage.removeItem(tokenKey(ip, port));
+  await ensurePaired(ip, port);
+  return send();
+}
@@ -0,0 +1,106 @@
+import { isTauri } from "../lib/env";
+import logger from "../lib/logger";
+import { authFetch, deviceOrigin } from "./auth";
+
+const log = logger.child({ module: "events" });
+
+/** 服务端事件名，见 src-tauri/src/events.rs；resync 表示可能漏了事件，需要重新拉取 */
+export type ServerEvent =
+  | "transfer-started"
+  | "transfer-progress"
+  | "transfer-updated"
+  | "transfer-completed"
+  | "transfer-failed"
+  | "fs-changed"
+  | "peers-changed"
+  | "resync";
+
+export interface FsChange {
+  kind: "created" | "removed" | "renamed";
+  path: string;
+  from?: string;
+}
+
+type Handler = (event: ServerEvent, payload: unknown) => void;
+
+const RECONNECT_DELAY = 3000;
+
+/**
+ * 订阅某台设备的事件流（/api/events，SSE）。
+ * 用 fetch 而不是 EventSource，这样可以带上配对令牌；断线后自动重连。
+ */
+export function subscribeDeviceEvents(ip: string, port: number, handler: Handler): () => void {
+  const controller = new AbortController();
+  const url = isTauri ? `${deviceOrigin(ip, port)}/api/events` : "/api/events";
+
+  (async () => {
+    let reconnecting = false;
+    while (!controller.signal.aborted) {
+      try {
+        const res = await authFetch(ip, port, url, { signal: controller.signal });
+        if (!res.ok || !res.body) throw new Error(`HTTP ${res.status}`);
+        // 断线期间的事件已经丢失
+        if (reconnecting) handler("resync", {});
+        await readStream(res.body, handler);
+      } catch (e) {
+        if (controller.signal.aborted) return;
+        log.warn({ ip, port, error: String(e) }, "event stream disconnected");
+      }
+      reconnecting = true;
+      await new Promise((r) => setTimeout(r, RECONNECT_DELAY));
+    }
+  })();
+
+  return () => controller.abort();
+}
+
+async function readStream(body: ReadableStream<Uint8Array>, handler: Handler) {
+  const reader = body.pipeThrough(new TextDecoderStream()).getReader();
+  let buffer = "";
+  for (;;) {
+    const { done, value } = await reader.read();
+    if (done) return;
+    buffer += value;
+
+    let end;
+    while ((end = buffer.indexOf("\n\n")) >= 0) {
+      const block = buffer.slice(0, end);
+      buffer = buffer.slice(end + 2);
+
+      let name = "message";
+      const data: string[] = [];
+      for (const line of block.split("\n")) {
+        if (line.startsWith("event:")) name = line.slice(6).trim();
+        else if (line.startsWith("data:")) data.push(line.slice(5).trimStart());
+      }
+      // 没有 data 的是保活注释
+      if (data.length === 0) continue;
+      handler(name as ServerEvent, JSON.parse(data.join("\n")));
+    }
+  }
+}
+
+/** 订阅本机事件：原生应用走 Tauri 事件，浏览器模式走当前主机的 SSE */
+export function subscribeLocalEvents(names: ServerEvent[], handler: Handler): () => void {
+  if (!isTauri) {
+    return subscribeDeviceEvents(location.hostname, Number(location.port), (event, payload) => {
+      if (names.includes(event)) handler(event, payload);
+    });
+  }
+
+  let cancelled = false;
+  let unlisteners: (() => void)[] = [];
+  import("@tauri-apps/api/event")
+    .then(({ listen }) =>
+      Promise.all(names.map((name) => listen(name, (e) => handler(name, e.payload))))
+    )
+    .then((fns) => {
+      if (cancelled) fns.forEach((fn) => fn());
+      else unlisteners = fns;
+    });
+
+  return () => {
+    cancelled = true;
+    unlisteners.forEach((fn) => fn());
+  };
+}
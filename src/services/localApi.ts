@@ -1,6 +1,7 @@
 import { isTauri, currentDeviceOrigin } from "../lib/env";
 import { Device } from "../types";
 import logger from "../lib/logger";
+import { authFetch } from "./auth";
 
 const log = logger.child({ module: "localApi" });
 
@@ -21,7 +22,7 @@ export async function getLocalDeviceInfo(): Promise<Device> {
   }
   const url = `${currentDeviceOrigin()}/api/device/info`;
   log.debug({ url }, "getLocalDeviceInfo request");
-  const res = await fetch(url);
+  const res = await authFetch(location.hostname, Number(location.port), url);
   const data = await res.json();
   log.debug({ data }, "getLocalDeviceInfo response");
   return data;
@@ -33,7 +34,8 @@ export async function setThrottleRate(bytesPerSec: number): Promise<void> {
     const { invoke } = await import("@tauri-apps/api/core");
     return invoke("set_throttle_rate", { bytesPerSec });
   }
-  await fetch(`${currentDeviceOrigin()}/api/settings/throttle`, {
+  const url = `${currentDeviceOrigin()}/api/settings/throttle`;
+  await authFetch(location.hostname, Number(location.port), url, {
     method: "PUT",
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({ bytes_per_sec: bytesPerSec }),
@@ -0,0 +1,114 @@
+import logger from "../lib/logger";
+
+const log = logger.child({ module: "peer" });
+
+/** 见 src-tauri/src/peer.rs */
+interface PeerResponse {
+  id: number;
+  status: number;
+  headers: [string, string][];
+}
+
+type PeerBody =
+  | { kind: "chunk"; data: number[] }
+  | { kind: "end" }
+  | { kind: "error"; message: string };
+
+/** 这些状态码的响应不能带正文，`new Response` 会报错 */
+const NULL_BODY_STATUS = [101, 204, 205, 304];
+
+/**
+ * 原生应用访问启用了 HTTPS 的设备：WebView 无法固定自签名证书，请求交给后端发出，
+ * 证书固定为设备指纹 `fingerprint`。用法和返回值与 `fetch` 相同，只能访问 `/api`。
+ */
+export async function peerFetch(
+  url: string,
+  fingerprint: string,
+  init: RequestInit = {},
+): Promise<Response> {
+  const { Channel, invoke } = await import("@tauri-apps/api/core");
+  const target = new URL(url);
+  // 借 Request 序列化正文（FormData 等），同时得到对应的 Content-Type
+  const request = new Request(url, init);
+  const body =
+    init.body == null ? undefined : Array.from(new Uint8Array(await request.arrayBuffer()));
+
+  let id: number | undefined;
+  let finished = false;
+  const abort = () => {
+    if (finished || id === undefined) return;
+    finished = true;
+    invoke("peer_abort", { id }).catch(() => {});
+  };
+
+  let controller!: ReadableStreamDefaultController<Uint8Array>;
+  const stream = new ReadableStream<Uint8Array>({
+    start: (c) => {
+      controller = c;
+    },
+    cancel: abort,
+  });
+  const onBody = new Channel<PeerBody>();
+  onBody.onmessage = (message) => {
+    if (finished) return;
+    if (message.kind === "chunk") {
+      controller.enqueue(new Uint8Array(message.data));
+      return;
+    }
+    finished = true;
+    if (message.kind === "end") controller.close();
+    else controller.error(new TypeError(message.message));
+  };
+
+  const sent = invoke<PeerResponse>("peer_fetch", {
+    request: {
+      address: target.host,
+      fingerprint,
+      method: request.method,
+      path: target.pathname + target.search,
+      headers: [...request.headers],
+      body,
+    },
+    onBody,
+  }).catch((e) => {
+    // 与 fetch 一致：连不上、证书不符都是 TypeError
+    log.warn({ url, error: e?.message ?? String(e) }, "peer request failed");
+    throw new TypeError(e?.message ?? String(e));
+  });
+
+  // 收到响应头之前被中止（如探测超时）时立即返回，响应到达后再断开
+  const signal = init.signal;
+  const aborted = new Promise<never>((_, reject) => {
+    if (!signal) return;
+    const onAbort = () => reject(signal.reason);
+    if (signal.aborted) onAbort();
+    else signal.addEventListener("abort", onAbort, { once: true });
+  });
+  const res = await Promise.race([sent, aborted]).catch((e) => {
+    sent.then(
+      (late) => {
+        id = late.id;
+        abort();
+      },
+      () => {},
+    );
+    throw e;
+  });
+
+  id = res.id;
+  signal?.addEventListener(
+    "abort",
+    () => {
+      if (finished) return;
+      abort();
+      controller.error(signal.reason);
+    },
+    { once: true },
+  );
+  const hasBody = !NULL_BODY_STATUS.includes(res.status);
+  if (!hasBody) abort();
+  return new Response(hasBody ? stream : null, {
+    status: res.status,
+    headers: res.headers,
+  });
+}
@@ -1,6 +1,7 @@
 import { isTauri } from "../lib/env";
-import { FileEntry } from "../types";
+import { FileEntry, RemoteTransfer } from "../types";
 import logger from "../lib/logger";
+import { apiError, authFetch, deviceOrigin } from "./auth";
 
 const log = logger.child({ module: "remoteApi" });
 
@@ -11,7 +12,7 @@ function deviceUrl(ip: string, port: number, path: string): string {
   if (!isTauri) {
     return path;
   }
-  return `http://${ip}:${port}${path}`;
+  return `${deviceOrigin(ip, port)}${path}`;
 }
 
 export async function listFiles(
@@ -21,11 +22,11 @@ export async function listFiles(
 ): Promise<FileEntry[]> {
   const url = deviceUrl(ip, port, `/api/files?path=${encodeURIComponent(dirPath)}`);
   log.debug({ url, dirPath }, "listFiles request");
-  const res = await fetch(url);
+  const res = await authFetch(ip, port, url);
   if (!res.ok) {
-    const text = await res.text();
-    log.error({ status: res.status, text, url }, "listFiles failed");
-    throw new Error(text);
+    const err = await apiError(res);
+    log.error({ status: res.status, code: err.code, text: err.message, url }, "listFiles failed");
+    throw err;
   }
   const data = await res.json();
   log.debug({ count: data.length, dirPath }, "listFiles response");
@@ -40,11 +41,11 @@ export async function downloadFile(
 ): Promise<Blob> {
   const url = deviceUrl(ip, port, `/api/files/download?path=${encodeURIComponent(filePath)}`);
   log.debug({ url, filePath }, "downloadFile request");
-  const res = await fetch(url);
+  const res = await authFetch(ip, port, url);
   if (!res.ok) {
-    const text = await res.text();
-    log.error({ status: res.status, text }, "downloadFile failed");
-    throw new Error(text);
+    const err = await apiError(res);
+    log.error({ status: res.status, code: err.code, text: err.message }, "downloadFile failed");
+    throw err;
   }
 
   const total = Number(res.headers.get("content-length") || 0);
@@ -64,26 +65,31 @@ export async function downloadFile(
   return new Blob(chunks);
 }
 
+/** 目标已存在时：覆盖（默认）、另存为 "name (1).ext"、跳过、报错 */
+export type ConflictPolicy = "overwrite" | "rename" | "skip" | "fail";
+
 export async function uploadFile(
   ip: string,
   port: number,
   targetDir: string,
-  file: File
+  file: File,
+  onConflict?: ConflictPolicy
 ): Promise<void> {
   const url = deviceUrl(ip, port, "/api/files/upload");
   log.debug({ url, targetDir, fileName: file.name, size: file.size }, "uploadFile request");
   const form = new FormData();
   form.append("path", targetDir);
+  if (onConflict) form.append("on_conflict", onConflict);
   form.append("file", file);
 
-  const res = await fetch(url, {
+  const res = await authFetch(ip, port, url, {
     method: "POST",
     body: form,
   });
   if (!res.ok) {
-    const text = await res.text();
-    log.error({ status: res.status, text }, "uploadFile failed");
-    throw new Error(text);
+    const err = await apiError(res);
+    log.error({ status: res.status, code: err.code, text: err.message }, "uploadFile failed");
+    throw err;
   }
   log.info({ targetDir, fileName: file.name }, "uploadFile complete");
 }
@@ -94,14 +100,16 @@ export async function deleteFile(
   filePath: string
 ): Promise<void> {
   log.debug({ filePath }, "deleteFile request");
-  const res = await fetch(
+  const res = await authFetch(
+    ip,
+    port,
     deviceUrl(ip, port, `/api/files?path=${encodeURIComponent(filePath)}`),
     { method: "DELETE" }
   );
   if (!res.ok) {
-    const text = await res.text();
-    log.error({ status: res.status, text }, "deleteFile failed");
-    throw new Error(text);
+    const err = await apiError(res);
+    log.error({ status: res.status, code: err.code, text: err.message }, "deleteFile failed");
+    throw err;
   }
   log.info({ filePath }, "deleteFile complete");
 }
@@ -113,15 +121,15 @@ export async function renameFile(
   newPath: string
 ): Promise<void> {
   log.debug({ oldPath, newPath }, "renameFile request");
-  const res = await fetch(deviceUrl(ip, port, "/api/files/rename"), {
+  const res = await authFetch(ip, port, deviceUrl(ip, port, "/api/files/rename"), {
     method: "PUT",
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({ old_path: oldPath, new_path: newPath }),
   });
   if (!res.ok) {
-    const text = await res.text();
-    log.error({ status: res.status, text }, "renameFile failed");
-    throw new Error(text);
+    const err = await apiError(res);
+    log.error({ status: res.status, code: err.code, text: err.message }, "renameFile failed");
+    throw err;
   }
   log.info({ oldPath, newPath }, "renameFile complete");
 }
@@ -132,15 +140,70 @@ export async function createDirectory(
   dirPath: string
 ): Promise<void> {
   log.debug({ dirPath }, "createDirectory request");
-  const res = await fetch(deviceUrl(ip, port, "/api/files/mkdir"), {
+  const res = await authFetch(ip, port, deviceUrl(ip, port, "/api/files/mkdir"), {
     method: "POST",
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({ path: dirPath }),
   });
   if (!res.ok) {
-    const text = await res.text();
-    log.error({ status: res.status, text }, "createDirectory failed");
-    throw new Error(text);
+    const err = await apiError(res);
+    log.error({ status: res.status, code: err.code, text: err.message }, "createDirectory failed");
+    throw err;
   }
   log.info({ dirPath }, "createDirectory complete");
 }
+
+export interface FileHash {
+  path: string;
+  size: number;
+  blake3?: string;
+  sha256?: string;
+}
+
+export async function hashFile(
+  ip: string,
+  port: number,
+  filePath: string,
+  algorithm?: "blake3" | "sha256"
+): Promise<FileHash> {
+  const params = new URLSearchParams({ path: filePath });
+  if (algorithm) params.set("algorithm", algorithm);
+  const res = await authFetch(ip, port, deviceUrl(ip, port, `/api/files/hash?${params}`));
+  if (!res.ok) {
+    const err = await apiError(res);
+    log.error({ status: res.status, code: err.code, text: err.message }, "hashFile failed");
+    throw err;
+  }
+  return res.json();
+}
+
+export async function listTransfers(ip: string, port: number): Promise<RemoteTransfer[]> {
+  const res = await authFetch(ip, port, deviceUrl(ip, port, "/api/transfers"));
+  if (!res.ok) {
+    const err = await apiError(res);
+    log.error({ status: res.status, code: err.code, text: err.message }, "listTransfers failed");
+    throw err;
+  }
+  return res.json();
+}
+
+export async function controlTransfer(
+  ip: string,
+  port: number,
+  id: string,
+  action: "pause" | "resume" | "cancel"
+): Promise<RemoteTransfer> {
+  log.debug({ id, action }, "controlTransfer request");
+  const res = await authFetch(
+    ip,
+    port,
+    deviceUrl(ip, port, `/api/transfers/${encodeURIComponent(id)}/${action}`),
+    { method: "POST" }
+  );
+  if (!res.ok) {
+    const err = await apiError(res);
+    log.error({ status: res.status, code: err.code, text: err.message }, "controlTransfer failed");
+    throw err;
+  }
+  return res.json();
+}
@@ -1,4 +1,7 @@
 import { create } from "zustand";
+import { isTauri } from "../lib/env";
+import { pickAddress } from "../lib/net";
+import { rememberDevice } from "../services/auth";
 import { Device } from "../types";
 
 interface DeviceStore {
@@ -10,15 +13,31 @@ interface DeviceStore {
   selectDevice: (device: Device | null) => void;
 }
 
-export const useDeviceStore = create<DeviceStore>((set) => ({
+export const useDeviceStore = create<DeviceStore>((set, get) => ({
   devices: [],
   localDevice: null,
   selectedDevice: null,
-  setDevices: (devices) => set({ devices }),
-  setLocalDevice: (device) =>
+  setDevices: (devices) => {
+    devices.forEach(rememberDevice);
+    set({ devices });
+  },
+  setLocalDevice: (device) => {
+    rememberDevice(device);
     set((state) => ({
       localDevice: device,
       selectedDevice: state.selectedDevice ?? device,
-    })),
-  selectDevice: (device) => set({ selectedDevice: device }),
+    }));
+  },
+  selectDevice: (device) => {
+    set({ selectedDevice: device });
+    if (device) rememberDevice(device);
+    // 多网卡的设备首选地址不一定连得上（VPN、另一个网段），换成最先响应的地址
+    if (!isTauri || !device?.addresses || device.addresses.length <= 1) return;
+    const fingerprint = device.https ? device.fingerprint : undefined;
+    pickAddress(device.addresses, device.port, fingerprint).then((ip) => {
+      if (ip !== device.ip && get().selectedDevice === device) {
+        set({ selectedDevice: { ...device, ip } });
+      }
+    });
+  },
 }));
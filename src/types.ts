@@ -1,9 +1,26 @@
+export interface InterfaceAddress {
+  interface: string;
+  ip: string;
+  kind: "ethernet" | "wifi" | "other" | "vpn" | "virtual";
+}
+
 export interface Device {
   name: string;
+  /** 首选地址；选中设备后会换成实际能连通的那个 */
   ip: string;
   port: number;
   platform: string;
   home_dir: string;
+  /** mDNS 解析到的全部地址，按优先级排序 */
+  addresses?: string[];
+  /** 本机各网卡的地址（仅本机信息） */
+  interfaces?: InterfaceAddress[];
+  /** 设备 id，地址和名字变了也不变；旧版本为空 */
+  id?: string;
+  /** 设备公钥指纹，用于人工核对；启用 HTTPS 时也是证书指纹 */
+  fingerprint?: string;
+  /** 只接受 HTTPS（自签名证书） */
+  https?: boolean;
 }
 
 export interface FileEntry {
@@ -27,3 +44,18 @@ export interface TransferTask {
   sourceDevice: string;
   targetDevice: string;
 }
+
+/** 服务端传输队列中的一项（/api/transfers） */
+export interface RemoteTransfer {
+  id: string;
+  direction: TransferDirection;
+  peer: { addr: string; device_id: string | null };
+  path: string;
+  total: number | null;
+  transferred: number;
+  rate: number;
+  status: TransferStatus | "paused";
+  error: string | null;
+  started_at: number;
+  updated_at: number;
+}